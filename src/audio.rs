@@ -1,11 +1,17 @@
-use crate::program::Program;
+use crate::program::{EnvelopeShape, LiveProgram, Params, Program};
 use anyhow::Result;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::StreamConfig;
-use log::{error, info};
-use std::f64::consts::TAU;
-use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use std::sync::Arc;
+use log::{error, info, warn};
+use std::f64::consts::{FRAC_PI_4, TAU};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::simd::cmp::{SimdOrd, SimdPartialOrd};
+use std::simd::num::{SimdFloat, SimdUint};
+use std::simd::{Select, Simd, StdFloat};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::SystemTime;
 
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Sync State
@@ -29,6 +35,22 @@ pub struct SyncState {
 
     /// Audio sample rate in Hz.
     pub sample_rate: AtomicU32,
+
+    /// Set by the visual thread to pause; the engine outputs silence and holds time.
+    pub paused: AtomicBool,
+
+    /// Set to request a shutdown; the engine fades out, then sets `stopped`.
+    pub stopping: AtomicBool,
+
+    /// Set by the engine once the exit fade has finished.
+    pub stopped: AtomicBool,
+
+    /// Extra visual delay in milliseconds on top of the buffer latency
+    /// (negative shows the visuals earlier).
+    pub av_offset_ms: AtomicI32,
+
+    /// Master intensity [0, 1] scaling both the audio gain and the flash, as f64 bits.
+    pub intensity_bits: AtomicU64,
 }
 
 impl SyncState {
@@ -38,6 +60,11 @@ impl SyncState {
             phase_bits: AtomicU64::new(0),
             buffer_frames: AtomicU32::new(0),
             sample_rate: AtomicU32::new(0),
+            paused: AtomicBool::new(false),
+            stopping: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            av_offset_ms: AtomicI32::new(0),
+            intensity_bits: AtomicU64::new(1.0f64.to_bits()),
         }
     }
 
@@ -56,10 +83,41 @@ impl SyncState {
         played as f64 / f64::from(rate)
     }
 
+    /// Get the time of the next sample the engine will write, in seconds.
+    #[inline]
+    pub fn write_time(&self) -> f64 {
+        let written = self.frames_written.load(Ordering::Acquire);
+        let rate = self.sample_rate.load(Ordering::Acquire);
+
+        if rate == 0 {
+            return 0.0;
+        }
+
+        written as f64 / f64::from(rate)
+    }
+
+    /// Get the master intensity [0, 1].
+    #[inline]
+    pub fn intensity(&self) -> f64 {
+        f64::from_bits(self.intensity_bits.load(Ordering::Relaxed))
+    }
+
+    /// Set the master intensity, clamped to [0, 1].
+    pub fn set_intensity(&self, intensity: f64) {
+        self.intensity_bits
+            .store(intensity.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get the pulse phase at the end of the last buffer written, without compensation.
+    #[inline]
+    pub fn raw_phase(&self) -> f64 {
+        f64::from_bits(self.phase_bits.load(Ordering::Acquire))
+    }
+
     /// Get the current pulse phase, compensated for buffer latency.
     #[inline]
     pub fn visual_phase(&self, freq: f64) -> f64 {
-        let raw_phase = f64::from_bits(self.phase_bits.load(Ordering::Acquire));
+        let raw_phase = self.raw_phase();
         let buffer = self.buffer_frames.load(Ordering::Acquire);
         let rate = self.sample_rate.load(Ordering::Acquire);
 
@@ -67,8 +125,9 @@ impl SyncState {
             return 0.0;
         }
 
-        // Rewind phase by buffer latency
-        let latency_secs = f64::from(buffer) / f64::from(rate);
+        // Rewind phase by buffer latency plus the user's offset
+        let offset_secs = f64::from(self.av_offset_ms.load(Ordering::Relaxed)) / 1000.0;
+        let latency_secs = f64::from(buffer) / f64::from(rate) + offset_secs;
         let phase_offset = freq * latency_secs;
 
         (raw_phase - phase_offset).rem_euclid(1.0)
@@ -81,40 +140,518 @@ impl Default for SyncState {
     }
 }
 
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Envelope
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Isochronic pulse envelope: a trapezoid with smoothstep edges, or a
+/// raised cosine.
+///
+/// Shared by the audio amplitude and, in linked mode, the visual brightness.
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope {
+    duty: f64,
+    inv_ramp: f64,
+    shape: EnvelopeShape,
+}
+
+impl Envelope {
+    /// Create an envelope for a pulse at `freq` Hz.
+    ///
+    /// `smooth` is the edge length in seconds. Zero selects the default of 10%
+    /// of the period. Either way the edge is capped at half the duty cycle.
+    #[inline]
+    pub fn new(duty: f64, freq: f64, smooth: f64) -> Self {
+        let ramp = if smooth > 0.0 { smooth * freq } else { 0.1 };
+        let ramp = ramp.min(duty * 0.5);
+        let inv_ramp = if ramp > 1e-9 { 1.0 / ramp } else { 1e9 };
+        Self { duty, inv_ramp, shape: EnvelopeShape::Trapezoid }
+    }
+
+    /// Use `shape` instead of the trapezoid.
+    #[inline]
+    pub fn with_shape(self, shape: EnvelopeShape) -> Self {
+        Self { shape, ..self }
+    }
+
+    /// Create the envelope for a parameter set.
+    #[inline]
+    pub fn from_params(params: &Params) -> Self {
+        Self::new(f64::from(params.duty), params.freq, f64::from(params.smooth)).with_shape(params.env)
+    }
+
+    /// Length of each edge as a fraction of the pulse period.
+    #[inline]
+    pub fn ramp(&self) -> f64 {
+        1.0 / self.inv_ramp
+    }
+
+    /// Envelope value [0, 1] at the given pulse phase [0, 1).
+    ///
+    /// The release starts at `duty - ramp` and reaches zero exactly at `duty`,
+    /// so the pulse never cuts off mid-ramp. A raised cosine ignores the ramp
+    /// and rises and falls across the whole on part.
+    #[inline]
+    pub fn value(&self, phase: f64) -> f64 {
+        if phase >= self.duty {
+            return 0.0;
+        }
+        if self.shape == EnvelopeShape::RaisedCosine {
+            return 0.5 * (1.0 - (TAU * phase / self.duty).cos());
+        }
+
+        // Trapezoidal envelope with smooth edges
+        let attack = (phase * self.inv_ramp).min(1.0);
+        let release = ((self.duty - phase) * self.inv_ramp).min(1.0);
+        let linear = attack.min(release);
+        // Apply smoothstep for softer transitions
+        linear * linear * (3.0 - 2.0 * linear)
+    }
+}
+
+/// Left and right channel gains for a pan position [-1, 1].
+///
+/// Equal-power law: the summed power stays the same at every position, so
+/// centered is 3 dB down on each channel and a hard pan is full volume on one.
+#[inline]
+fn pan_gains(pan: f64) -> (f64, f64) {
+    let angle = (pan + 1.0) * FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+/// Pink noise from filtered white noise (Paul Kellet's economy filter).
+///
+/// Seeded the same way every time, so renders are reproducible.
+#[derive(Debug, Clone)]
+struct PinkNoise {
+    state: u64,
+    poles: [f64; 3],
+}
+
+impl PinkNoise {
+    /// Scale that keeps the filtered output, which peaks around 7, within [-1, 1].
+    const SCALE: f64 = 0.125;
+
+    fn new() -> Self {
+        Self { state: 0x9E37_79B9_7F4A_7C15, poles: [0.0; 3] }
+    }
+
+    /// White noise in [-1, 1] from an xorshift64* generator.
+    #[inline]
+    fn white(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let bits = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        bits as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    #[inline]
+    fn next(&mut self) -> f64 {
+        let white = self.white();
+        let [b0, b1, b2] = &mut self.poles;
+        *b0 = 0.99765 * *b0 + white * 0.099_046;
+        *b1 = 0.963 * *b1 + white * 0.296_516_4;
+        *b2 = 0.57 * *b2 + white * 1.052_691_3;
+        (*b0 + *b1 + *b2 + white * 0.1848) * Self::SCALE
+    }
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Block Synthesis
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Frames synthesized together by the vectorized isochronic path.
+const LANES: usize = 4;
+
+/// One value per frame of a block.
+type Lanes = Simd<f64, LANES>;
+
+/// Oscillator phases of the isochronic voice, in cycles.
+#[derive(Debug, Clone, Copy)]
+struct IsochronicPhases {
+    tone: f64,
+    pulse: f64,
+    vibrato: f64,
+    autopan: f64,
+}
+
+/// Phase of each lane when advancing `phase` by the increment of each lane
+/// in turn; `phase` is left where the next block starts.
+#[inline]
+fn scan_phase(phase: &mut f64, increments: Lanes) -> Lanes {
+    let mut lanes = [0.0; LANES];
+    for (lane, inc) in lanes.iter_mut().zip(increments.to_array()) {
+        *lane = *phase;
+        *phase = (*phase + inc).fract();
+    }
+    Lanes::from_array(lanes)
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Sine Table
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Entries per cycle of the carrier sine table.
+const SINE_TABLE_SIZE: usize = 4096;
+
+/// One cycle of a sine, plus a copy of the first entry so interpolation
+/// never wraps.
+static SINE_TABLE: LazyLock<[f64; SINE_TABLE_SIZE + 1]> =
+    LazyLock::new(|| std::array::from_fn(|i| (i as f64 / SINE_TABLE_SIZE as f64 * TAU).sin()));
+
+/// `sin(phase * TAU)` for a phase in cycles, from the sine table.
+///
+/// Linear interpolation keeps the error below 1e-6, far under the noise
+/// floor of the f32 output.
+#[inline]
+fn sine_lut(phase: f64) -> f64 {
+    let pos = (phase - phase.floor()) * SINE_TABLE_SIZE as f64;
+    let index = (pos as usize).min(SINE_TABLE_SIZE - 1);
+    let frac = pos - index as f64;
+    let table = &*SINE_TABLE;
+    table[index] + (table[index + 1] - table[index]) * frac
+}
+
+/// [`sine_lut`] across lanes.
+#[inline]
+fn sine_lut_lanes(phase: Lanes) -> Lanes {
+    let pos = (phase - phase.floor()) * Lanes::splat(SINE_TABLE_SIZE as f64);
+    let index = pos.cast::<usize>().simd_min(Simd::splat(SINE_TABLE_SIZE - 1));
+    let frac = pos - index.cast::<f64>();
+    let table = &SINE_TABLE[..];
+    let low = Simd::gather_or_default(table, index);
+    let high = Simd::gather_or_default(table, index + Simd::splat(1));
+    low + (high - low) * frac
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Clicks
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Length of a keyframe click in seconds.
+const CLICK_SECS: f64 = 0.005;
+/// Pitch of a keyframe click in Hz.
+const CLICK_HZ: f64 = 1000.0;
+/// Peak amplitude of a keyframe click.
+const CLICK_VOL: f64 = 0.5;
+
+/// A short decaying click, `pos` frames after its onset.
+#[inline]
+fn click_sample(pos: u32, sample_rate: f64) -> f64 {
+    let t = f64::from(pos) / sample_rate;
+    (TAU * CLICK_HZ * t).cos() * (1.0 - t / CLICK_SECS) * CLICK_VOL
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Engine Options
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Mapping from program volume to output gain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmplitudeCurve {
+    /// Gain equals volume.
+    #[default]
+    Linear,
+    /// Gain is volume squared, closer to perceived loudness.
+    Squared,
+    /// Gain is volume cubed, for finer control at low levels.
+    Cubic,
+}
+
+impl AmplitudeCurve {
+    #[inline]
+    pub fn gain(self, vol: f64) -> f64 {
+        match self {
+            Self::Linear => vol,
+            Self::Squared => vol * vol,
+            Self::Cubic => vol * vol * vol,
+        }
+    }
+}
+
+impl std::str::FromStr for AmplitudeCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear" => Ok(Self::Linear),
+            "squared" => Ok(Self::Squared),
+            "cubic" => Ok(Self::Cubic),
+            _ => Err(format!("unknown amplitude curve '{s}' (expected: linear, squared, cubic)")),
+        }
+    }
+}
+
+/// Gain law for fades between two signals, where silence counts as a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrossfadeLaw {
+    /// Gains sum to one; the combined power dips 3 dB at the midpoint.
+    #[default]
+    Linear,
+    /// Squared gains sum to one, keeping uncorrelated signals at constant power.
+    EqualPower,
+}
+
+impl CrossfadeLaw {
+    /// Gains of the outgoing and incoming signal at position `t` [0, 1].
+    #[inline]
+    pub fn gains(self, t: f64) -> (f64, f64) {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => (1.0 - t, t),
+            Self::EqualPower => {
+                let (sin, cos) = (t * std::f64::consts::FRAC_PI_2).sin_cos();
+                (cos, sin)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for CrossfadeLaw {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear" => Ok(Self::Linear),
+            "equalpower" => Ok(Self::EqualPower),
+            _ => Err(format!("unknown crossfade law '{s}' (expected: linear, equalpower)")),
+        }
+    }
+}
+
+/// Runtime synthesis options that are not part of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EngineOptions {
+    /// Offset added to the binaural base tone in Hz.
+    pub tone_offset: f32,
+    /// Force binaural (`true`) or isochronic (`false`) synthesis regardless
+    /// of the program's `binaural` setting.
+    pub binaural: Option<bool>,
+    /// Maximum rate of change of the pulse frequency in Hz per second.
+    pub max_freq_slew: Option<f64>,
+    /// Snap the pulse frequency to the nearest multiple of this many Hz.
+    pub freq_grid: Option<f64>,
+    /// Mix a short click into the output at each keyframe.
+    pub keyframe_clicks: bool,
+    /// Play the isochronic carrier continuously instead of pulsing it.
+    pub ungated: bool,
+    /// Wall-clock instant of program time zero, shared between instances.
+    pub epoch: Option<SystemTime>,
+    /// Shaping applied to volume before it is used as gain.
+    pub amplitude_curve: AmplitudeCurve,
+    /// Starting phase of the carrier [0, 1), for reproducible measurements.
+    pub carrier_phase: f64,
+    /// Output gain in dB, applied on top of the program volume.
+    pub gain_db: f32,
+    /// Length of the fade out after a shutdown request in seconds
+    /// (default: [`DEFAULT_EXIT_FADE_SECS`]).
+    pub fade_out: Option<f64>,
+    /// Gain law of the fades after seeking or resuming and before exiting.
+    /// Parameter ramps, such as live edits and reloads, stay linear.
+    pub crossfade_law: CrossfadeLaw,
+    /// Level (0, 1] above which the output is soft-clipped (default: 0.95).
+    pub max_gain: Option<f32>,
+}
+
+impl EngineOptions {
+    /// Length of the fade out after a shutdown request, in seconds.
+    pub fn fade_out_secs(&self) -> f64 {
+        self.fade_out.unwrap_or(DEFAULT_EXIT_FADE_SECS)
+    }
+}
+
+/// Snap a frequency to the nearest multiple of `grid`, never below one grid step.
+#[inline]
+pub fn quantize_freq(freq: f64, grid: f64) -> f64 {
+    ((freq / grid).round() * grid).max(grid)
+}
+
+/// Callbacks a smaller buffer size must persist for before the latency
+/// compensation shrinks to it.
+const BUFFER_WINDOW: u32 = 64;
+
+/// Relative change in buffer size that updates the latency compensation.
+const BUFFER_TOLERANCE: f64 = 0.1;
+
+/// Pick a base tone offset uniformly within `[-range, range]` Hz.
+///
+/// Deterministic for a given seed so a session can be reproduced.
+pub fn random_tone_offset(range: f32, seed: u64) -> f32 {
+    // SplitMix64: one step is plenty for a single draw
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+
+    // Top 53 bits as a uniform value in [0, 1]
+    let unit = (z >> 11) as f64 / ((1u64 << 53) - 1) as f64;
+    ((unit * 2.0 - 1.0) * f64::from(range)) as f32
+}
+
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Audio Engine
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
+/// Volume below which a buffer is rendered as pure silence.
+const SILENT_VOL: f32 = 1e-6;
+
+/// Default length of the fade out after a shutdown request, in seconds.
+pub const DEFAULT_EXIT_FADE_SECS: f64 = 1.0;
+
+/// Fade-in after seeking into the program, in seconds.
+const SEEK_FADE_SECS: f64 = 0.005;
+
+/// Level above which the output limiter starts to compress, unless set with
+/// [`EngineOptions::max_gain`].
+const LIMITER_THRESHOLD: f32 = 0.95;
+
+/// Soft-clip samples above `threshold` so the output never leaves [-1, 1].
+///
+/// Levels up to the threshold pass unchanged; above it a tanh curve bends
+/// the excess into the remaining headroom.
+fn soft_limit(output: &mut [f32], threshold: f32) {
+    let headroom = 1.0 - threshold;
+    for sample in output.iter_mut() {
+        let magnitude = sample.abs();
+        if magnitude > threshold {
+            let limited = if headroom > 0.0 {
+                threshold + headroom * ((magnitude - threshold) / headroom).tanh()
+            } else {
+                1.0
+            };
+            *sample = limited.copysign(*sample);
+        }
+    }
+}
+
+/// Minimum time between warnings about clamped output gain, in seconds.
+const CLIP_WARNING_SECS: f64 = 10.0;
+
 /// Audio synthesis engine.
 ///
 /// Processes audio buffers and maintains oscillator state.
 pub struct AudioEngine {
     sample_rate: f64,
-    program: Arc<Program>,
+    program: Arc<LiveProgram>,
     sync: Arc<SyncState>,
+    options: EngineOptions,
+
+    // Linear output gain from `options.gain_db`
+    gain: f64,
 
     // Oscillator phases (f64 for long-session precision)
     left_phase: f64,
     right_phase: f64,
     pulse_phase: f64,
+    vibrato_phase: f64,
+    autopan_phase: f64,
+    swell_phase: f64,
+
+    // Pink noise generator for the `noise` parameter
+    noise: PinkNoise,
+
+    // Slew-limited pulse frequency and whether the limit is active
+    slewed_freq: Option<f64>,
+    slewing: bool,
+
+    // Position within the keyframe click being played
+    click_pos: Option<u32>,
+
+    // Output gain during the exit fade, and its decrease per frame once the
+    // fade has begun
+    exit_gain: f64,
+    exit_step: Option<f64>,
+
+    // Frame of the last warning about clamped gain
+    clip_warned_at: Option<u64>,
+
+    // Master intensity applied at the end of the last buffer
+    intensity: Option<f64>,
+
+    // Epoch to align to before the first audible buffer
+    pending_epoch: Option<SystemTime>,
+
+    // Position within the fade-in after a seek
+    seek_fade: Option<u32>,
+
+    // Largest buffer seen in the current measurement window, and its length in callbacks
+    buffer_window_max: u32,
+    buffer_window_len: u32,
 
     // Frame counter for time calculation
     frame_count: u64,
 }
 
 impl AudioEngine {
-    pub fn new(sample_rate: f64, program: Arc<Program>, sync: Arc<SyncState>) -> Self {
+    pub fn new(sample_rate: f64, program: Arc<LiveProgram>, sync: Arc<SyncState>) -> Self {
         Self {
             sample_rate,
             program,
             sync,
+            options: EngineOptions::default(),
+            gain: 1.0,
             left_phase: 0.0,
             right_phase: 0.0,
             pulse_phase: 0.0,
+            vibrato_phase: 0.0,
+            autopan_phase: 0.0,
+            noise: PinkNoise::new(),
+            swell_phase: 0.0,
+            slewed_freq: None,
+            slewing: false,
+            click_pos: None,
+            exit_gain: 1.0,
+            exit_step: None,
+            clip_warned_at: None,
+            intensity: None,
+            pending_epoch: None,
+            seek_fade: None,
+            buffer_window_max: 0,
+            buffer_window_len: 0,
             frame_count: 0,
         }
     }
 
+    /// Apply runtime synthesis options.
+    pub fn with_options(mut self, options: EngineOptions) -> Self {
+        self.options = options;
+        self.gain = 10f64.powf(f64::from(options.gain_db) / 20.0);
+        self.pending_epoch = options.epoch;
+        self.left_phase = options.carrier_phase.rem_euclid(1.0);
+        self.right_phase = self.left_phase;
+        self
+    }
+
+    /// Jump to `time` seconds into the program, with the pulse phase it would
+    /// have reached playing from the start.
+    pub fn seek(&mut self, time: f64) {
+        self.frame_count = (time.max(0.0) * self.sample_rate).round() as u64;
+        let time = self.frame_count as f64 / self.sample_rate;
+
+        self.pulse_phase = self.program.load().pulse_cycles_at(time).fract();
+        // Binaural beats are the phase difference between the two channels
+        self.right_phase = (self.left_phase + self.pulse_phase).fract();
+        self.slewed_freq = None;
+
+        // The new position may be mid-pulse; fade in rather than click
+        self.seek_fade = Some(0);
+    }
+
+    /// Seek to the position of `epoch` at wall-clock time `now`.
+    ///
+    /// Returns `false` without seeking while the epoch is still in the future.
+    fn align_to_epoch(&mut self, epoch: SystemTime, now: SystemTime) -> bool {
+        match now.duration_since(epoch) {
+            Ok(elapsed) => {
+                self.seek(elapsed.as_secs_f64());
+                info!("Aligned to epoch, {:.3}s into the program", elapsed.as_secs_f64());
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
     /// Process an audio buffer. Called from the audio thread.
     pub fn process(&mut self, output: &mut [f32], channels: usize) {
         let frame_count = output.len() / channels;
@@ -122,9 +659,27 @@ impl AudioEngine {
             return;
         }
 
-        // Update buffer size on first call (for latency compensation)
-        if self.sync.buffer_frames.load(Ordering::Relaxed) == 0 {
-            self.sync.buffer_frames.store(frame_count as u32, Ordering::Release);
+        // Keep the latency compensation in step with the driver's block size
+        self.track_buffer_size(frame_count as u32);
+
+        // Output silence and hold the timeline while paused
+        if self.sync.paused.load(Ordering::Acquire) {
+            output.fill(0.0);
+            // Resume mid-pulse with a fade in rather than a click
+            self.seek_fade = Some(0);
+            if self.sync.stopping.load(Ordering::Acquire) {
+                self.sync.stopped.store(true, Ordering::Release);
+            }
+            return;
+        }
+
+        // Hold silently at time zero until the shared epoch arrives
+        if let Some(epoch) = self.pending_epoch {
+            if !self.align_to_epoch(epoch, SystemTime::now()) {
+                output.fill(0.0);
+                return;
+            }
+            self.pending_epoch = None;
         }
 
         // Calculate time range for this buffer
@@ -132,16 +687,51 @@ impl AudioEngine {
         let t_end = (self.frame_count + frame_count as u64) as f64 / self.sample_rate;
 
         // Get interpolated parameters at buffer boundaries
-        let p_start = self.program.params_at(t_start);
-        let p_end = self.program.params_at(t_end);
+        let program = self.program.load();
+        let mut p_start = program.params_at(t_start);
+        let mut p_end = program.params_at(t_end);
+        if let Some(grid) = self.options.freq_grid {
+            p_start.freq = quantize_freq(p_start.freq, grid);
+            p_end.freq = quantize_freq(p_end.freq, grid);
+        }
+        self.limit_freq_slew(&mut p_start, &mut p_end, t_end - t_start);
 
         // Dispatch to appropriate synthesis method
-        if self.program.settings.binaural {
+        if p_start.vol.abs() < SILENT_VOL && p_end.vol.abs() < SILENT_VOL {
+            // Silent buffer: skip synthesis, but keep the pulse running for the visuals
+            output.fill(0.0);
+            self.advance_pulse_phase(&p_start, &p_end, frame_count);
+        } else if self.options.binaural.unwrap_or(program.settings.binaural) {
             self.process_binaural(output, channels, &p_start, &p_end);
+        } else if self.options.binaural.is_none() && program.settings.monaural {
+            self.process_monaural(output, channels, &p_start, &p_end);
         } else {
             self.process_isochronic(output, channels, &p_start, &p_end);
         }
 
+        self.mix_noise(output, channels, &p_start, &p_end);
+        self.apply_swell(output, channels, &p_start, &p_end);
+
+        if self.options.keyframe_clicks {
+            self.mix_keyframe_clicks(output, channels, &program);
+        }
+
+        self.apply_intensity(output, channels);
+
+        if self.seek_fade.is_some() {
+            self.apply_seek_fade(output, channels);
+        }
+
+        if self.sync.stopping.load(Ordering::Acquire) {
+            self.begin_fadeout(self.options.fade_out_secs());
+        }
+        if let Some(step) = self.exit_step {
+            self.apply_exit_fade(output, channels, step);
+        }
+
+        // Last stage, so it catches the sum of everything above
+        soft_limit(output, self.options.max_gain.unwrap_or(LIMITER_THRESHOLD));
+
         // Update frame counter
         self.frame_count += frame_count as u64;
 
@@ -150,137 +740,654 @@ impl AudioEngine {
         self.sync.phase_bits.store(self.pulse_phase.to_bits(), Ordering::Release);
     }
 
-    /// Generate binaural beats (stereo frequency difference).
-    fn process_binaural(
-        &mut self,
-        output: &mut [f32],
-        channels: usize,
-        p_start: &crate::program::Params,
-        p_end: &crate::program::Params,
-    ) {
-        let frame_count = output.len() / channels;
-        let inv_len = 1.0 / frame_count as f64;
-        let inv_sr = 1.0 / self.sample_rate;
-
-        let mut l_phase = self.left_phase;
-        let mut r_phase = self.right_phase;
-
-        for (i, frame) in output.chunks_exact_mut(channels).enumerate() {
-            // Linear parameter interpolation within buffer
-            let t = i as f64 * inv_len;
-
-            let vol = f64::from(p_start.vol) + f64::from(p_end.vol - p_start.vol) * t;
-            let tone = f64::from(p_start.tone) + f64::from(p_end.tone - p_start.tone) * t;
-            let freq = p_start.freq + (p_end.freq - p_start.freq) * t;
-
-            // Left channel: base tone, Right channel: base + beat frequency
-            let l_inc = tone * inv_sr;
-            let r_inc = (tone + freq) * inv_sr;
-
-            let l_sample = (l_phase * TAU).sin() * vol;
-            let r_sample = (r_phase * TAU).sin() * vol;
+    /// Update the published buffer size when the driver changes block sizes.
+    ///
+    /// Larger buffers take effect at once, since they add latency right away.
+    /// Smaller ones must persist for a whole window, so a single short block
+    /// from a driver with variable sizes doesn't shrink the compensation.
+    fn track_buffer_size(&mut self, frames: u32) {
+        let current = self.sync.buffer_frames.load(Ordering::Relaxed);
+        let significant =
+            |size: u32| f64::from(size.abs_diff(current)) > f64::from(current) * BUFFER_TOLERANCE;
+
+        self.buffer_window_max = self.buffer_window_max.max(frames);
+        self.buffer_window_len += 1;
+
+        let update = if current == 0 || (frames > current && significant(frames)) {
+            Some(frames)
+        } else if self.buffer_window_len >= BUFFER_WINDOW {
+            let max = self.buffer_window_max;
+            self.buffer_window_max = 0;
+            self.buffer_window_len = 0;
+            significant(max).then_some(max)
+        } else {
+            None
+        };
 
-            frame[0] = l_sample as f32;
-            if channels >= 2 {
-                frame[1] = r_sample as f32;
+        if let Some(size) = update {
+            if current != 0 {
+                info!("Audio buffer size changed: {current} -> {size} frames");
             }
-
-            // Advance phases (keep in [0, 1) for numerical stability)
-            l_phase = (l_phase + l_inc).fract();
-            r_phase = (r_phase + r_inc).fract();
+            self.sync.buffer_frames.store(size, Ordering::Release);
+            self.buffer_window_max = 0;
+            self.buffer_window_len = 0;
         }
-
-        self.left_phase = l_phase;
-        self.right_phase = r_phase;
-
-        // For binaural, pulse_phase tracks the beat phase for visual sync
-        let avg_freq = (p_start.freq + p_end.freq) * 0.5;
-        let phase_inc = avg_freq * (frame_count as f64 / self.sample_rate);
-        self.pulse_phase = (self.pulse_phase + phase_inc).fract();
     }
 
-    /// Generate isochronic tones (amplitude-modulated carrier).
-    fn process_isochronic(
-        &mut self,
-        output: &mut [f32],
-        channels: usize,
-        p_start: &crate::program::Params,
-        p_end: &crate::program::Params,
-    ) {
+    /// Modulate the output gain with the slow swell LFO.
+    ///
+    /// The gain dips from 1 to `1 - depth` and back once per cycle, so a swell
+    /// never raises the level above the program volume.
+    fn apply_swell(&mut self, output: &mut [f32], channels: usize, p_start: &Params, p_end: &Params) {
         let frame_count = output.len() / channels;
-        let inv_len = 1.0 / frame_count as f64;
         let inv_sr = 1.0 / self.sample_rate;
 
-        let mut tone_phase = self.left_phase;
-        let mut pulse_phase = self.pulse_phase;
+        if p_start.swell_depth == 0.0 && p_end.swell_depth == 0.0 {
+            let avg_rate = f64::from(p_start.swell_rate + p_end.swell_rate) * 0.5;
+            self.swell_phase = (self.swell_phase + avg_rate * frame_count as f64 * inv_sr).fract();
+            return;
+        }
 
+        let inv_len = 1.0 / frame_count as f64;
+        let mut phase = self.swell_phase;
         for (i, frame) in output.chunks_exact_mut(channels).enumerate() {
-            // Linear parameter interpolation within buffer
             let t = i as f64 * inv_len;
+            let rate = f64::from(p_start.swell_rate) + f64::from(p_end.swell_rate - p_start.swell_rate) * t;
+            let depth = f64::from(p_start.swell_depth) + f64::from(p_end.swell_depth - p_start.swell_depth) * t;
 
-            let vol = f64::from(p_start.vol) + f64::from(p_end.vol - p_start.vol) * t;
-            let tone = f64::from(p_start.tone) + f64::from(p_end.tone - p_start.tone) * t;
-            let freq = p_start.freq + (p_end.freq - p_start.freq) * t;
-            let duty = f64::from(p_start.duty) + f64::from(p_end.duty - p_start.duty) * t;
+            let gain = (1.0 - depth * 0.5 * (1.0 - (phase * TAU).cos())) as f32;
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+            phase = (phase + rate * inv_sr).fract();
+        }
+        self.swell_phase = phase;
+    }
 
-            // Phase increments
-            let tone_inc = tone * inv_sr;
-            let pulse_inc = freq * inv_sr;
+    /// Scale the output by the master intensity, ramping across the buffer
+    /// when it changed so live adjustments don't click.
+    fn apply_intensity(&mut self, output: &mut [f32], channels: usize) {
+        let target = self.sync.intensity();
+        let start = self.intensity.unwrap_or(target);
+        self.intensity = Some(target);
+        if start == 1.0 && target == 1.0 {
+            return;
+        }
+
+        let step = (target - start) / (output.len() / channels) as f64;
+        for (i, frame) in output.chunks_exact_mut(channels).enumerate() {
+            let gain = (start + step * (i + 1) as f64) as f32;
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
 
-            // Generate carrier tone
-            let carrier = (tone_phase * TAU).sin();
+    /// Ramp up the output after a seek or pause.
+    fn apply_seek_fade(&mut self, output: &mut [f32], channels: usize) {
+        let Some(start) = self.seek_fade else {
+            return;
+        };
+        let len = (SEEK_FADE_SECS * self.sample_rate) as u32;
 
-            // Generate smooth envelope to avoid clicks
-            // Ramp duration is 10% of period or half the duty cycle, whichever is smaller
-            let ramp = 0.1_f64.min(duty * 0.5);
-            let inv_ramp = if ramp > 1e-9 { 1.0 / ramp } else { 1e9 };
+        let mut pos = start;
+        for frame in output.chunks_exact_mut(channels) {
+            if pos >= len {
+                break;
+            }
+            let gain = self.options.crossfade_law.gains(f64::from(pos) / f64::from(len)).1 as f32;
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+            pos += 1;
+        }
 
-            let envelope = if pulse_phase >= duty {
-                0.0
-            } else {
-                // Trapezoidal envelope with smooth edges
-                let attack = (pulse_phase * inv_ramp).min(1.0);
-                let release = ((duty - pulse_phase) * inv_ramp).min(1.0);
-                let linear = attack.min(release);
-                // Apply smoothstep for softer transitions
-                linear * linear * (3.0 - 2.0 * linear)
-            };
+        self.seek_fade = (pos < len).then_some(pos);
+    }
 
-            let sample = (carrier * envelope * vol) as f32;
+    /// Start fading the output to silence over `secs`, after which the engine
+    /// reports itself as stopped. Calls while already fading are ignored.
+    pub fn begin_fadeout(&mut self, secs: f64) {
+        if self.exit_step.is_none() {
+            self.exit_step = Some(1.0 / (secs * self.sample_rate).max(1.0));
+        }
+    }
 
-            frame[0] = sample;
-            if channels >= 2 {
-                frame[1] = sample;
+    /// Lower `exit_gain` by `step` per frame, then report the engine as stopped.
+    ///
+    /// `exit_gain` runs linearly from 1 to 0; the crossfade law shapes it.
+    fn apply_exit_fade(&mut self, output: &mut [f32], channels: usize, step: f64) {
+        for frame in output.chunks_exact_mut(channels) {
+            let gain = self.options.crossfade_law.gains(1.0 - self.exit_gain).0 as f32;
+            for sample in frame.iter_mut() {
+                *sample *= gain;
             }
-
-            // Advance phases
-            tone_phase = (tone_phase + tone_inc).fract();
-            pulse_phase = (pulse_phase + pulse_inc).fract();
+            self.exit_gain = (self.exit_gain - step).max(0.0);
         }
 
-        self.left_phase = tone_phase;
-        self.pulse_phase = pulse_phase;
+        if self.exit_gain == 0.0 {
+            self.sync.stopped.store(true, Ordering::Release);
+        }
     }
-}
+
+    /// Mix pink noise into every channel at `noise` times the volume.
+    ///
+    /// Leaves the buffer untouched while noise is off.
+    fn mix_noise(&mut self, output: &mut [f32], channels: usize, p_start: &Params, p_end: &Params) {
+        if p_start.noise == 0.0 && p_end.noise == 0.0 {
+            return;
+        }
+
+        let inv_len = 1.0 / (output.len() / channels) as f64;
+        for (i, frame) in output.chunks_exact_mut(channels).enumerate() {
+            let t = i as f64 * inv_len;
+            let lerp = |a: f32, b: f32| f64::from(a) + f64::from(b - a) * t;
+
+            let level = self.output_gain(lerp(p_start.vol, p_end.vol)).min(1.0) * lerp(p_start.noise, p_end.noise);
+            let sample = (self.noise.next() * level) as f32;
+            for out in frame.iter_mut() {
+                *out += sample;
+            }
+        }
+    }
+
+    /// Output gain for a program volume, before clamping to unity.
+    #[inline]
+    fn output_gain(&self, vol: f64) -> f64 {
+        self.options.amplitude_curve.gain(vol) * self.gain
+    }
+
+    /// Warn, at most every [`CLIP_WARNING_SECS`], when a buffer's gain was clamped.
+    fn warn_if_clamped(&mut self, peak_gain: f64) {
+        if peak_gain <= 1.0 {
+            return;
+        }
+        let interval = (CLIP_WARNING_SECS * self.sample_rate) as u64;
+        if self.clip_warned_at.is_some_and(|frame| self.frame_count < frame + interval) {
+            return;
+        }
+        self.clip_warned_at = Some(self.frame_count);
+        warn!(
+            "Output gain {peak_gain:.2} ({:+.1} dB) exceeds 1.0 and is clamped; lower the volume or --gain-db",
+            20.0 * peak_gain.log10()
+        );
+    }
+
+    /// Clamp the pulse frequency change over a buffer to the configured slew rate.
+    fn limit_freq_slew(&mut self, p_start: &mut Params, p_end: &mut Params, dt: f64) {
+        let Some(max_slew) = self.options.max_freq_slew else {
+            return;
+        };
+
+        let current = self.slewed_freq.unwrap_or(p_start.freq);
+        let step = max_slew * dt;
+        let freq = p_end.freq.clamp(current - step, current + step);
+
+        let slewing = freq != p_end.freq;
+        if slewing != self.slewing {
+            self.slewing = slewing;
+            if slewing {
+                info!("Limiting frequency change to {max_slew} Hz/s");
+            } else {
+                info!("Frequency slew limit released");
+            }
+        }
+
+        p_start.freq = current;
+        p_end.freq = freq;
+        self.slewed_freq = Some(freq);
+    }
+
+    /// Mix a click into the buffer at each keyframe it covers.
+    fn mix_keyframe_clicks(&mut self, output: &mut [f32], channels: usize, program: &Program) {
+        let start = self.frame_count;
+        let end = start + (output.len() / channels) as u64;
+        let mut onsets = program
+            .keyframe_times()
+            .map(|time| (time * self.sample_rate).round() as u64)
+            .skip_while(|&frame| frame < start)
+            .take_while(|&frame| frame < end)
+            .peekable();
+
+        let click_frames = (CLICK_SECS * self.sample_rate) as u32;
+
+        for (i, frame) in output.chunks_exact_mut(channels).enumerate() {
+            if onsets.next_if_eq(&(start + i as u64)).is_some() {
+                self.click_pos = Some(0);
+            }
+
+            let Some(pos) = self.click_pos else {
+                if onsets.peek().is_none() {
+                    break;
+                }
+                continue;
+            };
+
+            let sample = click_sample(pos, self.sample_rate) as f32;
+            for out in frame.iter_mut() {
+                *out += sample;
+            }
+            self.click_pos = (pos + 1 < click_frames).then_some(pos + 1);
+        }
+    }
+
+    /// Generate binaural beats (stereo frequency difference).
+    fn process_binaural(
+        &mut self,
+        output: &mut [f32],
+        channels: usize,
+        p_start: &Params,
+        p_end: &Params,
+    ) {
+        let frame_count = output.len() / channels;
+        let inv_len = 1.0 / frame_count as f64;
+        let inv_sr = 1.0 / self.sample_rate;
+
+        let mut l_phase = self.left_phase;
+        let mut r_phase = self.right_phase;
+        let tone_offset = f64::from(self.options.tone_offset);
+        let mut peak_gain: f64 = 0.0;
+
+        for (i, frame) in output.chunks_exact_mut(channels).enumerate() {
+            // Linear parameter interpolation within buffer
+            let t = i as f64 * inv_len;
+
+            let vol = f64::from(p_start.vol) + f64::from(p_end.vol - p_start.vol) * t;
+            let gain = self.output_gain(vol);
+            peak_gain = peak_gain.max(gain);
+            let vol = gain.min(1.0);
+            let tone = f64::from(p_start.tone) + f64::from(p_end.tone - p_start.tone) * t + tone_offset;
+            let freq = p_start.freq + (p_end.freq - p_start.freq) * t;
+
+            // Left channel: base tone, Right channel: base + beat frequency
+            let l_inc = tone * inv_sr;
+            let r_inc = (tone + freq) * inv_sr;
+
+            let l_sample = sine_lut(l_phase) * vol;
+            let r_sample = sine_lut(r_phase) * vol;
+
+            frame[0] = l_sample as f32;
+            if channels >= 2 {
+                frame[1] = r_sample as f32;
+            }
+
+            // Advance phases (keep in [0, 1) for numerical stability)
+            l_phase = (l_phase + l_inc).fract();
+            r_phase = (r_phase + r_inc).fract();
+        }
+
+        self.left_phase = l_phase;
+        self.right_phase = r_phase;
+        self.warn_if_clamped(peak_gain);
+
+        // For binaural, pulse_phase tracks the beat phase for visual sync
+        self.advance_pulse_phase(p_start, p_end, frame_count);
+    }
+
+    /// Generate monaural beats: both tones mixed into every channel.
+    fn process_monaural(
+        &mut self,
+        output: &mut [f32],
+        channels: usize,
+        p_start: &Params,
+        p_end: &Params,
+    ) {
+        let frame_count = output.len() / channels;
+        let inv_len = 1.0 / frame_count as f64;
+        let inv_sr = 1.0 / self.sample_rate;
+
+        let mut low_phase = self.left_phase;
+        let mut high_phase = self.right_phase;
+        let tone_offset = f64::from(self.options.tone_offset);
+        let mut peak_gain: f64 = 0.0;
+
+        for (i, frame) in output.chunks_exact_mut(channels).enumerate() {
+            let t = i as f64 * inv_len;
+
+            let vol = f64::from(p_start.vol) + f64::from(p_end.vol - p_start.vol) * t;
+            let gain = self.output_gain(vol);
+            peak_gain = peak_gain.max(gain);
+            let vol = gain.min(1.0);
+            let tone = f64::from(p_start.tone) + f64::from(p_end.tone - p_start.tone) * t + tone_offset;
+            let freq = p_start.freq + (p_end.freq - p_start.freq) * t;
+
+            // Each tone at half level, so the sum peaks at vol where they line up
+            let sample = (sine_lut(low_phase) + sine_lut(high_phase)) * 0.5 * vol;
+            frame.fill(sample as f32);
+
+            low_phase = (low_phase + tone * inv_sr).fract();
+            high_phase = (high_phase + (tone + freq) * inv_sr).fract();
+        }
+
+        self.left_phase = low_phase;
+        self.right_phase = high_phase;
+        self.warn_if_clamped(peak_gain);
+
+        // The beat is the pulse, as for binaural
+        self.advance_pulse_phase(p_start, p_end, frame_count);
+    }
+
+    /// Advance the pulse phase over a buffer with linearly changing frequency.
+    fn advance_pulse_phase(&mut self, p_start: &Params, p_end: &Params, frame_count: usize) {
+        let avg_freq = (p_start.freq + p_end.freq) * 0.5;
+        let phase_inc = avg_freq * (frame_count as f64 / self.sample_rate);
+        self.pulse_phase = (self.pulse_phase + phase_inc).fract();
+    }
+
+    /// Generate isochronic tones (amplitude-modulated carrier).
+    fn process_isochronic(
+        &mut self,
+        output: &mut [f32],
+        channels: usize,
+        p_start: &Params,
+        p_end: &Params,
+    ) {
+        self.render_isochronic(output, channels, p_start, p_end, true);
+    }
+
+    /// Synthesize the isochronic voice, vectorized or one frame at a time.
+    ///
+    /// The vectorized path covers whole blocks of [`LANES`] frames and leaves
+    /// the remainder to the scalar loop, which picks up the phases where the
+    /// last block left them.
+    fn render_isochronic(
+        &mut self,
+        output: &mut [f32],
+        channels: usize,
+        p_start: &Params,
+        p_end: &Params,
+        vectorized: bool,
+    ) {
+        let frame_count = output.len() / channels;
+        let inv_len = 1.0 / frame_count as f64;
+        let inv_sr = 1.0 / self.sample_rate;
+
+        let mut phases = IsochronicPhases {
+            tone: self.left_phase,
+            pulse: self.pulse_phase,
+            vibrato: self.vibrato_phase,
+            autopan: self.autopan_phase,
+        };
+        let mut peak_gain: f64 = 0.0;
+
+        let block_frames = if vectorized { frame_count - frame_count % LANES } else { 0 };
+        let (blocks, tail) = output.split_at_mut(block_frames * channels);
+
+        for (b, block) in blocks.chunks_exact_mut(LANES * channels).enumerate() {
+            let t = Lanes::from_array(std::array::from_fn(|lane| (b * LANES + lane) as f64)) * Lanes::splat(inv_len);
+            let gain = self.isochronic_block(block, channels, &mut phases, t, p_start, p_end, inv_sr);
+            peak_gain = peak_gain.max(gain);
+        }
+
+        for (i, frame) in tail.chunks_exact_mut(channels).enumerate() {
+            let t = (block_frames + i) as f64 * inv_len;
+            let gain = self.isochronic_frame(frame, &mut phases, t, p_start, p_end, inv_sr);
+            peak_gain = peak_gain.max(gain);
+        }
+
+        self.left_phase = phases.tone;
+        self.pulse_phase = phases.pulse;
+        self.vibrato_phase = phases.vibrato;
+        self.autopan_phase = phases.autopan;
+        self.warn_if_clamped(peak_gain);
+    }
+
+    /// Synthesize one frame at position `t` [0, 1) of the buffer and advance
+    /// the phases. Returns the unclamped gain.
+    #[inline]
+    fn isochronic_frame(
+        &self,
+        frame: &mut [f32],
+        phases: &mut IsochronicPhases,
+        t: f64,
+        p_start: &Params,
+        p_end: &Params,
+        inv_sr: f64,
+    ) -> f64 {
+        // Linear parameter interpolation within buffer
+        let lerp = |a: f32, b: f32| f64::from(a) + f64::from(b - a) * t;
+
+        let gain = self.output_gain(lerp(p_start.vol, p_end.vol));
+        let vol = gain.min(1.0);
+        let tone = lerp(p_start.tone, p_end.tone);
+        let freq = p_start.freq + (p_end.freq - p_start.freq) * t;
+        let duty = lerp(p_start.duty, p_end.duty);
+        let smooth = lerp(p_start.smooth, p_end.smooth);
+        let depth = lerp(p_start.vibrato_depth, p_end.vibrato_depth);
+        let rate = lerp(p_start.vibrato_rate, p_end.vibrato_rate);
+        let pan_rate = lerp(p_start.autopan_rate, p_end.autopan_rate);
+        let pan_depth = lerp(p_start.autopan_depth, p_end.autopan_depth);
+        let pan = lerp(p_start.pan, p_end.pan);
+
+        // Slow sinusoidal FM of the carrier
+        let tone = tone + depth * (phases.vibrato * TAU).sin();
+
+        // Phase increments
+        let tone_inc = tone * inv_sr;
+        let pulse_inc = freq * inv_sr;
+
+        // Generate carrier tone
+        let carrier = sine_lut(phases.tone);
+
+        // Generate smooth envelope to avoid clicks
+        let envelope = if self.options.ungated {
+            1.0
+        } else {
+            // The shape switches at a keyframe, so it holds for the whole buffer
+            Envelope::new(duty, freq, smooth).with_shape(p_start.env).value(phases.pulse)
+        };
+
+        let sample = carrier * envelope * vol;
+
+        if frame.len() >= 2 {
+            let (left, right) = pan_gains((pan + pan_depth * (phases.autopan * TAU).sin()).clamp(-1.0, 1.0));
+            frame[0] = (sample * left) as f32;
+            frame[1] = (sample * right) as f32;
+        } else {
+            frame[0] = sample as f32;
+        }
+
+        // Advance phases
+        phases.tone = (phases.tone + tone_inc).fract();
+        phases.pulse = (phases.pulse + pulse_inc).fract();
+        phases.vibrato = (phases.vibrato + rate * inv_sr).fract();
+        phases.autopan = (phases.autopan + pan_rate * inv_sr).fract();
+
+        gain
+    }
+
+    /// Synthesize [`LANES`] frames at positions `t` of the buffer, the same
+    /// way as [`Self::isochronic_frame`]. Returns the largest unclamped gain.
+    #[inline]
+    #[allow(clippy::too_many_arguments)]
+    fn isochronic_block(
+        &self,
+        block: &mut [f32],
+        channels: usize,
+        phases: &mut IsochronicPhases,
+        t: Lanes,
+        p_start: &Params,
+        p_end: &Params,
+        inv_sr: f64,
+    ) -> f64 {
+        let lerp = |a: f64, b: f64| Lanes::splat(a) + Lanes::splat(b - a) * t;
+        let lerp32 = |a: f32, b: f32| lerp(f64::from(a), f64::from(b));
+        let one = Lanes::splat(1.0);
+        let inv_sr = Lanes::splat(inv_sr);
+
+        let gain = lerp32(p_start.vol, p_end.vol);
+        let gain = Lanes::from_array(gain.to_array().map(|vol| self.output_gain(vol)));
+        let vol = gain.simd_min(one);
+        let freq = lerp(p_start.freq, p_end.freq);
+        let duty = lerp32(p_start.duty, p_end.duty);
+        let smooth = lerp32(p_start.smooth, p_end.smooth);
+        let depth = lerp32(p_start.vibrato_depth, p_end.vibrato_depth);
+        let rate = lerp32(p_start.vibrato_rate, p_end.vibrato_rate);
+        let pan_rate = lerp32(p_start.autopan_rate, p_end.autopan_rate);
+        let pan_depth = lerp32(p_start.autopan_depth, p_end.autopan_depth);
+        let pan = lerp32(p_start.pan, p_end.pan);
+
+        // Each lane's phases are the running sum of the increments before it
+        let vibrato_phase = scan_phase(&mut phases.vibrato, rate * inv_sr);
+        let autopan_phase = scan_phase(&mut phases.autopan, pan_rate * inv_sr);
+        let tone = lerp32(p_start.tone, p_end.tone) + depth * (vibrato_phase * Lanes::splat(TAU)).sin();
+        let tone_phase = scan_phase(&mut phases.tone, tone * inv_sr);
+        let pulse_phase = scan_phase(&mut phases.pulse, freq * inv_sr);
+
+        let carrier = sine_lut_lanes(tone_phase);
+
+        let envelope = if self.options.ungated {
+            one
+        } else if p_start.env == EnvelopeShape::RaisedCosine {
+            let value = Lanes::splat(0.5) * (one - (Lanes::splat(TAU) * pulse_phase / duty).cos());
+            pulse_phase.simd_ge(duty).select(Lanes::splat(0.0), value)
+        } else {
+            // Envelope::new and Envelope::value across lanes
+            let ramp = smooth
+                .simd_gt(Lanes::splat(0.0))
+                .select(smooth * freq, Lanes::splat(0.1))
+                .simd_min(duty * Lanes::splat(0.5));
+            let inv_ramp = ramp.simd_gt(Lanes::splat(1e-9)).select(one / ramp, Lanes::splat(1e9));
+            let attack = (pulse_phase * inv_ramp).simd_min(one);
+            let release = ((duty - pulse_phase) * inv_ramp).simd_min(one);
+            let linear = attack.simd_min(release);
+            let value = linear * linear * (Lanes::splat(3.0) - Lanes::splat(2.0) * linear);
+            pulse_phase.simd_ge(duty).select(Lanes::splat(0.0), value)
+        };
+
+        let sample = (carrier * envelope * vol).to_array();
+
+        if channels >= 2 {
+            let pan = (pan + pan_depth * (autopan_phase * Lanes::splat(TAU)).sin())
+                .simd_clamp(Lanes::splat(-1.0), one)
+                .to_array();
+            for (lane, frame) in block.chunks_exact_mut(channels).enumerate() {
+                let (left, right) = pan_gains(pan[lane]);
+                frame[0] = (sample[lane] * left) as f32;
+                frame[1] = (sample[lane] * right) as f32;
+            }
+        } else {
+            for (out, sample) in block.iter_mut().zip(sample) {
+                *out = sample as f32;
+            }
+        }
+
+        gain.reduce_max()
+    }
+}
 
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Audio Setup
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
+/// Pick `requested` if any of the supported rate ranges contains it.
+fn select_sample_rate(requested: u32, supported: &[RangeInclusive<u32>]) -> Option<u32> {
+    supported
+        .iter()
+        .any(|range| range.contains(&requested))
+        .then_some(requested)
+}
+
+/// Describe each output device with its default config, e.g.
+/// "Headphones (48000 Hz, 2 channels, f32)".
+///
+/// Devices that fail to report a name or config are skipped with a warning.
+pub fn list_output_devices() -> Result<Vec<String>> {
+    let host = cpal::default_host();
+    let mut devices = Vec::new();
+
+    for device in host.output_devices()? {
+        let name = match device.description() {
+            Ok(description) => description.name().to_owned(),
+            Err(e) => {
+                warn!("Skipping output device without a name: {e}");
+                continue;
+            }
+        };
+        match device.default_output_config() {
+            Ok(config) => devices.push(format!(
+                "{name} ({} Hz, {} channels, {})",
+                config.sample_rate(),
+                config.channels(),
+                config.sample_format()
+            )),
+            Err(e) => warn!("Skipping output device '{name}': {e}"),
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Index of the device called `wanted`, ignoring case.
+///
+/// An exact name wins; otherwise the first name containing `wanted` is used.
+fn match_device_name<S: AsRef<str>>(names: &[S], wanted: &str) -> Option<usize> {
+    let wanted = wanted.to_lowercase();
+    let names: Vec<String> = names.iter().map(|name| name.as_ref().to_lowercase()).collect();
+    names
+        .iter()
+        .position(|name| *name == wanted)
+        .or_else(|| names.iter().position(|name| name.contains(&wanted)))
+}
+
+/// The output device called `name`, or the default device if it isn't found.
+fn select_output_device(host: &cpal::Host, name: Option<&str>) -> Result<cpal::Device> {
+    if let Some(wanted) = name {
+        let devices: Vec<_> = host.output_devices()?.collect();
+        let names: Vec<String> = devices
+            .iter()
+            .map(|device| device.description().map(|d| d.name().to_owned()).unwrap_or_default())
+            .collect();
+
+        match match_device_name(&names, wanted) {
+            Some(index) => return Ok(devices.into_iter().nth(index).expect("index from the same list")),
+            None => warn!("No audio output device matches '{wanted}', using the default"),
+        }
+    }
+
+    host.default_output_device()
+        .ok_or_else(|| anyhow::anyhow!("No audio output device available"))
+}
+
 /// Initialize audio output and start playback.
 ///
+/// Plays on the output device matching `device` by name, or the default.
 /// Returns the stream handle (must be kept alive) and initializes the sync state.
-pub fn start(program: Arc<Program>, sync: Arc<SyncState>) -> Result<cpal::Stream> {
+pub fn start(
+    program: Arc<LiveProgram>,
+    sync: Arc<SyncState>,
+    options: EngineOptions,
+    device: Option<&str>,
+) -> Result<cpal::Stream> {
     let host = cpal::default_host();
-
-    let device = host
-        .default_output_device()
-        .ok_or_else(|| anyhow::anyhow!("No audio output device available"))?;
+    let device = select_output_device(&host, device)?;
 
     let device_name = device.description().map(|d| d.name().to_owned())?;
     info!("Audio device: {device_name}");
 
-    let config: StreamConfig = device.default_output_config()?.into();
+    let default_config = device.default_output_config()?;
+    let mut config: StreamConfig = default_config.clone().into();
+
+    // Use the program's preferred rate if the device supports it
+    if let Some(requested) = program.load().settings.sample_rate {
+        let supported: Vec<_> = device
+            .supported_output_configs()?
+            .filter(|c| {
+                c.channels() == default_config.channels()
+                    && c.sample_format() == default_config.sample_format()
+            })
+            .map(|c| c.min_sample_rate()..=c.max_sample_rate())
+            .collect();
+
+        match select_sample_rate(requested, &supported) {
+            Some(rate) => config.sample_rate = rate,
+            None => warn!(
+                "Sample rate {requested} Hz not supported, using {} Hz",
+                config.sample_rate
+            ),
+        }
+    }
+
     let sample_rate = config.sample_rate;
     let channels = config.channels as usize;
 
@@ -290,7 +1397,7 @@ pub fn start(program: Arc<Program>, sync: Arc<SyncState>) -> Result<cpal::Stream
     sync.sample_rate.store(sample_rate, Ordering::Release);
 
     // Create engine
-    let mut engine = AudioEngine::new(f64::from(sample_rate), program, sync);
+    let mut engine = AudioEngine::new(f64::from(sample_rate), program, sync).with_options(options);
 
     // Build and start stream
     let stream = device.build_output_stream(
@@ -307,6 +1414,191 @@ pub fn start(program: Arc<Program>, sync: Arc<SyncState>) -> Result<cpal::Stream
     Ok(stream)
 }
 
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Offline Rendering
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Default sample rate for offline rendering.
+pub const RENDER_SAMPLE_RATE: u32 = 48000;
+
+/// Frames synthesized per call when rendering, about a device buffer's worth.
+const RENDER_CHUNK_FRAMES: usize = 1024;
+
+/// Render `duration` seconds of the program to a 16-bit stereo WAV file.
+///
+/// Runs the same engine as live playback, so the file sounds like a session
+/// with the same options. Returns the number of frames written.
+pub fn render_to_wav(
+    program: Arc<Program>,
+    path: &Path,
+    sample_rate: u32,
+    duration: f64,
+    options: EngineOptions,
+) -> Result<u64> {
+    anyhow::ensure!(duration.is_finite() && duration > 0.0, "render duration must be positive and finite");
+
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)
+        .map_err(|e| anyhow::anyhow!("creating '{}': {e}", path.display()))?;
+
+    let total = (duration * f64::from(sample_rate)).round() as u64;
+    let written = render_chunks(program, sample_rate, options, Some(total), |chunk| {
+        for &sample in chunk {
+            writer
+                .write_sample((sample * f32::from(i16::MAX)) as i16)
+                .map_err(|e| anyhow::anyhow!("writing '{}': {e}", path.display()))?;
+        }
+        Ok(true)
+    })?;
+    writer
+        .finalize()
+        .map_err(|e| anyhow::anyhow!("writing '{}': {e}", path.display()))?;
+
+    Ok(written)
+}
+
+/// Stream the program as raw interleaved stereo f32 samples (little-endian).
+///
+/// Renders `duration` seconds, or without one until `out` is closed; a broken
+/// pipe ends the render normally. Returns the number of frames written.
+pub fn render_raw(
+    program: Arc<Program>,
+    out: &mut impl std::io::Write,
+    sample_rate: u32,
+    duration: Option<f64>,
+    options: EngineOptions,
+) -> Result<u64> {
+    if let Some(secs) = duration {
+        anyhow::ensure!(secs.is_finite() && secs > 0.0, "render duration must be positive and finite");
+    }
+
+    let total = duration.map(|secs| (secs * f64::from(sample_rate)).round() as u64);
+    let mut bytes = Vec::with_capacity(RENDER_CHUNK_FRAMES * 2 * size_of::<f32>());
+    let mut closed = false;
+    let written = render_chunks(program, sample_rate, options, total, |chunk| {
+        bytes.clear();
+        bytes.extend(chunk.iter().flat_map(|sample| sample.to_le_bytes()));
+        match out.write_all(&bytes) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                closed = true;
+                Ok(false)
+            }
+            Err(e) => Err(anyhow::anyhow!("writing raw audio: {e}")),
+        }
+    })?;
+    if !closed
+        && let Err(e) = out.flush()
+        && e.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        anyhow::bail!("writing raw audio: {e}");
+    }
+
+    Ok(written)
+}
+
+/// Run the engine offline over `total` frames, or until `sink` returns false,
+/// passing each chunk of stereo samples to `sink`. Returns the frames handed
+/// to a sink that accepted them.
+fn render_chunks(
+    program: Arc<Program>,
+    sample_rate: u32,
+    options: EngineOptions,
+    total: Option<u64>,
+    mut sink: impl FnMut(&[f32]) -> Result<bool>,
+) -> Result<u64> {
+    let live = Arc::new(LiveProgram::new(program));
+    // Offline time starts at zero, not at a wall-clock instant
+    let options = EngineOptions { epoch: None, ..options };
+    let mut engine =
+        AudioEngine::new(f64::from(sample_rate), live, Arc::new(SyncState::new())).with_options(options);
+
+    let mut buffer = vec![0.0f32; RENDER_CHUNK_FRAMES * 2];
+    let mut written = 0;
+    while total.is_none_or(|total| written < total) {
+        let frames = total.map_or(RENDER_CHUNK_FRAMES, |total| {
+            (total - written).min(RENDER_CHUNK_FRAMES as u64) as usize
+        });
+        let chunk = &mut buffer[..frames * 2];
+        engine.process(chunk, 2);
+        if !sink(chunk)? {
+            break;
+        }
+        written += frames as u64;
+    }
+
+    Ok(written)
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Rendered File Verification
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Expected format of a rendered audio file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSpec {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Duration in seconds.
+    pub duration: f64,
+}
+
+/// Format found in a WAV file, plus any mismatches against a [`RenderSpec`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavReport {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub duration: f64,
+    pub problems: Vec<String>,
+}
+
+impl WavReport {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Read back a WAV file and check it against the expected format.
+pub fn verify_wav(path: &Path, expected: &RenderSpec) -> Result<WavReport> {
+    let reader = hound::WavReader::open(path)
+        .map_err(|e| anyhow::anyhow!("opening '{}': {e}", path.display()))?;
+    let spec = reader.spec();
+    let duration = f64::from(reader.duration()) / f64::from(spec.sample_rate);
+
+    let mut problems = Vec::new();
+    if spec.sample_rate != expected.sample_rate {
+        problems.push(format!(
+            "sample rate is {} Hz, expected {} Hz",
+            spec.sample_rate, expected.sample_rate
+        ));
+    }
+    if spec.channels != expected.channels {
+        problems.push(format!(
+            "{} channels, expected {}",
+            spec.channels, expected.channels
+        ));
+    }
+    // Allow one frame of rounding
+    if (duration - expected.duration).abs() > 1.0 / f64::from(expected.sample_rate) {
+        problems.push(format!(
+            "duration is {duration:.3}s, expected {:.3}s",
+            expected.duration
+        ));
+    }
+
+    Ok(WavReport {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        duration,
+        problems,
+    })
+}
+
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Tests
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
@@ -314,10 +1606,13 @@ pub fn start(program: Arc<Program>, sync: Arc<SyncState>) -> Result<cpal::Stream
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::program::{Params, Settings};
+    use crate::program::{Program, Settings};
+    use std::f64::consts::FRAC_1_SQRT_2;
+    use std::time::Duration;
 
-    fn test_program() -> Arc<Program> {
-        Arc::new(Program::constant(Params::default(), Settings::default()))
+    fn test_program() -> Arc<LiveProgram> {
+        let program = Program::constant(Params::default(), Settings::default());
+        Arc::new(LiveProgram::new(Arc::new(program)))
     }
 
     #[test]
@@ -352,6 +1647,767 @@ mod tests {
         assert!(engine.pulse_phase >= 0.0 && engine.pulse_phase < 1.0);
     }
 
+    #[test]
+    fn engine_picks_up_swapped_program() {
+        let sync = Arc::new(SyncState::new());
+        let live = test_program();
+        let mut engine = AudioEngine::new(48000.0, live.clone(), sync);
+
+        let mut buffer = vec![0.0f32; 1024];
+        engine.process(&mut buffer, 2);
+        assert!(buffer.iter().any(|&s| s.abs() > 0.001));
+
+        let silent = Params { vol: 0.0, ..Params::default() };
+        live.store(Program::constant(silent, Settings::default()));
+        engine.process(&mut buffer, 2);
+        assert!(buffer.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn paused_engine_is_silent_and_holds_time() {
+        let sync = Arc::new(SyncState::new());
+        let mut engine = AudioEngine::new(48000.0, test_program(), sync.clone());
+
+        let mut buffer = vec![0.0f32; 1024];
+        engine.process(&mut buffer, 2);
+        let written = sync.frames_written.load(Ordering::Acquire);
+
+        sync.paused.store(true, Ordering::Release);
+        engine.process(&mut buffer, 2);
+        assert!(buffer.iter().all(|&s| s == 0.0));
+        assert_eq!(sync.frames_written.load(Ordering::Acquire), written);
+    }
+
+    #[test]
+    fn fadeout_reaches_silence_and_stops() {
+        let params = Params { tone: 1000.0, vol: 1.0, ..Params::default() };
+        let program = Program::constant(params, Settings { binaural: true, ..Settings::default() });
+        let sync = Arc::new(SyncState::new());
+        let mut engine = AudioEngine::new(48000.0, Arc::new(LiveProgram::new(Arc::new(program))), sync.clone());
+
+        engine.begin_fadeout(0.01);
+        engine.begin_fadeout(10.0); // already fading
+        let mut buffer = vec![0.0f32; 960];
+        engine.process(&mut buffer, 2);
+
+        // Linear law: 480 frames over a 480 frame fade, each at or below its gain
+        for (i, frame) in buffer.chunks_exact(2).enumerate() {
+            let gain = 1.0 - i as f32 / 480.0;
+            assert!(frame.iter().all(|s| s.abs() <= gain + 1e-6), "{i}: {frame:?}");
+        }
+        engine.process(&mut buffer, 2);
+        assert!(buffer.iter().all(|s| s.abs() < 1e-6));
+        assert!(sync.stopped.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn resuming_fades_in() {
+        let program = Program::constant(
+            Params { tone: 1000.0, vol: 1.0, ..Params::default() },
+            Settings { binaural: true, ..Settings::default() },
+        );
+        let sync = Arc::new(SyncState::new());
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let mut engine = AudioEngine::new(48000.0, live, sync.clone());
+
+        let mut buffer = vec![0.0f32; 1024];
+        engine.process(&mut buffer, 2);
+        sync.paused.store(true, Ordering::Release);
+        engine.process(&mut buffer, 2);
+        sync.paused.store(false, Ordering::Release);
+        engine.process(&mut buffer, 2);
+
+        // The first frames back stay near silence instead of jumping to full level
+        let fade = (SEEK_FADE_SECS * 48000.0) as usize;
+        let head = buffer[..fade / 4 * 2].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        let tail = buffer[fade * 2..].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(head < 0.3, "{head}");
+        assert!(tail > 0.9, "{tail}");
+    }
+
+    #[test]
+    fn mode_override_selects_synthesis() {
+        let render = |binaural: bool, force: Option<bool>| {
+            let program = Program::constant(
+                Params { freq: 10.0, tone: 200.0, ..Params::default() },
+                Settings { binaural, ..Settings::default() },
+            );
+            let live = Arc::new(LiveProgram::new(Arc::new(program)));
+            let options = EngineOptions { binaural: force, ..EngineOptions::default() };
+            let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+            let mut buffer = vec![0.0f32; 4096];
+            engine.process(&mut buffer, 2);
+            // Binaural channels differ; isochronic channels are identical
+            buffer.chunks_exact(2).any(|f| f[0] != f[1])
+        };
+
+        assert!(!render(false, None));
+        assert!(render(true, None));
+        assert!(render(false, Some(true)));
+        assert!(!render(true, Some(false)));
+    }
+
+    #[test]
+    fn smooth_widens_envelope_edges() {
+        let program = Program::parse("00:00 freq=10 duty=0.5\n00:10 smooth=0.02").unwrap();
+        let sharp = Envelope::from_params(&program.params_at(0.0));
+        let soft = Envelope::from_params(&program.params_at(10.0));
+
+        // Default keeps the 10% of period heuristic
+        assert!((sharp.ramp() - 0.1).abs() < 1e-9);
+        assert!((soft.ramp() - 0.2).abs() < 1e-6);
+
+        // 5 ms into the pulse the sharp edge is done, the soft one is not
+        assert_eq!(sharp.value(0.05), 0.5);
+        assert!(soft.value(0.05) < 0.2);
+        assert_eq!(soft.value(0.25), 1.0);
+    }
+
+    #[test]
+    fn vibrato_oscillates_carrier_within_depth() {
+        let program = Program::parse("00:00 tone=200 vibrato=10:5").unwrap();
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+
+        // One frame per buffer, so each phase step is the instantaneous frequency
+        let mut frame = [0.0f32; 2];
+        let (mut lowest, mut highest) = (f64::MAX, f64::MIN);
+        for _ in 0..48000 {
+            let before = engine.left_phase;
+            engine.process(&mut frame, 2);
+            let hz = (engine.left_phase - before).rem_euclid(1.0) * 48000.0;
+            lowest = lowest.min(hz);
+            highest = highest.max(hz);
+        }
+
+        assert!((lowest - 190.0).abs() < 0.1);
+        assert!((highest - 210.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn freq_step_is_slewed() {
+        let program = Program::parse("00:00 freq=10\n00:01 freq=20").unwrap();
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let options = EngineOptions { max_freq_slew: Some(5.0), ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        // 10 ms buffers: the step lands at the end of buffer 100
+        let mut buffer = vec![0.0f32; 960];
+        for _ in 0..100 {
+            engine.process(&mut buffer, 2);
+        }
+        assert!((engine.slewed_freq.unwrap() - 10.05).abs() < 1e-9);
+        assert!(engine.slewing);
+
+        for _ in 0..100 {
+            engine.process(&mut buffer, 2);
+        }
+        let freq = engine.slewed_freq.unwrap();
+        assert!((freq - 15.05).abs() < 1e-6, "{freq}");
+
+        // Reaches the target and releases the limit
+        for _ in 0..200 {
+            engine.process(&mut buffer, 2);
+        }
+        assert_eq!(engine.slewed_freq, Some(20.0));
+        assert!(!engine.slewing);
+    }
+
+    #[test]
+    fn freq_snaps_to_grid() {
+        assert_eq!(quantize_freq(6.3, 0.5), 6.5);
+        assert_eq!(quantize_freq(6.2, 0.5), 6.0);
+        assert_eq!(quantize_freq(0.1, 0.5), 0.5);
+
+        let program = Program::parse("00:00 freq=6.3").unwrap();
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let options = EngineOptions { freq_grid: Some(0.5), ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        let mut buffer = vec![0.0f32; 96000];
+        engine.process(&mut buffer, 2);
+        assert!((engine.pulse_phase - 0.5).abs() < 1e-6, "{}", engine.pulse_phase);
+    }
+
+    #[test]
+    fn ungated_engine_plays_steady_sine() {
+        let params = Params { tone: 1000.0, vol: 0.5, ..Params::default() };
+        let program = Program::constant(params, Settings::default());
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let options = EngineOptions { ungated: true, ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        let mut buffer = vec![0.0f32; 9600];
+        for block in 0..10 {
+            engine.process(&mut buffer, 2);
+            for (i, frame) in buffer.chunks_exact(2).enumerate() {
+                let n = (block * 4800 + i) as f64;
+                // Centered, so each channel is 3 dB down
+                let expected = 0.5 * FRAC_1_SQRT_2 * (TAU * 1000.0 * n / 48000.0).sin();
+                assert!((f64::from(frame[0]) - expected).abs() < 1e-4, "sample {n}");
+                assert_eq!(frame[0], frame[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn engines_sharing_an_epoch_agree_on_phase() {
+        let program = Arc::new(Program::parse("00:00 freq=10\n00:10 freq=20 >linear").unwrap());
+        let engine = || {
+            let live = Arc::new(LiveProgram::new(program.clone()));
+            AudioEngine::new(48000.0, live, Arc::new(SyncState::new()))
+        };
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        // Launched a second apart, then compared at the same wall time
+        let mut early = engine();
+        let mut late = engine();
+        assert!(early.align_to_epoch(epoch, epoch + Duration::from_secs(2)));
+        let mut buffer = vec![0.0f32; 960];
+        for _ in 0..100 {
+            early.process(&mut buffer, 2);
+        }
+        assert!(late.align_to_epoch(epoch, epoch + Duration::from_secs(3)));
+
+        assert_eq!(early.frame_count, late.frame_count);
+        let diff = (early.pulse_phase - late.pulse_phase).abs();
+        assert!(diff.min(1.0 - diff) < 1e-4, "{} vs {}", early.pulse_phase, late.pulse_phase);
+
+        // Before the epoch, nothing moves
+        let mut waiting = engine();
+        assert!(!waiting.align_to_epoch(epoch, epoch - Duration::from_secs(1)));
+        assert_eq!(waiting.frame_count, 0);
+    }
+
+    #[test]
+    fn amplitude_curve_shapes_gain() {
+        assert_eq!(AmplitudeCurve::Squared.gain(0.5), 0.25);
+        assert_eq!(AmplitudeCurve::Cubic.gain(0.5), 0.125);
+        assert_eq!(AmplitudeCurve::Linear.gain(0.5), 0.5);
+        assert_eq!("Squared".parse(), Ok(AmplitudeCurve::Squared));
+        assert!("log".parse::<AmplitudeCurve>().is_err());
+
+        // Applied where volume becomes gain
+        let params = Params { tone: 1000.0, vol: 0.5, ..Params::default() };
+        let program = Program::constant(params, Settings { binaural: true, ..Settings::default() });
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let options = EngineOptions { amplitude_curve: AmplitudeCurve::Squared, ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        let mut buffer = vec![0.0f32; 9600];
+        engine.process(&mut buffer, 2);
+        let peak = buffer.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!((peak - 0.25).abs() < 1e-3, "{peak}");
+    }
+
+    #[test]
+    fn swell_modulates_level_at_its_rate() {
+        let params = Params { tone: 1000.0, vol: 1.0, swell_rate: 0.5, swell_depth: 1.0, ..Params::default() };
+        let program = Program::constant(params, Settings { binaural: true, ..Settings::default() });
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        // Without limiting, so full scale measures as 1
+        let options = EngineOptions { max_gain: Some(1.0), ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        // Peak level of each 50 ms block over two swell periods
+        let mut buffer = vec![0.0f32; 4800];
+        let levels: Vec<f32> = (0..80)
+            .map(|_| {
+                engine.process(&mut buffer, 2);
+                buffer.iter().fold(0.0f32, |m, s| m.max(s.abs()))
+            })
+            .collect();
+
+        // Loudest at the start of each 2 s cycle, silent halfway through
+        assert!(levels[0] > 0.99 && levels[40] > 0.99, "{levels:?}");
+        assert!(levels[19] < 0.05 && levels[59] < 0.05, "{levels:?}");
+        assert!((levels[10] - 0.5).abs() < 0.1, "{levels:?}");
+    }
+
+    #[test]
+    fn equal_power_keeps_midpoint_power() {
+        let power = |law: CrossfadeLaw| {
+            let (out, into) = law.gains(0.5);
+            out * out + into * into
+        };
+        assert!((power(CrossfadeLaw::Linear) - 0.5).abs() < 1e-12);
+        assert!((power(CrossfadeLaw::EqualPower) - 1.0).abs() < 1e-12);
+
+        for law in [CrossfadeLaw::Linear, CrossfadeLaw::EqualPower] {
+            assert_eq!(law.gains(0.0), (1.0, 0.0));
+            let (out, into) = law.gains(1.0);
+            assert!(out.abs() < 1e-12 && (into - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn vectorized_isochronic_matches_scalar() {
+        let p_start = Params {
+            freq: 10.0,
+            tone: 300.0,
+            vol: 0.2,
+            duty: 0.4,
+            vibrato_rate: 5.0,
+            vibrato_depth: 20.0,
+            autopan_rate: 2.0,
+            autopan_depth: 0.8,
+            pan: 0.3,
+            ..Params::default()
+        };
+        let p_end = Params { freq: 12.0, tone: 320.0, vol: 0.9, smooth: 0.01, pan: -0.4, ..p_start };
+
+        for channels in [1, 2] {
+            let engine = || AudioEngine::new(48000.0, test_program(), Arc::new(SyncState::new()));
+            let (mut scalar, mut vectorized) = (engine(), engine());
+            // Not a multiple of the lane width, so the tail runs the scalar loop
+            let mut expected = vec![0.0f32; 1027 * channels];
+            let mut actual = expected.clone();
+
+            for _ in 0..20 {
+                scalar.render_isochronic(&mut expected, channels, &p_start, &p_end, false);
+                vectorized.render_isochronic(&mut actual, channels, &p_start, &p_end, true);
+                for (i, (a, e)) in actual.iter().zip(&expected).enumerate() {
+                    assert!((a - e).abs() <= f32::EPSILON, "{channels} channels, sample {i}: {a} vs {e}");
+                }
+            }
+            assert!((vectorized.pulse_phase - scalar.pulse_phase).abs() < 1e-9);
+            assert!((vectorized.left_phase - scalar.left_phase).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn limiter_keeps_output_in_range() {
+        let input = [-10.0, -1.5, -0.5, 0.0, 0.9, 0.95, 0.99, 1.0, 1.2, 4.0, 100.0];
+        for threshold in [0.5, LIMITER_THRESHOLD, 1.0] {
+            let mut output = input;
+            soft_limit(&mut output, threshold);
+
+            for (&out, &sample) in output.iter().zip(&input) {
+                assert!((-1.0..=1.0).contains(&out), "{threshold}: {sample} -> {out}");
+                if sample.abs() <= threshold {
+                    assert_eq!(out, sample);
+                }
+            }
+            // Still monotonic, so louder input never comes out quieter
+            assert!(output.windows(2).all(|w| w[0] <= w[1]), "{output:?}");
+        }
+    }
+
+    #[test]
+    fn raised_cosine_envelope_shape() {
+        let envelope = Envelope::new(0.5, 10.0, 0.0).with_shape(EnvelopeShape::RaisedCosine);
+        assert!(envelope.value(0.0).abs() < 1e-12);
+        assert!((envelope.value(0.125) - 0.5).abs() < 1e-12);
+        assert!((envelope.value(0.25) - 1.0).abs() < 1e-12);
+        assert!((envelope.value(0.375) - 0.5).abs() < 1e-12);
+        assert_eq!(envelope.value(0.5), 0.0);
+        assert_eq!(envelope.value(0.9), 0.0);
+
+        // Selected by the program, and the same on the vectorized path
+        let program = Program::parse("00:00 freq=10 tone=250 vol=1 duty=0.5 env=cosine").unwrap();
+        let p = program.params_at(0.0);
+        assert_eq!(p.env, EnvelopeShape::RaisedCosine);
+        let engine = || AudioEngine::new(48000.0, test_program(), Arc::new(SyncState::new()));
+        let (mut scalar, mut vectorized) = (engine(), engine());
+        let mut expected = vec![0.0f32; 9600];
+        let mut actual = expected.clone();
+        scalar.render_isochronic(&mut expected, 2, &p, &p, false);
+        vectorized.render_isochronic(&mut actual, 2, &p, &p, true);
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() <= f32::EPSILON);
+        }
+
+        // A quarter of the way into the on part, the pulse is at half height
+        let frame = (0.0125 * 48000.0) as usize;
+        let carrier = (TAU * 250.0 * frame as f64 / 48000.0).sin() * FRAC_1_SQRT_2;
+        assert!((f64::from(expected[frame * 2]) - 0.5 * carrier).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sine_table_matches_sin() {
+        for i in 0..=100_000 {
+            let phase = f64::from(i) / 100_000.0 * 3.0 - 1.0;
+            let error = (sine_lut(phase) - (phase * TAU).sin()).abs();
+            assert!(error < 1e-4, "phase {phase}: error {error}");
+        }
+    }
+
+    #[test]
+    fn intensity_scales_gain() {
+        let params = Params { tone: 1000.0, vol: 0.8, ..Params::default() };
+        let program = Program::constant(params, Settings { binaural: true, ..Settings::default() });
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let sync = Arc::new(SyncState::new());
+        sync.set_intensity(0.5);
+        let mut engine = AudioEngine::new(48000.0, live, sync.clone());
+
+        let mut buffer = vec![0.0f32; 9600];
+        engine.process(&mut buffer, 2);
+        let peak = buffer.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!((peak - 0.4).abs() < 1e-3, "{peak}");
+
+        // Changes ramp across the next buffer and then hold
+        sync.set_intensity(1.0);
+        engine.process(&mut buffer, 2);
+        let (first, last) = (buffer[..200].iter(), buffer[9400..].iter());
+        assert!(first.fold(0.0f32, |m, s| m.max(s.abs())) < 0.45);
+        assert!(last.fold(0.0f32, |m, s| m.max(s.abs())) > 0.79);
+
+        sync.set_intensity(7.0);
+        assert_eq!(sync.intensity(), 1.0);
+    }
+
+    #[test]
+    fn over_unity_gain_is_clamped() {
+        let params = Params { tone: 1000.0, vol: 0.8, ..Params::default() };
+        let program = Program::constant(params, Settings { binaural: true, ..Settings::default() });
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        // Without limiting, so the clamped level measures as 1
+        let options = EngineOptions { gain_db: 12.0, max_gain: Some(1.0), ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        let mut buffer = vec![0.0f32; 9600];
+        engine.process(&mut buffer, 2);
+        let peak = buffer.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak <= 1.0 && peak > 0.999, "{peak}");
+        assert_eq!(engine.clip_warned_at, Some(0));
+
+        // Warnings are throttled
+        engine.process(&mut buffer, 2);
+        assert_eq!(engine.clip_warned_at, Some(0));
+
+        // Gain within range is untouched and never warns
+        let options = EngineOptions { gain_db: -6.0, ..EngineOptions::default() };
+        let mut quiet = AudioEngine::new(48000.0, engine.program.clone(), Arc::new(SyncState::new()))
+            .with_options(options);
+        quiet.process(&mut buffer, 2);
+        let peak = buffer.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!((peak - 0.8 * 0.501).abs() < 1e-3, "{peak}");
+        assert_eq!(quiet.clip_warned_at, None);
+    }
+
+    #[test]
+    fn pulse_tail_fades_to_silence() {
+        for (freq, duty) in [(10.0, 0.5), (40.0, 0.05), (3.0, 0.99)] {
+            let params = Params { freq, duty, tone: 1000.0, vol: 1.0, ..Params::default() };
+            let program = Program::constant(params, Settings::default());
+            let live = Arc::new(LiveProgram::new(Arc::new(program)));
+            let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+
+            let mut buffer = vec![0.0f32; 96000];
+            engine.process(&mut buffer, 2);
+            let left: Vec<f32> = buffer.iter().step_by(2).copied().collect();
+
+            // The sample before each silent gap is already at the end of the release
+            let mut tails = 0;
+            for i in 1..left.len() - 8 {
+                if left[i - 1] != 0.0 && left[i..i + 8].iter().all(|&s| s == 0.0) {
+                    assert!(left[i - 1].abs() < 1e-3, "{freq} Hz duty {duty}: {}", left[i - 1]);
+                    tails += 1;
+                }
+            }
+            assert!(tails > 0);
+        }
+    }
+
+    #[test]
+    fn buffer_size_tracks_driver_changes() {
+        let sync = Arc::new(SyncState::new());
+        let mut engine = AudioEngine::new(48000.0, test_program(), sync.clone());
+        let mut run = |frames: usize, times: usize| {
+            let mut buffer = vec![0.0f32; frames * 2];
+            for _ in 0..times {
+                engine.process(&mut buffer, 2);
+            }
+            sync.buffer_frames.load(Ordering::Acquire)
+        };
+
+        assert_eq!(run(512, 10), 512);
+        // Small jitter is ignored
+        assert_eq!(run(500, 100), 512);
+        // A larger block counts immediately
+        assert_eq!(run(1024, 1), 1024);
+        // A smaller one only after it persists for a window
+        assert_eq!(run(256, BUFFER_WINDOW as usize - 1), 1024);
+        assert_eq!(run(256, 1), 256);
+    }
+
+    #[test]
+    fn seek_into_pulse_fades_in() {
+        let params = Params { freq: 1.0, duty: 0.9, tone: 250.0, vol: 1.0, ..Params::default() };
+        let program = Program::constant(params, Settings::default());
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        // Without limiting, so full scale measures as 1
+        let options = EngineOptions { max_gain: Some(1.0), ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        // A quarter period in, the envelope is fully open and the carrier at its peak
+        engine.seek(0.25);
+        let mut buffer = vec![0.0f32; 960];
+        engine.process(&mut buffer, 2);
+
+        assert_eq!(buffer[0], 0.0);
+        let fade = (SEEK_FADE_SECS * 48000.0) as usize;
+        for (i, frame) in buffer.chunks_exact(2).take(fade).enumerate() {
+            assert!(frame[0].abs() <= i as f32 / fade as f32 + 1e-6);
+        }
+        // Full level, 3 dB down for the centered pan, once the fade is over
+        let peak = buffer[fade * 2..].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak > 0.99 * std::f32::consts::FRAC_1_SQRT_2, "{peak}");
+    }
+
+    #[test]
+    fn carrier_starts_at_configured_phase() {
+        let params = Params { vol: 1.0, ..Params::default() };
+        let program = Program::constant(params, Settings { binaural: true, ..Settings::default() });
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+
+        for phase in [0.0, 0.25, 0.6] {
+            // Without limiting, so full scale measures as 1
+            let options = EngineOptions { carrier_phase: phase, max_gain: Some(1.0), ..EngineOptions::default() };
+            let mut engine = AudioEngine::new(48000.0, live.clone(), Arc::new(SyncState::new())).with_options(options);
+
+            let mut buffer = vec![0.0f32; 64];
+            engine.process(&mut buffer, 2);
+            let expected = (phase * TAU).sin() as f32;
+            assert!((buffer[0] - expected).abs() < 1e-6, "{phase}: {}", buffer[0]);
+            assert!((buffer[1] - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn clicks_mark_each_keyframe() {
+        let program = Program::parse("00:00 vol=0\n00:01 freq=12\n00:02.5 freq=8").unwrap();
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let options = EngineOptions { keyframe_clicks: true, ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        // Silent program, so every audible run is a click
+        let mut onsets = Vec::new();
+        let mut buffer = vec![0.0f32; 2000];
+        let mut was_audible = false;
+        for n in 0..150 {
+            engine.process(&mut buffer, 2);
+            for (i, frame) in buffer.chunks_exact(2).enumerate() {
+                let audible = frame[0] != 0.0;
+                if audible && !was_audible {
+                    onsets.push(n * 1000 + i);
+                }
+                was_audible = audible;
+            }
+        }
+
+        assert_eq!(onsets, [0, 48000, 120000]);
+    }
+
+    #[test]
+    fn invert_duty_swaps_on_and_off_time() {
+        let on_fraction = |source: &str| {
+            let program = Program::parse(source).unwrap();
+            let live = Arc::new(LiveProgram::new(Arc::new(program)));
+            let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+
+            let mut buffer = vec![0.0f32; 96000];
+            engine.process(&mut buffer, 2);
+            let on = buffer.chunks_exact(2).filter(|f| f[0] != 0.0).count();
+            on as f64 / 48000.0
+        };
+
+        let normal = on_fraction("00:00 freq=10 vol=1 duty=0.2");
+        let inverted = on_fraction("00:00 freq=10 vol=1 duty=0.2 invert_duty");
+        assert!((normal - 0.2).abs() < 0.01, "{normal}");
+        assert!((inverted - 0.8).abs() < 0.01, "{inverted}");
+    }
+
+    #[test]
+    fn autopan_oscillates_channel_gains() {
+        let program = Program::parse("00:00 freq=40 duty=0.9 vol=1 autopan=2:0.5").unwrap();
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+
+        let mut buffer = vec![0.0f32; 96000];
+        engine.process(&mut buffer, 2);
+
+        // Pan position from the channel ratio, wherever the pulse is audible
+        let pans: Vec<f64> = buffer
+            .chunks_exact(2)
+            .filter(|f| f[0].abs() > 1e-3 && f[1].abs() > 1e-3)
+            .map(|f| f64::from(f[1] / f[0]).atan() / FRAC_PI_4 - 1.0)
+            .collect();
+
+        // 2 Hz over one second: the pan crosses center at 0.25, 0.5 and 0.75 s
+        let crossings = pans.windows(2).filter(|w| w[0].signum() != w[1].signum()).count();
+        assert_eq!(crossings, 3);
+
+        let widest = pans.iter().fold(0.0f64, |m, p| m.max(p.abs()));
+        assert!((widest - 0.5).abs() < 0.01, "{widest}");
+    }
+
+    #[test]
+    fn pan_keeps_constant_power() {
+        let render = |pan: f64| {
+            let program = Program::parse(&format!("00:00 freq=40 duty=0.9 vol=0.5 pan={pan}")).unwrap();
+            let live = Arc::new(LiveProgram::new(Arc::new(program)));
+            let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+            let mut buffer = vec![0.0f32; 9600];
+            engine.process(&mut buffer, 2);
+            buffer
+        };
+
+        let center = render(0.0);
+        assert!(center.iter().any(|s| s.abs() > 1e-3));
+        for pan in [-1.0, -0.5, -0.25, 0.25, 0.75, 1.0] {
+            let buffer = render(pan);
+            for (frame, reference) in buffer.chunks_exact(2).zip(center.chunks_exact(2)) {
+                let power = frame[0] * frame[0] + frame[1] * frame[1];
+                let expected = reference[0] * reference[0] + reference[1] * reference[1];
+                assert!((power - expected).abs() < 1e-5, "pan {pan}: {frame:?} vs {reference:?}");
+            }
+        }
+
+        // Panning left favors the left channel
+        let left = render(-0.25);
+        assert!(left.chunks_exact(2).all(|f| f[1].abs() <= f[0].abs()));
+        let (l, r) = pan_gains(0.0);
+        assert!((l - FRAC_1_SQRT_2).abs() < 1e-12 && (r - FRAC_1_SQRT_2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hard_pan_keeps_unity_gain() {
+        fn render(source: &str, channels: usize) -> Vec<f32> {
+            let program = Program::parse(source).unwrap();
+            let live = Arc::new(LiveProgram::new(Arc::new(program)));
+            // Threshold at full scale, so only levels past it get touched
+            let options = EngineOptions { max_gain: Some(1.0), ..EngineOptions::default() };
+            let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+            let mut buffer = vec![0.0f32; 48000 * channels];
+            engine.process(&mut buffer, channels);
+            buffer
+        }
+
+        let mono = render("00:00 freq=40 duty=0.9 vol=1", 1);
+        for (pan, loud) in [(-1.0, 0), (1.0, 1)] {
+            let stereo = render(&format!("00:00 freq=40 duty=0.9 vol=1 pan={pan}"), 2);
+            // A hard pan is the mono signal on one side, never louder
+            for (frame, &sample) in stereo.chunks(2).zip(&mono) {
+                assert!(frame.iter().all(|s| s.abs() <= 1.0), "{pan}: {frame:?}");
+                assert!((frame[loud] - sample).abs() < 1e-6, "{pan}: {} vs {sample}", frame[loud]);
+                assert!(frame[1 - loud].abs() < 1e-6, "{pan}: {frame:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn sample_rate_falls_back_when_unsupported() {
+        let supported = [44100..=48000, 88200..=96000];
+        assert_eq!(select_sample_rate(96000, &supported), Some(96000));
+        assert_eq!(select_sample_rate(44100, &supported), Some(44100));
+        assert_eq!(select_sample_rate(192000, &supported), None);
+        assert_eq!(select_sample_rate(48000, &[]), None);
+    }
+
+    #[test]
+    fn silent_buffers_take_fast_path() {
+        let program = Program::parse("00:00 freq=10 vol=0").unwrap();
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+
+        let mut buffer = vec![1.0f32; 960];
+        engine.process(&mut buffer, 2);
+        assert!(buffer.iter().all(|&s| s == 0.0));
+
+        // The carrier was skipped, but the pulse kept running for the visuals
+        assert_eq!(engine.left_phase, 0.0);
+        assert!((engine.pulse_phase - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn av_offset_rewinds_visual_phase() {
+        let sync = SyncState::new();
+        sync.sample_rate.store(48000, Ordering::Release);
+        sync.buffer_frames.store(480, Ordering::Release);
+        sync.phase_bits.store(0.5f64.to_bits(), Ordering::Release);
+
+        // 10 ms of buffer latency at 10 Hz
+        assert!((sync.visual_phase(10.0) - 0.4).abs() < 1e-9);
+
+        sync.av_offset_ms.store(20, Ordering::Relaxed);
+        assert!((sync.visual_phase(10.0) - 0.2).abs() < 1e-9);
+        sync.av_offset_ms.store(-10, Ordering::Relaxed);
+        assert!((sync.visual_phase(10.0) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn random_tone_offset_is_bounded_and_seeded() {
+        for seed in 0..1000 {
+            let offset = random_tone_offset(5.0, seed);
+            assert!(offset.abs() <= 5.0);
+            assert_eq!(offset, random_tone_offset(5.0, seed));
+        }
+        assert_ne!(random_tone_offset(5.0, 1), random_tone_offset(5.0, 2));
+        assert_eq!(random_tone_offset(0.0, 42), 0.0);
+    }
+
+    #[test]
+    fn binaural_tone_offset_shifts_both_channels() {
+        let program = Program::constant(
+            Params { freq: 10.0, tone: 200.0, ..Params::default() },
+            Settings { binaural: true, ..Settings::default() },
+        );
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let options = EngineOptions { tone_offset: 40.0, ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+
+        let mut buffer = vec![0.0f32; 2 * 4800];
+        engine.process(&mut buffer, 2);
+
+        // 0.1 s of a 240 Hz left tone is exactly 24 cycles
+        assert!(engine.left_phase < 1e-6 || engine.left_phase > 1.0 - 1e-6);
+        // Right is 250 Hz: 25 cycles, beat frequency unchanged
+        assert!(engine.right_phase < 1e-6 || engine.right_phase > 1.0 - 1e-6);
+    }
+
+    #[test]
+    fn verify_rendered_wav() {
+        let path = std::env::temp_dir().join(format!("isochronator_verify_test_{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        // Render one second of the engine's output
+        let sync = Arc::new(SyncState::new());
+        let mut engine = AudioEngine::new(48000.0, test_program(), sync);
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        let mut buffer = vec![0.0f32; 960]; // 480 frames
+        for _ in 0..100 {
+            engine.process(&mut buffer, 2);
+            for &s in &buffer {
+                writer.write_sample((s * f32::from(i16::MAX)) as i16).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+
+        let expected = RenderSpec {
+            sample_rate: 48000,
+            channels: 2,
+            duration: 1.0,
+        };
+        let report = verify_wav(&path, &expected).unwrap();
+        assert!(report.passed(), "{:?}", report.problems);
+        assert_eq!(report.sample_rate, 48000);
+        assert_eq!(report.channels, 2);
+        assert!((report.duration - 1.0).abs() < 1e-9);
+
+        let wrong = RenderSpec { duration: 2.0, ..expected };
+        assert_eq!(verify_wav(&path, &wrong).unwrap().problems.len(), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
     #[test]
     fn sync_state_latency_compensation() {
         let sync = SyncState::new();
@@ -368,6 +2424,147 @@ mod tests {
         // Visual phase at 10 Hz should be rewound by latency
         let phase = sync.visual_phase(10.0);
         // 10 Hz * (1024/48000) seconds = ~0.213 cycles offset
-        assert!(phase >= 0.0 && phase < 1.0);
+        assert!((0.0..1.0).contains(&phase));
+    }
+
+    #[test]
+    fn device_names_match_ignoring_case() {
+        let names = ["HDMI Output", "Headphones", "USB Headphones Amp"];
+
+        assert_eq!(match_device_name(&names, "headphones"), Some(1));
+        assert_eq!(match_device_name(&names, "HDMI"), Some(0));
+        assert_eq!(match_device_name(&names, "usb"), Some(2));
+        assert_eq!(match_device_name(&names, "speakers"), None);
+    }
+
+    #[test]
+    fn render_to_wav_matches_live_engine() {
+        let path = std::env::temp_dir().join(format!("isochronator_render_test_{}.wav", std::process::id()));
+        let program = Program::parse("00:00 freq=10 vol=0.2\n00:01 vol=0.8 freq=6 >linear").unwrap();
+        let program = Arc::new(program);
+
+        let frames = render_to_wav(program.clone(), &path, 44100, 1.5, EngineOptions::default()).unwrap();
+        assert_eq!(frames, 66150);
+        assert_eq!(u64::from(hound::WavReader::open(&path).unwrap().duration()), frames);
+
+        let expected = RenderSpec { sample_rate: 44100, channels: 2, duration: 1.5 };
+        assert!(verify_wav(&path, &expected).unwrap().passed());
+
+        // Same engine, same buffer size, written the same way
+        let live = Arc::new(LiveProgram::new(program));
+        let mut engine = AudioEngine::new(44100.0, live, Arc::new(SyncState::new()));
+        let mut buffer = vec![0.0f32; RENDER_CHUNK_FRAMES * 2];
+        let mut samples = hound::WavReader::open(&path).unwrap().into_samples::<i16>();
+        let mut remaining = frames as usize;
+        while remaining > 0 {
+            let chunk = &mut buffer[..remaining.min(RENDER_CHUNK_FRAMES) * 2];
+            engine.process(chunk, 2);
+            for &s in chunk.iter() {
+                assert_eq!(samples.next().unwrap().unwrap(), (s * f32::from(i16::MAX)) as i16);
+            }
+            remaining -= chunk.len() / 2;
+        }
+        assert!(samples.next().is_none());
+
+        let endless = Arc::new(Program::parse("00:00 freq=10").unwrap());
+        assert!(render_to_wav(endless, &path, 44100, f64::INFINITY, EngineOptions::default()).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn monaural_beats_mix_both_tones_into_every_channel() {
+        let program = Program::parse("00:00 freq=2 tone=200 vol=0.5 monaural").unwrap();
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+
+        let mut buffer = vec![0.0f32; 48000];
+        engine.process(&mut buffer, 2);
+        assert!(buffer.chunks_exact(2).all(|f| f[0] == f[1]));
+
+        // The 2 Hz beat cancels the tones at 0.25 s and lines them up at 0.5 s
+        let peak = |from: f64, to: f64| {
+            buffer
+                .chunks_exact(2)
+                .skip((from * 48000.0) as usize)
+                .take(((to - from) * 48000.0) as usize)
+                .fold(0.0f32, |m, f| m.max(f[0].abs()))
+        };
+        assert!(peak(0.24, 0.26) < 0.05, "{}", peak(0.24, 0.26));
+        assert!(peak(0.49, 0.51) > 0.45, "{}", peak(0.49, 0.51));
+
+        // Forcing a mode overrides the setting
+        let program = Program::parse("00:00 freq=2 tone=200 vol=0.5 monaural").unwrap();
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let options = EngineOptions { binaural: Some(true), ..EngineOptions::default() };
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new())).with_options(options);
+        engine.process(&mut buffer, 2);
+        assert!(buffer.chunks_exact(2).any(|f| f[0] != f[1]));
+    }
+
+    #[test]
+    fn pink_noise_mixes_under_the_tone() {
+        let render = |source: &str| {
+            let live = Arc::new(LiveProgram::new(Arc::new(Program::parse(source).unwrap())));
+            let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+            let mut buffer = vec![0.0f32; 48000];
+            engine.process(&mut buffer, 2);
+            buffer
+        };
+
+        // noise=0 is the same as no noise at all, down to the bit
+        let plain = render("00:00 freq=2 duty=0.5 vol=0.5");
+        assert_eq!(render("00:00 freq=2 duty=0.5 vol=0.5 noise=0"), plain);
+
+        // The gap after the pulse is silent without noise, and not with it
+        let noisy = render("00:00 freq=2 duty=0.5 vol=0.5 noise=0.5");
+        let gap = 2 * 13000..2 * 23000;
+        assert!(plain[gap.clone()].iter().all(|&s| s == 0.0));
+        let peak = noisy[gap.clone()].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak > 0.01 && peak <= 0.25, "{peak}");
+        assert!(noisy[gap].chunks_exact(2).all(|f| f[0] == f[1]));
+    }
+
+    #[test]
+    fn pink_noise_stays_in_range() {
+        let mut noise = PinkNoise::new();
+        let samples: Vec<f64> = (0..480_000).map(|_| noise.next()).collect();
+        assert!(samples.iter().all(|s| s.abs() <= 1.0));
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!(mean.abs() < 0.05, "{mean}");
+    }
+
+    #[test]
+    fn render_raw_writes_interleaved_f32() {
+        let program = Arc::new(Program::parse("00:00 freq=10 vol=0.5").unwrap());
+
+        let mut out = Vec::new();
+        let frames = render_raw(program.clone(), &mut out, 48000, Some(0.5), EngineOptions::default()).unwrap();
+        assert_eq!(frames, 24000);
+        assert_eq!(out.len(), 24000 * 2 * size_of::<f32>());
+
+        // The same samples the engine produces, byte for byte
+        let live = Arc::new(LiveProgram::new(program.clone()));
+        let mut engine = AudioEngine::new(48000.0, live, Arc::new(SyncState::new()));
+        let mut buffer = vec![0.0f32; RENDER_CHUNK_FRAMES * 2];
+        engine.process(&mut buffer, 2);
+        let expected: Vec<u8> = buffer.iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(&out[..expected.len()], expected.as_slice());
+
+        // An endless render stops quietly when the reader goes away
+        struct ClosesAfter(usize);
+        impl std::io::Write for ClosesAfter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.0 == 0 {
+                    return Err(std::io::ErrorKind::BrokenPipe.into());
+                }
+                self.0 -= 1;
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let frames = render_raw(program, &mut ClosesAfter(3), 48000, None, EngineOptions::default()).unwrap();
+        assert_eq!(frames, 3 * RENDER_CHUNK_FRAMES as u64);
     }
 }
\ No newline at end of file
@@ -1,22 +1,31 @@
 #![windows_subsystem = "windows"]
 #![forbid(unsafe_code)]
+#![feature(portable_simd)]
 
 use anyhow::{Context, Result};
 use argh::FromArgs;
 use bytemuck::{Pod, Zeroable};
 use eframe::egui;
 use env_logger::Env;
-use log::info;
-use std::path::PathBuf;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 mod audio;
+#[cfg(feature = "midi")]
+mod midi;
 mod program;
+mod repl;
 mod visuals;
+#[cfg(feature = "watch")]
+mod watch;
 
-use program::{Params, Program, Settings};
+use audio::SyncState;
+use program::{LiveProgram, Params, Program, Settings};
 
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Color
@@ -24,7 +33,7 @@ use program::{Params, Program, Settings};
 
 /// RGBA color in sRGB color space.
 #[repr(C)]
-#[derive(Default, Copy, Clone, Debug, Pod, Zeroable, PartialEq, Eq)]
+#[derive(Default, Copy, Clone, Debug, Pod, Zeroable, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -79,6 +88,17 @@ impl Color {
         }
     }
 
+    /// Convert linear RGB back to an opaque sRGB color.
+    #[inline]
+    pub fn from_linear(rgb: [f64; 3]) -> Self {
+        Self {
+            r: Self::linear_to_srgb(rgb[0]),
+            g: Self::linear_to_srgb(rgb[1]),
+            b: Self::linear_to_srgb(rgb[2]),
+            a: 255,
+        }
+    }
+
     /// Convert to linear RGB for GPU operations.
     #[inline]
     pub fn to_linear(self) -> [f64; 3] {
@@ -95,8 +115,26 @@ impl FromStr for Color {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let s = s.strip_prefix('#').unwrap_or(s);
+
+        // CSS-style shorthand: #FA0 is #FFAA00
+        if s.len() == 3 {
+            let mut digits = s.chars().map(|c| c.to_digit(16).map(|d| d as u8 * 17));
+            let mut next = |name: &str| {
+                digits
+                    .next()
+                    .flatten()
+                    .ok_or_else(|| format!("{name}: invalid hex digit"))
+            };
+            return Ok(Self {
+                r: next("red")?,
+                g: next("green")?,
+                b: next("blue")?,
+                a: 255,
+            });
+        }
+
         if s.len() != 6 {
-            return Err("expected #RRGGBB format".into());
+            return Err("expected #RRGGBB or #RGB format".into());
         }
         Ok(Self {
             r: u8::from_str_radix(&s[0..2], 16).map_err(|e| format!("red: {e}"))?,
@@ -121,6 +159,264 @@ struct Args {
     /// run profiling workload for PGO optimization
     #[argh(switch)]
     profile: bool,
+
+    /// treat program warnings as errors
+    #[argh(switch)]
+    strict: bool,
+
+    /// send MIDI notes in sync with the pulses (requires the `midi` feature)
+    #[argh(switch)]
+    midi_out: bool,
+
+    /// reload the program file whenever it is saved, crossfading to the new
+    /// version at the current time (requires the `watch` feature)
+    #[argh(switch)]
+    watch: bool,
+
+    /// render the program's audio to this 16-bit stereo WAV file and exit;
+    /// programs without an end need --duration. With '-', stream raw interleaved
+    /// stereo f32 (little-endian) to stdout until --duration, the program's end
+    /// or the pipe closes
+    #[argh(option)]
+    render: Option<PathBuf>,
+
+    /// check that a rendered WAV file matches the program's duration and format
+    #[argh(option)]
+    verify: Option<PathBuf>,
+
+    /// play --freq, --tone, --vol and --duty as audio and change them with commands typed on stdin
+    #[argh(switch)]
+    repl: bool,
+
+    /// list the available audio output devices and exit
+    #[argh(switch)]
+    list_devices: bool,
+
+    /// play on the audio output device with this name (see --list-devices)
+    #[argh(option)]
+    device: Option<String>,
+
+    /// print the pulse period and timing at the start of the program and exit
+    #[argh(switch)]
+    show_timing: bool,
+
+    /// print an ASCII plot of one pulse envelope for --duty and --ramp and exit
+    #[argh(switch)]
+    show_envelope: bool,
+
+    /// duty cycle for --show-envelope, --export-program and --repl (default: 0.5)
+    #[argh(option, default = "0.5")]
+    duty: f64,
+
+    /// write a one-keyframe program from --freq, --tone, --vol, --duty, --on, --off and --binaural, then exit
+    #[argh(option)]
+    export_program: Option<PathBuf>,
+
+    /// pulse frequency in Hz for --export-program and --repl (default: 10)
+    #[argh(option, default = "10.0")]
+    freq: f64,
+
+    /// carrier tone in Hz for --export-program and --repl (default: 200)
+    #[argh(option, default = "200.0")]
+    tone: f32,
+
+    /// volume from 0 to 1 for --export-program and --repl (default: 0.5)
+    #[argh(option, default = "0.5")]
+    vol: f32,
+
+    /// pulse on color for --export-program (default: #FFFFFF)
+    #[argh(option, default = "Color::WHITE")]
+    on: Color,
+
+    /// pulse off color for --export-program (default: #000000)
+    #[argh(option, default = "Color::BLACK")]
+    off: Color,
+
+    /// use binaural beats for --export-program and --repl
+    #[argh(switch)]
+    binaural: bool,
+
+    /// edge length for --show-envelope as a fraction of the period (default: automatic)
+    #[argh(option)]
+    ramp: Option<f64>,
+
+    /// sample rate for rendered files in Hz (default: 48000)
+    #[argh(option)]
+    sample_rate: Option<u32>,
+
+    /// render the program as binaural beats, using freq as the beat and tone as the base
+    #[argh(switch)]
+    as_binaural: bool,
+
+    /// render the program as isochronic pulses even if it sets binaural
+    #[argh(switch)]
+    as_isochronic: bool,
+
+    /// mix a short click into the audio at each keyframe
+    #[argh(switch)]
+    keyframe_clicks: bool,
+
+    /// limit how fast the pulse frequency may change, in Hz per second
+    #[argh(option)]
+    max_freq_slew: Option<f64>,
+
+    /// snap the pulse frequency to the nearest multiple of this many Hz
+    #[argh(option)]
+    freq_grid: Option<f64>,
+
+    /// ignore the program and play a steady sine at this frequency until stopped
+    #[argh(option)]
+    test_tone: Option<f32>,
+
+    /// start the program at this wall-clock time (Unix milliseconds), to flash in sync across machines
+    #[argh(option)]
+    epoch: Option<u64>,
+
+    /// hold the start until the next whole wall-clock second, so sessions started
+    /// within the same second begin together
+    #[argh(switch)]
+    sync_start: bool,
+
+    /// shape volume into gain: linear, squared or cubic (default: linear)
+    #[argh(option, default = "audio::AmplitudeCurve::Linear")]
+    amplitude_curve: audio::AmplitudeCurve,
+
+    /// gain law of the fades on seek, resume and exit: linear or equalpower
+    /// (default: linear). Preview changes, --watch reloads and live edits ramp
+    /// the parameters linearly instead and don't use it
+    #[argh(option, default = "audio::CrossfadeLaw::Linear")]
+    crossfade_law: audio::CrossfadeLaw,
+
+    /// overall strength from 0 to 1, scaling both volume and flash brightness; Up and Down adjust it live (default: 1)
+    #[argh(option, default = "1.0")]
+    intensity: f64,
+
+    /// output gain in dB on top of the program volume; clamped so it never clips (default: 0)
+    #[argh(option, default = "0.0")]
+    gain_db: f32,
+
+    /// level from 0 to 1 above which the output is softly limited (default: 0.95)
+    #[argh(option)]
+    max_gain: Option<f32>,
+
+    /// seconds to fade the audio out and the flash to the off color when the
+    /// session ends or Escape is pressed (default: 1)
+    #[argh(option, default = "audio::DEFAULT_EXIT_FADE_SECS")]
+    fade_out: f64,
+
+    /// starting phase of the carrier tone, from 0 to 1 (default: 0)
+    #[argh(option, default = "0.0")]
+    carrier_phase: f64,
+
+    /// randomly offset the binaural base tone within ±this many Hz
+    #[argh(option)]
+    random_base: Option<f32>,
+
+    /// seed for randomized options (default: time-based)
+    #[argh(option)]
+    seed: Option<u64>,
+
+    /// leave out keyframes with these comma-separated tags
+    #[argh(option)]
+    skip_tags: Option<String>,
+
+    /// play a finite program this many times, with a short silent gap between plays
+    #[argh(option)]
+    repeat_count: Option<u32>,
+
+    /// cap the flash brightness of each color channel, from 0 to 1 in linear light
+    #[argh(option)]
+    max_brightness: Option<f64>,
+
+    /// approach --max-brightness smoothly instead of clipping at it
+    #[argh(switch)]
+    brightness_knee: bool,
+
+    /// keep the display from sleeping during the session
+    #[argh(switch)]
+    prevent_sleep: bool,
+
+    /// log each keyframe as playback passes it
+    #[argh(switch)]
+    log_keyframes: bool,
+
+    /// print a readable breakdown of the program's settings and keyframes
+    #[argh(switch)]
+    explain: bool,
+
+    /// write the program as JSON to this file and exit
+    #[argh(option)]
+    export_json: Option<PathBuf>,
+
+    /// print the program as a Rust `const` with this name, for pasting into tests, and exit
+    #[argh(option)]
+    export_rust: Option<String>,
+
+    /// render the flash frames of a finite program as a PNG sequence into this directory
+    #[argh(option)]
+    render_video: Option<PathBuf>,
+
+    /// write the flash brightness of a finite program as time,brightness CSV rows to this file
+    #[argh(option)]
+    export_brightness: Option<PathBuf>,
+
+    /// frame rate for --render-video and --export-brightness (default: 30)
+    #[argh(option, default = "30")]
+    fps: u32,
+
+    /// end the session after this many seconds (default: when the program ends)
+    #[argh(option)]
+    duration: Option<f64>,
+
+    /// play audio only, without opening a window (Ctrl-C fades out and exits)
+    #[argh(switch)]
+    no_window: bool,
+
+    /// play programs that flash at 15-25 Hz, the rates most likely to trigger
+    /// photosensitive seizures, instead of refusing to start; also accepts
+    /// such flashing from --watch reloads and live edits
+    #[argh(switch)]
+    allow_unsafe_flicker: bool,
+
+    /// hold the final frame when the program ends until a key is pressed
+    #[argh(switch)]
+    freeze_on_exit: bool,
+
+    /// keep the flash area at 16:9 and letterbox the rest of the window
+    #[argh(switch)]
+    lock_aspect: bool,
+
+    /// color of the letterbox bars with --lock-aspect, as #RRGGBB (default: black)
+    #[argh(option)]
+    letterbox_color: Option<Color>,
+
+    /// delay the visuals by this many milliseconds (negative: earlier; adjust live with [ and ])
+    #[argh(option, default = "0")]
+    av_offset: i32,
+
+    /// draw the raw and latency-compensated pulse phase as moving bars (toggle with F3)
+    #[argh(switch)]
+    visualize_sync: bool,
+
+    /// show elapsed time, frequency and volume in the corner (toggle with F1)
+    #[argh(switch)]
+    hud: bool,
+
+    /// show the flash in a borderless, always-on-top window that clicks pass through
+    #[argh(switch)]
+    overlay: bool,
+
+    /// opacity of the --overlay window, from 0 to 1 (default: 0.3)
+    #[argh(option, default = "0.3")]
+    overlay_opacity: f64,
+
+    /// extrapolate the pulse phase between audio buffers to reduce flash jitter
+    #[argh(switch)]
+    smooth_phase: bool,
+
+    /// color shown while paused, as #RRGGBB (default: the off color)
+    #[argh(option)]
+    idle_color: Option<Color>,
 }
 
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
@@ -137,6 +433,91 @@ const DEFAULT_PROGRAM: &str = r#"
 02:00 vol=0.0 >linear
 "#;
 
+/// Minimum time between preview updates while a slider is dragged.
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Ramp time from the previous to the new preview parameters, in seconds.
+const PREVIEW_RAMP_SECS: f64 = 0.03;
+
+/// Rate limiter that applies the latest pending change at most once per interval.
+struct Throttle {
+    interval: Duration,
+    last_applied: Option<Instant>,
+    pending: bool,
+}
+
+impl Throttle {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_applied: None,
+            pending: false,
+        }
+    }
+
+    /// Record that a new value is waiting to be applied.
+    fn mark(&mut self) {
+        self.pending = true;
+    }
+
+    /// Whether the pending value should be applied now.
+    fn poll(&mut self, now: Instant) -> bool {
+        if !self.pending {
+            return false;
+        }
+        if let Some(last) = self.last_applied
+            && now.duration_since(last) < self.interval
+        {
+            return false;
+        }
+        self.pending = false;
+        self.last_applied = Some(now);
+        true
+    }
+}
+
+/// In-process audio preview that follows the simple mode controls.
+struct Preview {
+    _stream: cpal::Stream,
+    program: Arc<LiveProgram>,
+    sync: Arc<SyncState>,
+    throttle: Throttle,
+    applied: (Params, Settings),
+}
+
+impl Preview {
+    fn start(params: Params, settings: Settings) -> Result<Self> {
+        let program = Arc::new(LiveProgram::new(Arc::new(Program::constant(params, settings))));
+        let sync = Arc::new(SyncState::new());
+        let stream = audio::start(program.clone(), sync.clone(), audio::EngineOptions::default(), None)?;
+
+        Ok(Self {
+            _stream: stream,
+            program,
+            sync,
+            throttle: Throttle::new(PREVIEW_INTERVAL),
+            applied: (params, settings),
+        })
+    }
+
+    /// Queue new parameters; returns true while an update is still pending.
+    fn update(&mut self, params: Params, settings: Settings) -> bool {
+        if (params, settings) != self.applied {
+            self.applied = (params, settings);
+            self.throttle.mark();
+        }
+
+        if self.throttle.poll(Instant::now()) {
+            // Ramp from what is playing now to avoid zipper noise
+            let at = self.sync.write_time();
+            let from = self.program.load().params_at_uncached(at);
+            self.program.store(Program::ramp(from, params, at, PREVIEW_RAMP_SECS, settings));
+        }
+
+        self.throttle.pending
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum GuiMode {
     Simple,
@@ -159,9 +540,12 @@ struct ControlPanel {
     // Program mode state
     program_text: String,
     program_error: Option<String>,
+    program_file: Option<PathBuf>,
+    program_file_status: String,
 
     // Active session management
     active_session: Option<Child>,
+    preview: Option<Preview>,
 }
 
 impl Default for ControlPanel {
@@ -178,7 +562,10 @@ impl Default for ControlPanel {
             headless: false,
             program_text: DEFAULT_PROGRAM.trim().into(),
             program_error: None,
+            program_file: None,
+            program_file_status: String::new(),
             active_session: None,
+            preview: None,
         }
     }
 }
@@ -186,7 +573,11 @@ impl Default for ControlPanel {
 impl ControlPanel {
     /// Build a constant program from simple mode settings.
     fn build_simple_program(&self) -> Program {
-        let params = Params {
+        Program::constant(self.simple_params(), self.simple_settings())
+    }
+
+    fn simple_params(&self) -> Params {
+        Params {
             freq: self.freq,
             tone: self.tone,
             vol: self.vol,
@@ -203,14 +594,28 @@ impl ControlPanel {
                 b: (self.off_color[2] * 255.0) as u8,
                 a: 255,
             },
-        };
-        Program::constant(
-            params,
-            Settings {
-                binaural: self.binaural,
-                headless: self.headless,
-            },
-        )
+            ..Params::default()
+        }
+    }
+
+    fn simple_settings(&self) -> Settings {
+        Settings {
+            binaural: self.binaural,
+            headless: self.headless,
+            ..Default::default()
+        }
+    }
+
+    /// Start or stop the live audio preview.
+    fn set_preview(&mut self, enabled: bool) {
+        if !enabled {
+            self.preview = None;
+            return;
+        }
+        match Preview::start(self.simple_params(), self.simple_settings()) {
+            Ok(preview) => self.preview = Some(preview),
+            Err(e) => self.program_error = Some(format!("Failed to start preview: {e}")),
+        }
     }
 
     /// Convert simple mode settings to program text.
@@ -219,9 +624,33 @@ impl ControlPanel {
         self.program_error = None;
     }
 
+    /// Pick a program file and check that it parses.
+    fn load_program_file(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Entrainment program", &["ent", "iso", "txt"])
+            .pick_file()
+        else {
+            return;
+        };
+
+        let result = Program::load(&path);
+        self.program_file_status = describe_loaded_program(&path, &result);
+        self.program_file = result.is_ok().then_some(path);
+    }
+
     /// Launch a new entrainment session.
     fn launch(&mut self) {
         self.stop();
+        self.preview = None;
+
+        // A loaded file is played directly
+        if self.mode == GuiMode::Program
+            && let Some(path) = self.program_file.clone()
+        {
+            self.program_error = None;
+            self.spawn_session(&path);
+            return;
+        }
 
         let source = match self.mode {
             GuiMode::Simple => self.build_simple_program().to_source(),
@@ -236,18 +665,20 @@ impl ControlPanel {
         self.program_error = None;
 
         // Write to temporary file
-        let mut path = std::env::temp_dir();
-        path.push("isochronator_session.ent");
-
+        let path = gui_session_path();
         if let Err(e) = std::fs::write(&path, &source) {
             self.program_error = Some(format!("Failed to write temp file: {e}"));
             return;
         }
 
-        // Spawn session process
+        self.spawn_session(&path);
+    }
+
+    /// Run a session for the program file in a child process.
+    fn spawn_session(&mut self, path: &Path) {
         let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("isochronator"));
 
-        match Command::new(&exe).arg(&path).spawn() {
+        match Command::new(&exe).arg(path).spawn() {
             Ok(child) => {
                 info!("Launched session: {:?} {:?}", exe, path);
                 self.active_session = Some(child);
@@ -269,10 +700,10 @@ impl ControlPanel {
 
     /// Poll and clean up finished child processes.
     fn poll_session(&mut self) {
-        if let Some(child) = &mut self.active_session {
-            if matches!(child.try_wait(), Ok(Some(_))) {
-                self.active_session = None;
-            }
+        if let Some(child) = &mut self.active_session
+            && matches!(child.try_wait(), Ok(Some(_)))
+        {
+            self.active_session = None;
         }
     }
 }
@@ -318,6 +749,14 @@ impl eframe::App for ControlPanel {
                 }
             });
         });
+
+        // Feed control changes to the live preview
+        let (params, settings) = (self.simple_params(), self.simple_settings());
+        if let Some(preview) = &mut self.preview
+            && preview.update(params, settings)
+        {
+            ctx.request_repaint_after(PREVIEW_INTERVAL);
+        }
     }
 
     fn on_exit(&mut self) {
@@ -345,7 +784,7 @@ impl ControlPanel {
                 ui.end_row();
 
                 ui.label("Duty Cycle");
-                ui.add(egui::Slider::new(&mut self.duty, 0.1..=0.9));
+                ui.add(egui::Slider::new(&mut self.duty, 0.01..=0.99));
                 ui.end_row();
 
                 ui.label("On Color");
@@ -363,6 +802,13 @@ impl ControlPanel {
                 ui.label("Display");
                 ui.checkbox(&mut self.headless, "Audio only (no visuals)");
                 ui.end_row();
+
+                ui.label("Preview");
+                let mut previewing = self.preview.is_some();
+                if ui.checkbox(&mut previewing, "Live audio preview").changed() {
+                    self.set_preview(previewing);
+                }
+                ui.end_row();
             });
 
         ui.add_space(8.0);
@@ -373,6 +819,29 @@ impl ControlPanel {
     }
 
     fn ui_program_mode(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("📂 Load Program…").clicked() {
+                self.load_program_file();
+            }
+            if self.program_file.is_some() && ui.button("✖ Use Editor").clicked() {
+                self.program_file = None;
+                self.program_file_status.clear();
+            }
+        });
+        if !self.program_file_status.is_empty() {
+            let color = if self.program_file.is_some() {
+                ui.visuals().text_color()
+            } else {
+                egui::Color32::RED
+            };
+            ui.colored_label(color, &self.program_file_status);
+        }
+        ui.add_space(4.0);
+
+        if self.program_file.is_some() {
+            return;
+        }
+
         ui.label("Entrainment Program:");
         ui.add_space(4.0);
 
@@ -395,6 +864,23 @@ impl ControlPanel {
     }
 }
 
+/// Summary of a loaded program file for the control panel.
+fn describe_loaded_program(path: &Path, result: &Result<Program>) -> String {
+    let name = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into());
+    match result {
+        Ok(program) => {
+            let length = if program.duration.is_finite() {
+                format!("{:.1}s", program.duration)
+            } else {
+                "no end".into()
+            };
+            let keyframes = program.keyframe_times().count();
+            format!("{name}: {keyframes} keyframes, {length}")
+        }
+        Err(e) => format!("{name}: {e:#}"),
+    }
+}
+
 fn run_gui() -> Result<()> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -411,6 +897,338 @@ fn run_gui() -> Result<()> {
         .map_err(|e| anyhow::anyhow!("GUI error: {e}"))
 }
 
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Tools
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Check a rendered file against the program and print a pass/fail report.
+fn verify_render(path: &Path, program: &Program, sample_rate: Option<u32>) -> Result<()> {
+    if !program.duration.is_finite() {
+        anyhow::bail!("cannot verify a render of a program without a finite duration");
+    }
+
+    let expected = audio::RenderSpec {
+        sample_rate: sample_rate.unwrap_or(audio::RENDER_SAMPLE_RATE),
+        channels: 2,
+        duration: program.duration,
+    };
+    let report = audio::verify_wav(path, &expected)?;
+
+    println!(
+        "{}: {} Hz, {} channels, {:.3}s",
+        path.display(),
+        report.sample_rate,
+        report.channels,
+        report.duration
+    );
+    for problem in &report.problems {
+        println!("  {problem}");
+    }
+
+    if report.passed() {
+        println!("PASS");
+        Ok(())
+    } else {
+        anyhow::bail!("FAIL: {} problem(s)", report.problems.len())
+    }
+}
+
+/// Pulse timing derived from the program's starting parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Timing {
+    /// Pulse period in seconds.
+    period: f64,
+    /// Time the pulse is on in each period, in seconds.
+    pulse_on: f64,
+    /// Length of each envelope edge in seconds.
+    edge: f64,
+    /// Samples per pulse period at the given sample rate.
+    period_samples: f64,
+    /// Visual phase shift per millisecond of audio latency.
+    phase_per_ms: f64,
+}
+
+impl Timing {
+    fn new(params: &Params, sample_rate: u32) -> Self {
+        let period = 1.0 / params.freq;
+        let envelope = audio::Envelope::from_params(params);
+        Self {
+            period,
+            pulse_on: period * f64::from(params.duty),
+            edge: period * envelope.ramp(),
+            period_samples: period * f64::from(sample_rate),
+            phase_per_ms: params.freq / 1000.0,
+        }
+    }
+}
+
+/// Print the pulse timing at the start of the program.
+fn show_timing(program: &Program, sample_rate: Option<u32>) {
+    let sample_rate = sample_rate.unwrap_or(audio::RENDER_SAMPLE_RATE);
+    let params = program.params_at(0.0);
+    let timing = Timing::new(&params, sample_rate);
+
+    println!("Frequency:       {:.3} Hz", params.freq);
+    println!("Period:          {:.3} ms", timing.period * 1000.0);
+    println!("Pulse on:        {:.3} ms (duty {:.2})", timing.pulse_on * 1000.0, params.duty);
+    println!("Envelope edge:   {:.3} ms", timing.edge * 1000.0);
+    println!("Period samples:  {:.1} @ {sample_rate} Hz", timing.period_samples);
+    println!("Phase per ms:    {:.4} of latency", timing.phase_per_ms);
+    if program.duration.is_finite() {
+        println!(
+            "Total on-time:   {:.1} s of {:.1} s",
+            program.total_on_time(),
+            program.duration
+        );
+    }
+}
+
+/// Columns and rows of the `--show-envelope` plot.
+const ENVELOPE_PLOT_SIZE: (usize, usize) = (64, 12);
+
+/// Sample one period of the envelope at the center of each plot column.
+fn envelope_samples(envelope: &audio::Envelope, columns: usize) -> Vec<f64> {
+    (0..columns)
+        .map(|i| envelope.value((i as f64 + 0.5) / columns as f64))
+        .collect()
+}
+
+/// Print an ASCII plot of one pulse envelope.
+fn show_envelope(duty: f64, ramp: Option<f64>) {
+    let duty = duty.clamp(0.01, 0.99);
+    // At 1 Hz the edge length in seconds is a fraction of the period
+    let envelope = audio::Envelope::new(duty, 1.0, ramp.unwrap_or(0.0));
+    let (columns, rows) = ENVELOPE_PLOT_SIZE;
+    let samples = envelope_samples(&envelope, columns);
+
+    println!("duty={duty:.2} ramp={:.3}", envelope.ramp());
+    for row in (0..rows).rev() {
+        let threshold = (row as f64 + 0.5) / rows as f64;
+        let line: String = samples
+            .iter()
+            .map(|&v| if v >= threshold { '#' } else { ' ' })
+            .collect();
+        println!("|{}", line.trim_end());
+    }
+    println!("+{}", "-".repeat(columns));
+}
+
+/// Play an ungated sine at the default program volume, for checking audio routing and levels.
+fn run_test_tone(hz: f32, device: Option<String>) -> Result<()> {
+    anyhow::ensure!(hz > 0.0, "--test-tone must be positive");
+    info!("Playing a {hz} Hz test tone, press Ctrl-C to stop");
+
+    let params = Params { tone: hz, ..Params::default() };
+    let program = Program::constant(params, Settings { headless: true, ..Settings::default() });
+    let options = visuals::SessionOptions {
+        engine: audio::EngineOptions {
+            binaural: Some(false),
+            ungated: true,
+            ..Default::default()
+        },
+        device,
+        ..Default::default()
+    };
+    visuals::run_headless(Arc::new(program), options)
+}
+
+/// The first whole second of Unix time at or after `now`.
+fn next_whole_second(now: std::time::SystemTime) -> std::time::SystemTime {
+    let since_epoch = now.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() + u64::from(since_epoch.subsec_nanos() > 0);
+    std::time::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// The program's source as a Rust string constant named `name`, to load
+/// with `Program::parse`.
+fn rust_fixture(program: &Program, name: &str) -> Result<String> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    anyhow::ensure!(valid, "'{name}' is not a valid Rust identifier");
+
+    // Enough hashes that no `"#...` inside the source ends the raw string
+    let source = program.to_source();
+    let longest = source
+        .split('"')
+        .skip(1)
+        .map(|rest| rest.chars().take_while(|&c| c == '#').count())
+        .max()
+        .unwrap_or(0);
+    let hashes = "#".repeat(longest + 1);
+
+    Ok(format!(
+        "const {}: &str = r{hashes}\"\n{source}\"{hashes};\n",
+        name.to_ascii_uppercase()
+    ))
+}
+
+/// Seed derived from the current time, for options that don't specify one.
+fn time_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Flat Config
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Fixed session parameters given as individual flags instead of a program file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FlatConfig {
+    freq: f64,
+    tone: f32,
+    vol: f32,
+    duty: f32,
+    on: Color,
+    off: Color,
+    binaural: bool,
+}
+
+impl FlatConfig {
+    fn from_args(args: &Args) -> Result<Self> {
+        anyhow::ensure!(args.freq > 0.0, "--freq must be positive");
+        anyhow::ensure!(args.tone > 0.0, "--tone must be positive");
+        anyhow::ensure!((0.0..=1.0).contains(&args.vol), "--vol must be in [0, 1]");
+        anyhow::ensure!((0.01..=0.99).contains(&args.duty), "--duty must be in [0.01, 0.99]");
+        Ok(Self {
+            freq: args.freq,
+            tone: args.tone,
+            vol: args.vol,
+            duty: args.duty as f32,
+            on: args.on,
+            off: args.off,
+            binaural: args.binaural,
+        })
+    }
+}
+
+/// The constant program equivalent to a flat config.
+fn config_to_program(config: &FlatConfig) -> Program {
+    let params = Params {
+        freq: config.freq,
+        tone: config.tone,
+        vol: config.vol,
+        duty: config.duty,
+        on: config.on,
+        off: config.off,
+        ..Params::default()
+    };
+    Program::constant(params, Settings { binaural: config.binaural, ..Settings::default() })
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Launch Config
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// What to do when started without arguments, from `default_action` in the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+enum DefaultAction {
+    #[default]
+    Gui,
+    Program(PathBuf),
+    LastSession,
+}
+
+impl FromStr for DefaultAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gui" => Ok(Self::Gui),
+            "last-session" => Ok(Self::LastSession),
+            _ => match s.strip_prefix("program=") {
+                Some(path) if !path.is_empty() => Ok(Self::Program(path.into())),
+                _ => Err(format!("unknown default_action '{s}' (expected: gui, program=<path>, last-session)")),
+            },
+        }
+    }
+}
+
+/// Directory holding the config file and the last session record.
+fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("isochronator"))
+}
+
+/// Read `default_action` from config text of `key = value` lines.
+fn parse_launch_config(text: &str) -> Result<DefaultAction> {
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("line {}: expected key = value", i + 1))?;
+        if key.trim() == "default_action" {
+            return value
+                .trim()
+                .parse()
+                .map_err(|e| anyhow::anyhow!("line {}: {e}", i + 1));
+        }
+    }
+    Ok(DefaultAction::Gui)
+}
+
+/// Resolve the no-argument action to a program path, or `None` for the GUI.
+fn resolve_default_action(action: DefaultAction, last_session: Option<PathBuf>) -> Option<PathBuf> {
+    match action {
+        DefaultAction::Gui => None,
+        DefaultAction::Program(path) => Some(path),
+        DefaultAction::LastSession => {
+            if last_session.is_none() {
+                warn!("No previous session to resume, opening the control panel");
+            }
+            last_session
+        }
+    }
+}
+
+/// Program to play when started without arguments, per the config file.
+fn configured_program() -> Option<PathBuf> {
+    let dir = config_dir()?;
+    let action = match std::fs::read_to_string(dir.join("config")) {
+        Ok(text) => parse_launch_config(&text)
+            .inspect_err(|e| warn!("Ignoring {}: {e:#}", dir.join("config").display()))
+            .unwrap_or_default(),
+        Err(_) => DefaultAction::Gui,
+    };
+    let last = std::fs::read_to_string(dir.join("last_session"))
+        .ok()
+        .map(|path| PathBuf::from(path.trim()));
+    resolve_default_action(action, last)
+}
+
+/// Temporary file the control panel writes unsaved programs to for a session.
+fn gui_session_path() -> PathBuf {
+    std::env::temp_dir().join("isochronator_session.ent")
+}
+
+/// Whether `path` is the control panel's temporary program file.
+fn is_gui_session(path: &Path) -> bool {
+    let scratch = gui_session_path();
+    path == scratch || matches!((path.canonicalize(), scratch.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Record the program file of this session for `default_action = last-session`.
+///
+/// The control panel's temporary file is skipped, since the next launch from
+/// the panel overwrites it.
+fn remember_last_session(path: &Path) {
+    if is_gui_session(path) {
+        return;
+    }
+    let (Some(dir), Ok(path)) = (config_dir(), path.canonicalize()) else {
+        return;
+    };
+    let result = std::fs::create_dir_all(&dir)
+        .and_then(|()| std::fs::write(dir.join("last_session"), path.to_string_lossy().as_bytes()));
+    if let Err(e) = result {
+        log::debug!("Could not record last session: {e}");
+    }
+}
+
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Entry Point
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
@@ -422,11 +1240,56 @@ fn main() -> Result<()> {
         .filter_module("naga", log::LevelFilter::Warn)
         .init();
 
-    let args: Args = argh::from_env();
+    let mut args: Args = argh::from_env();
+
+    if args.list_devices {
+        for device in audio::list_output_devices()? {
+            println!("{device}");
+        }
+        return Ok(());
+    }
 
-    // No arguments: launch GUI
+    if args.show_envelope {
+        show_envelope(args.duty, args.ramp);
+        return Ok(());
+    }
+
+    if let Some(hz) = args.test_tone {
+        return run_test_tone(hz, args.device);
+    }
+
+    if let Some(max_gain) = args.max_gain {
+        anyhow::ensure!(max_gain > 0.0 && max_gain <= 1.0, "--max-gain must be in (0, 1]");
+    }
+    anyhow::ensure!(
+        args.fade_out.is_finite() && args.fade_out >= 0.0,
+        "--fade-out must be zero or more seconds"
+    );
+
+    if args.repl {
+        let program = config_to_program(&FlatConfig::from_args(&args)?);
+        let engine = audio::EngineOptions {
+            gain_db: args.gain_db,
+            max_gain: args.max_gain,
+            fade_out: Some(args.fade_out),
+            ..Default::default()
+        };
+        return repl::run(program, engine, args.device.as_deref());
+    }
+
+    if let Some(out) = &args.export_program {
+        let program = config_to_program(&FlatConfig::from_args(&args)?);
+        std::fs::write(out, program.to_source()).with_context(|| format!("writing {}", out.display()))?;
+        info!("Wrote {}", out.display());
+        return Ok(());
+    }
+
+    // No arguments: launch GUI, unless configured otherwise
     if args.program.is_none() && !args.profile {
-        return run_gui();
+        match configured_program() {
+            Some(path) => args.program = Some(path),
+            None => return run_gui(),
+        }
     }
 
     // Profile mode: run CPU benchmark for PGO
@@ -440,12 +1303,402 @@ fn main() -> Result<()> {
 
     // Session mode: load and run program
     let path = args.program.context("No program file specified")?;
-    let program = Program::load(&path).with_context(|| format!("Loading {}", path.display()))?;
+    let mut program = Program::load(&path).with_context(|| format!("Loading {}", path.display()))?;
+
+    for warning in &program.warnings {
+        warn!("{}: {warning}", path.display());
+    }
+    if args.strict && !program.warnings.is_empty() {
+        anyhow::bail!("{} warning(s) in strict mode", program.warnings.len());
+    }
+
+    anyhow::ensure!(
+        !args.watch || (args.skip_tags.is_none() && args.repeat_count.is_none()),
+        "--watch reloads the file as written and can't be combined with --skip-tags or --repeat-count"
+    );
+
+    if let Some(tags) = &args.skip_tags {
+        let tags: Vec<&str> = tags.split(',').map(str::trim).filter(|t| !t.is_empty()).collect();
+        program = program.without_tags(&tags)?;
+        info!("Skipping tags {tags:?}, {:.1}s remaining", program.duration);
+    }
+
+    let mut repeat = None;
+    if let Some(count) = args.repeat_count {
+        let period = program.duration + program::REPEAT_GAP_SECS;
+        program = program.repeated(count).context("--repeat-count")?;
+        repeat = Some(visuals::Repeat { count, period });
+        info!("Repeating {count} times, {:.1}s in total", program.duration);
+    }
+
+    if args.explain {
+        print!("{}", program.explain());
+        return Ok(());
+    }
+
+    if let Some(out) = &args.export_json {
+        std::fs::write(out, program.to_json()).with_context(|| format!("writing {}", out.display()))?;
+        info!("Wrote {}", out.display());
+        return Ok(());
+    }
+
+    if let Some(name) = &args.export_rust {
+        print!("{}", rust_fixture(&program, name)?);
+        return Ok(());
+    }
+
+    if let Some(out) = &args.export_brightness {
+        let rows = visuals::export_brightness(&program, out, args.fps)?;
+        println!("Wrote {rows} rows to {}", out.display());
+        return Ok(());
+    }
+
+    if let Some(dir) = &args.render_video {
+        let frames = visuals::render_frames(Arc::new(program), dir, args.fps)?;
+        println!("Wrote {frames} frames to {}", dir.display());
+        println!(
+            "Assemble with: ffmpeg -framerate {} -i {}/frame_%06d.png -pix_fmt yuv420p out.mp4",
+            args.fps,
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    if args.show_timing {
+        show_timing(&program, args.sample_rate.or(program.settings.sample_rate));
+        return Ok(());
+    }
+
+    if let Some(wav) = &args.verify {
+        return verify_render(wav, &program, args.sample_rate.or(program.settings.sample_rate));
+    }
+
+    let mut engine = audio::EngineOptions {
+        keyframe_clicks: args.keyframe_clicks,
+        amplitude_curve: args.amplitude_curve,
+        crossfade_law: args.crossfade_law,
+        carrier_phase: args.carrier_phase,
+        gain_db: args.gain_db,
+        max_gain: args.max_gain,
+        fade_out: Some(args.fade_out),
+        ..Default::default()
+    };
+
+    match (args.as_binaural, args.as_isochronic) {
+        (true, true) => anyhow::bail!("--as-binaural and --as-isochronic are mutually exclusive"),
+        (true, false) => engine.binaural = Some(true),
+        (false, true) => engine.binaural = Some(false),
+        (false, false) => {}
+    }
+
+    if let Some(slew) = args.max_freq_slew {
+        anyhow::ensure!(slew > 0.0, "--max-freq-slew must be positive");
+        engine.max_freq_slew = Some(slew);
+    }
+
+    anyhow::ensure!(
+        (0.0..1.0).contains(&args.carrier_phase),
+        "--carrier-phase must be in [0, 1)"
+    );
+
+    if let Some(ms) = args.epoch {
+        engine.epoch = Some(std::time::UNIX_EPOCH + Duration::from_millis(ms));
+        info!("Program time zero at Unix epoch {ms} ms");
+    }
+
+    if args.sync_start {
+        anyhow::ensure!(args.epoch.is_none(), "--sync-start and --epoch are mutually exclusive");
+        let now = std::time::SystemTime::now();
+        let start = next_whole_second(now);
+        engine.epoch = Some(start);
+        info!(
+            "Starting at Unix time {}s, in {:.3}s",
+            start.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs(),
+            start.duration_since(now).unwrap_or_default().as_secs_f64()
+        );
+    }
+
+    if let Some(grid) = args.freq_grid {
+        anyhow::ensure!(grid > 0.0, "--freq-grid must be positive");
+        engine.freq_grid = Some(grid);
+    }
+
+    if let Some(range) = args.random_base {
+        let seed = args.seed.unwrap_or_else(time_seed);
+        engine.tone_offset = audio::random_tone_offset(range.abs(), seed);
+        let base = program.params_at(0.0).tone;
+        info!(
+            "Binaural base tone: {:.2} Hz ({:+.2} Hz, seed={seed})",
+            base + engine.tone_offset,
+            engine.tone_offset
+        );
+    }
+
+    if let Some(out) = &args.render {
+        if let Some(secs) = args.duration {
+            anyhow::ensure!(secs > 0.0, "--duration must be positive");
+        }
+        let sample_rate = args
+            .sample_rate
+            .or(program.settings.sample_rate)
+            .unwrap_or(audio::RENDER_SAMPLE_RATE);
+
+        if out.as_os_str() == "-" {
+            let duration = args.duration.or(program.duration.is_finite().then_some(program.duration));
+            info!("Streaming raw f32 stereo at {sample_rate} Hz to stdout");
+            let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+            let frames = audio::render_raw(Arc::new(program), &mut stdout, sample_rate, duration, engine)?;
+            info!("Streamed {:.1}s ({frames} frames)", frames as f64 / f64::from(sample_rate));
+            return Ok(());
+        }
+
+        let duration = args.duration.unwrap_or(program.duration);
+        anyhow::ensure!(
+            duration.is_finite(),
+            "--render needs --duration for a program without an end"
+        );
+        let frames = audio::render_to_wav(Arc::new(program), out, sample_rate, duration, engine)?;
+        println!(
+            "Wrote {:.1}s ({frames} frames at {sample_rate} Hz) to {}",
+            frames as f64 / f64::from(sample_rate),
+            out.display()
+        );
+        return Ok(());
+    }
 
     info!(
         "Starting session: duration={:.1}s, binaural={}, headless={}",
-        program.duration, program.settings.binaural, program.settings.headless
+        program.duration,
+        engine.binaural.unwrap_or(program.settings.binaural),
+        program.settings.headless
+    );
+
+    let brightness_cap = match (args.max_brightness, args.brightness_knee) {
+        (Some(max), knee) => {
+            anyhow::ensure!(max > 0.0 && max <= 1.0, "--max-brightness must be in (0, 1]");
+            Some(visuals::BrightnessCap { max, knee })
+        }
+        (None, true) => anyhow::bail!("--brightness-knee needs --max-brightness"),
+        (None, false) => None,
+    };
+
+    anyhow::ensure!((0.0..=1.0).contains(&args.intensity), "--intensity must be in [0, 1]");
+    if let Some(secs) = args.duration {
+        anyhow::ensure!(secs > 0.0, "--duration must be positive");
+    }
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&args.overlay_opacity),
+        "--overlay-opacity must be in [0, 1]"
     );
 
-    visuals::run_session(Arc::new(program))
-}
\ No newline at end of file
+    let options = visuals::SessionOptions {
+        midi_out: args.midi_out,
+        engine,
+        idle_color: args.idle_color,
+        freeze_on_exit: args.freeze_on_exit,
+        lock_aspect: args.lock_aspect,
+        letterbox_color: args.letterbox_color,
+        av_offset_ms: args.av_offset,
+        visualize_sync: args.visualize_sync,
+        hud: args.hud,
+        repeat,
+        log_keyframes: args.log_keyframes,
+        brightness_cap,
+        prevent_sleep: args.prevent_sleep,
+        smooth_phase: args.smooth_phase,
+        overlay: args.overlay.then_some(args.overlay_opacity),
+        intensity: Some(args.intensity),
+        duration: args.duration,
+        device: args.device,
+        allow_unsafe_flicker: args.allow_unsafe_flicker,
+        watch: args.watch.then(|| path.clone()),
+    };
+
+    remember_last_session(&path);
+    if plays_without_window(args.no_window, &program.settings) {
+        return visuals::run_headless(Arc::new(program), options);
+    }
+
+    visuals::run_session(Arc::new(program), options)
+}
+
+/// Whether a session runs audio-only without a window, either by `--no-window`
+/// or the program's own `headless` setting.
+fn plays_without_window(no_window: bool, settings: &Settings) -> bool {
+    no_window || settings.headless
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Tests
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_lerp_blends_in_linear_light() {
+        // A raw sRGB blend of black and white would give 128
+        let mid = Color::lerp(Color::BLACK, Color::WHITE, 0.5);
+        assert_eq!((mid.r, mid.g, mid.b), (188, 188, 188));
+        assert_eq!(Color::lerp(Color::BLACK, Color::WHITE, 0.0), Color::BLACK);
+        assert_eq!(Color::lerp(Color::BLACK, Color::WHITE, 1.0), Color::WHITE);
+        assert_eq!(Color::from_linear([0.5; 3]), mid);
+    }
+
+    #[test]
+    fn timing_for_known_params() {
+        let params = Params { freq: 10.0, duty: 0.4, ..Params::default() };
+        let timing = Timing::new(&params, 48000);
+
+        assert!((timing.period - 0.1).abs() < 1e-12);
+        assert!((timing.pulse_on - 0.04).abs() < 1e-7);
+        assert!((timing.edge - 0.01).abs() < 1e-9); // 10% of the period
+        assert!((timing.period_samples - 4800.0).abs() < 1e-6);
+        assert!((timing.phase_per_ms - 0.01).abs() < 1e-12);
+    }
+
+    #[test]
+    fn envelope_plot_sampling() {
+        let envelope = audio::Envelope::new(0.3, 1.0, 0.01);
+        let samples = envelope_samples(&envelope, 100);
+
+        assert_eq!(samples.len(), 100);
+        assert_eq!(samples[0], envelope.value(0.005)); // mid attack
+        assert_eq!(samples[15], 1.0); // plateau at phase 0.155
+        assert_eq!(samples[50], 0.0); // off at phase 0.505
+    }
+
+    #[test]
+    fn throttle_limits_burst() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(Duration::from_millis(30));
+
+        // A 100 ms drag producing an update every 5 ms
+        let mut applied = Vec::new();
+        for ms in (0..=100).step_by(5) {
+            throttle.mark();
+            if throttle.poll(start + Duration::from_millis(ms)) {
+                applied.push(ms);
+            }
+        }
+        assert_eq!(applied, [0, 30, 60, 90]);
+
+        // The last value is still delivered once the interval has passed
+        assert!(!throttle.poll(start + Duration::from_millis(110)));
+        assert!(throttle.poll(start + Duration::from_millis(120)));
+        assert!(!throttle.poll(start + Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn simple_mode_duty_reaches_session() {
+        for duty in [0.01, 0.5, 0.99] {
+            let panel = ControlPanel { duty, ..ControlPanel::default() };
+            let program = Program::parse(&panel.build_simple_program().to_source()).unwrap();
+            assert_eq!(program.params_at(0.0).duty, duty);
+        }
+    }
+
+    #[test]
+    fn headless_program_skips_window() {
+        let headless = Program::parse("00:00 freq=10 headless").unwrap();
+        assert!(plays_without_window(false, &headless.settings));
+
+        let windowed = Program::parse("00:00 freq=10").unwrap();
+        assert!(!plays_without_window(false, &windowed.settings));
+        assert!(plays_without_window(true, &windowed.settings));
+    }
+
+    #[test]
+    fn exported_config_round_trips() {
+        let config = FlatConfig {
+            freq: 7.5,
+            tone: 180.0,
+            vol: 0.6,
+            duty: 0.3,
+            on: Color { r: 255, g: 136, b: 0, a: 255 },
+            off: Color::BLACK,
+            binaural: true,
+        };
+        let exported = Program::parse(&config_to_program(&config).to_source()).unwrap();
+        let params = exported.params_at(0.0);
+
+        assert_eq!(params.freq, config.freq);
+        assert_eq!(params.tone, config.tone);
+        assert_eq!(params.vol, config.vol);
+        assert_eq!(params.duty, config.duty);
+        assert_eq!((params.on, params.off), (config.on, config.off));
+        assert!(exported.settings.binaural);
+        assert!(exported.duration.is_infinite());
+    }
+
+    #[test]
+    fn default_action_from_config() {
+        let config = "# launch settings\ndefault_action = program=/sessions/alpha.ent\n";
+        assert_eq!(
+            parse_launch_config(config).unwrap(),
+            DefaultAction::Program("/sessions/alpha.ent".into())
+        );
+        assert_eq!(parse_launch_config("default_action=last-session").unwrap(), DefaultAction::LastSession);
+        assert_eq!(parse_launch_config("").unwrap(), DefaultAction::Gui);
+        assert!(parse_launch_config("default_action = fullscreen").is_err());
+        assert!(parse_launch_config("default_action = program=").is_err());
+
+        let last = Some(PathBuf::from("/tmp/last.ent"));
+        assert_eq!(resolve_default_action(DefaultAction::Gui, last.clone()), None);
+        assert_eq!(resolve_default_action(DefaultAction::LastSession, last.clone()), last);
+        assert_eq!(resolve_default_action(DefaultAction::LastSession, None), None);
+        assert_eq!(
+            resolve_default_action(DefaultAction::Program("/a.ent".into()), last),
+            Some("/a.ent".into())
+        );
+    }
+
+    #[test]
+    fn gui_scratch_file_is_not_remembered() {
+        assert!(is_gui_session(&gui_session_path()));
+        assert!(!is_gui_session(Path::new("/sessions/alpha.ent")));
+        assert!(!is_gui_session(&std::env::temp_dir().join("alpha.ent")));
+    }
+
+    #[test]
+    fn loaded_program_description() {
+        let path = Path::new("/sessions/alpha.ent");
+
+        let program = Program::parse("00:00 vol=0\n00:05 vol=1 >linear\n02:00 vol=0 >linear");
+        assert_eq!(describe_loaded_program(path, &program), "alpha.ent: 3 keyframes, 120.0s");
+
+        let endless = Program::parse("00:00 freq=10");
+        assert_eq!(describe_loaded_program(path, &endless), "alpha.ent: 1 keyframes, no end");
+
+        let broken = Program::parse("00:00 freq=-1");
+        assert_eq!(
+            describe_loaded_program(path, &broken),
+            "alpha.ent: line 1: freq must be positive"
+        );
+    }
+
+    #[test]
+    fn sync_start_rounds_up_to_whole_second() {
+        let at = |ms: u64| std::time::UNIX_EPOCH + Duration::from_millis(ms);
+        assert_eq!(next_whole_second(at(1_234_250)), at(1_235_000));
+        assert_eq!(next_whole_second(at(1_234_999)), at(1_235_000));
+        assert_eq!(next_whole_second(at(1_235_000)), at(1_235_000));
+    }
+
+    #[test]
+    fn rust_fixture_reparses() {
+        let source = "00:00 freq=10 on=#FF8800 note=\"start\"\n00:30 freq=6 >smooth note=\"#1\"\n01:00 vol=0";
+        let program = Program::parse(source).unwrap();
+        let fixture = rust_fixture(&program, "deep_dive").unwrap();
+        assert!(fixture.starts_with("const DEEP_DIVE: &str = r##\""), "{fixture}");
+
+        let literal = &fixture[fixture.find('"').unwrap() + 1..fixture.rfind('"').unwrap()];
+        let reparsed = Program::parse(literal).unwrap();
+        assert_eq!(reparsed.to_source(), program.to_source());
+        assert_eq!(reparsed.duration, program.duration);
+
+        assert!(rust_fixture(&program, "2fast").is_err());
+        assert!(rust_fixture(&program, "my-program").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! MIDI note output synchronized with the pulse.
+//!
+//! A background thread polls the shared [`SyncState`] and sends a note-on at
+//! each pulse onset and a note-off at each pulse end. The note follows the
+//! carrier tone and the velocity follows the volume.
+
+use crate::audio::SyncState;
+use crate::program::LiveProgram;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use midir::{MidiOutput, MidiOutputConnection};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const NOTE_ON: u8 = 0x90;
+const NOTE_OFF: u8 = 0x80;
+
+/// Polling interval of the edge detection thread.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Conversions
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Convert a frequency in Hz to the nearest MIDI note number (A4 = 440 Hz = 69).
+pub fn tone_to_note(hz: f32) -> u8 {
+    let note = 69.0 + 12.0 * (f64::from(hz) / 440.0).log2();
+    note.round().clamp(0.0, 127.0) as u8
+}
+
+/// Convert a volume [0, 1] to a MIDI velocity.
+///
+/// Never returns 0, since a note-on with velocity 0 means note-off.
+pub fn vol_to_velocity(vol: f32) -> u8 {
+    (vol * 127.0).round().clamp(1.0, 127.0) as u8
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Edge Detection
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Pulse edges reported by [`EdgeDetector`], in the order they occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Edges {
+    pub falling: bool,
+    pub rising: bool,
+}
+
+/// Detects pulse on/off transitions from successive phase samples.
+#[derive(Debug, Default)]
+pub struct EdgeDetector {
+    last_phase: f64,
+    on: bool,
+}
+
+impl EdgeDetector {
+    /// Feed a new phase sample and report the edges crossed since the last one.
+    ///
+    /// A phase wrap while staying "on" counts as a pulse end followed by a new onset.
+    pub fn update(&mut self, phase: f64, duty: f64) -> Edges {
+        let on = phase < duty;
+        let wrapped = phase < self.last_phase;
+
+        let edges = Edges {
+            falling: self.on && (!on || wrapped),
+            rising: on && (!self.on || wrapped),
+        };
+
+        self.last_phase = phase;
+        self.on = on;
+        edges
+    }
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Output Thread
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Handle to the running MIDI output thread. Stops the thread on drop.
+pub struct MidiPulseOut {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MidiPulseOut {
+    /// Connect to the first available MIDI output port and start sending notes.
+    pub fn start(program: Arc<LiveProgram>, sync: Arc<SyncState>) -> Result<Self> {
+        let output = MidiOutput::new("Isochronator").context("initializing MIDI")?;
+        let port = output
+            .ports()
+            .into_iter()
+            .next()
+            .context("No MIDI output port available")?;
+
+        let port_name = output.port_name(&port).unwrap_or_else(|_| "unknown".into());
+        let conn = output
+            .connect(&port, "isochronator-pulse")
+            .map_err(|e| anyhow::anyhow!("connecting to MIDI port '{port_name}': {e}"))?;
+        info!("MIDI output: {port_name}");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || run(conn, &program, &sync, &stop))
+        };
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for MidiPulseOut {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(mut conn: MidiOutputConnection, program: &LiveProgram, sync: &SyncState, stop: &AtomicBool) {
+    let mut detector = EdgeDetector::default();
+    let mut active_note: Option<u8> = None;
+
+    while !stop.load(Ordering::Acquire) {
+        let params = program.load().params_at_uncached(sync.playback_time());
+        let phase = sync.visual_phase(params.freq);
+        let edges = detector.update(phase, f64::from(params.duty));
+
+        if edges.falling && let Some(note) = active_note.take() {
+            send(&mut conn, &[NOTE_OFF, note, 0]);
+        }
+        if edges.rising {
+            let note = tone_to_note(params.tone);
+            send(&mut conn, &[NOTE_ON, note, vol_to_velocity(params.vol)]);
+            active_note = Some(note);
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    if let Some(note) = active_note {
+        send(&mut conn, &[NOTE_OFF, note, 0]);
+    }
+}
+
+fn send(conn: &mut MidiOutputConnection, message: &[u8]) {
+    if let Err(e) = conn.send(message) {
+        warn!("MIDI send failed: {e}");
+    }
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Tests
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_to_midi_note() {
+        assert_eq!(tone_to_note(440.0), 69);
+        assert_eq!(tone_to_note(261.63), 60); // middle C
+        assert_eq!(tone_to_note(200.0), 55); // ~G3 (196 Hz)
+        assert_eq!(tone_to_note(1.0), 0); // clamped
+        assert_eq!(tone_to_note(100_000.0), 127); // clamped
+    }
+
+    #[test]
+    fn velocity_is_never_zero() {
+        assert_eq!(vol_to_velocity(0.0), 1);
+        assert_eq!(vol_to_velocity(1.0), 127);
+    }
+
+    #[test]
+    fn edges_follow_duty_cycle() {
+        let mut detector = EdgeDetector::default();
+
+        assert!(detector.update(0.1, 0.5).rising);
+        assert_eq!(detector.update(0.3, 0.5), Edges::default());
+        assert!(detector.update(0.6, 0.5).falling);
+
+        // Wrapping while on reports the missed pulse end and a new onset
+        detector.update(0.2, 0.5);
+        let edges = detector.update(0.1, 0.5);
+        assert!(edges.falling && edges.rising);
+    }
+}
@@ -12,22 +12,70 @@
 //! 00:10 vol=0.8 >linear          // Fade in over 10 seconds
 //! 02:00 freq=6 >smooth           // Smooth ease to 6 Hz
 //! 05:00 vol=0 >linear            // Fade out
+//! +00:30 hold                     // +MM:SS is relative to the previous keyframe
+//! // Curves: step, linear, smooth, exp (slow start), log (fast start, gentle end)
 //!
-//! // Settings (only on first line): binaural, headless
+//! // @rate <param> <+/-delta>/<secs>s ramps a parameter linearly at a fixed rate
+//! // from the previous keyframe until the next one, e.g. "@rate vol +0.1/60s".
+//! // Explicit values on the next keyframe win; it defaults to a linear curve.
+//!
+//! // loop <count> { ... } plays the keyframes inside <count> times. One play
+//! // lasts from the keyframe before the loop to the last keyframe inside, and
+//! // each repeat starts where the previous one ended. Loops can't be nested.
+//! // For three one-minute waves after a keyframe at 05:00:
+//! //   loop 3 {
+//! //   05:30 freq=6 >linear
+//! //   06:00 freq=10 >linear
+//! //   }
+//!
+//! // <field>><curve> (or <field>:<curve>) overrides the curve for one parameter group, e.g.
+//! // "00:20 vol=1 on=#FF0000 >smooth color:step" eases the volume but switches
+//! // the color at the end. Fields: freq, tone, vol, duty, smooth, vibrato,
+//! // autopan, pan, swell, noise, color
+//!
+//! // via=#RRGGBB makes the on color pass through a midpoint color on the way in
+//! // hold keeps all current values, for a keyframe that only marks a point in time
+//! // period=<secs> sets freq as the time between pulses, e.g. period=3 is 0.333 Hz
+//! // freq above 100 Hz warns: pulses that fast act as amplitude modulation, not entrainment
+//! // left=<hz> right=<hz> set binaural tones per ear, i.e. tone=left freq=right-left
+//! // tag=<name> names a section, so it can be left out with --skip-tags
+//! // note="<text>" documents a keyframe and has no effect on playback
+//! // smooth=<secs> sets the isochronic pulse edge length (0 = 10% of the period)
+//! // env=cosine shapes each isochronic pulse as a raised cosine instead of a
+//! // trapezoid (env=trapezoid); it switches at its keyframe
+//! // vibrato=<depth_hz>:<rate_hz> frequency-modulates the isochronic carrier
+//! // autopan=<rate_hz>:<depth> slowly pans isochronic pulses across the stereo field
+//! // pan=<-1..1> places isochronic pulses left (-1) or right (1) of center; autopan swings around it
+//! // swell=<rate_hz>:<depth> slowly rises and falls the overall volume, e.g. swell=0.05:0.3
+//! // noise=<0..1> mixes pink noise under the tone, relative to vol, to soften the gaps
+//!
+//! // Settings (only on first line): binaural, monaural, headless, linked, invert_duty,
+//! // default_curve=<curve>, samplerate=<hz>, binaural_on=<color>, isochronic_on=<color>
+//! // monaural mixes tone and tone+freq into both channels, so the beat works on speakers
+//! // linked makes the visual brightness follow the audio envelope shape
+//! // invert_duty keeps the pulse on for (1 - duty), so duty sets the length of the gap
+//! // default_curve sets the curve used by keyframes without a '>' directive
+//! // samplerate requests a playback rate, e.g. for very high carrier tones
+//! // binaural_on=#RRGGBB and isochronic_on=#RRGGBB set the starting on color for
+//! // that mode when the first line has no on=, e.g. a blue tint for binaural programs
 //! ```
 
 use crate::Color;
 use anyhow::{bail, Context, Result};
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
 use std::fmt::Write;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Curve
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
 /// Interpolation curve for transitions between keyframes.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Curve {
     /// Instant change at the keyframe time (no interpolation).
     #[default]
@@ -36,6 +84,10 @@ pub enum Curve {
     Linear,
     /// Smooth ease-in-out (Hermite smoothstep).
     Smooth,
+    /// Quadratic ease-in: slow start, fast end.
+    Exp,
+    /// Quadratic ease-out: fast start, slow end, e.g. for fades into silence.
+    Log,
 }
 
 impl Curve {
@@ -47,6 +99,21 @@ impl Curve {
             Self::Step => if t >= 1.0 { 1.0 } else { 0.0 },
             Self::Linear => t,
             Self::Smooth => t * t * (3.0 - 2.0 * t), // Hermite smoothstep
+            Self::Exp => t * t,
+            Self::Log => 1.0 - (1.0 - t) * (1.0 - t),
+        }
+    }
+
+    /// Area under the curve from 0 to `t`, for integrating a parameter over a transition.
+    #[inline]
+    pub fn integral(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Step => 0.0,
+            Self::Linear => t * t * 0.5,
+            Self::Smooth => t * t * t * (1.0 - 0.5 * t),
+            Self::Exp => t * t * t / 3.0,
+            Self::Log => t * t * (1.0 - t / 3.0),
         }
     }
 
@@ -55,25 +122,97 @@ impl Curve {
             "step" => Ok(Self::Step),
             "linear" => Ok(Self::Linear),
             "smooth" => Ok(Self::Smooth),
-            _ => bail!("unknown curve '{s}' (expected: step, linear, smooth)"),
+            "exp" => Ok(Self::Exp),
+            "log" => Ok(Self::Log),
+            _ => bail!("unknown curve '{s}' (expected: step, linear, smooth, exp, log)"),
+        }
+    }
+
+    fn to_str(self) -> &'static str {
+        match self {
+            Self::Step => "step",
+            Self::Linear => "linear",
+            Self::Smooth => "smooth",
+            Self::Exp => "exp",
+            Self::Log => "log",
+        }
+    }
+}
+
+/// Amplitude shape of each isochronic pulse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvelopeShape {
+    /// Flat top with smoothstep edges set by `smooth`.
+    #[default]
+    Trapezoid,
+    /// One raised-cosine hump across the on part of the period.
+    #[serde(rename = "cosine")]
+    RaisedCosine,
+}
+
+impl EnvelopeShape {
+    fn parse(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "trapezoid" => Ok(Self::Trapezoid),
+            "cosine" => Ok(Self::RaisedCosine),
+            _ => bail!("unknown envelope '{s}' (expected: trapezoid, cosine)"),
         }
     }
 
-    fn to_str(self) -> Option<&'static str> {
+    fn to_str(self) -> &'static str {
         match self {
-            Self::Step => None,
-            Self::Linear => Some("linear"),
-            Self::Smooth => Some("smooth"),
+            Self::Trapezoid => "trapezoid",
+            Self::RaisedCosine => "cosine",
         }
     }
 }
 
+/// Parameter groups that can override a keyframe's curve, as in `>smooth color>step`.
+const CURVE_FIELDS: [&str; 11] =
+    ["freq", "tone", "vol", "duty", "smooth", "vibrato", "autopan", "pan", "swell", "noise", "color"];
+const FREQ_FIELD: usize = 0;
+const DUTY_FIELD: usize = 3;
+
+/// Curves for individual parameter groups, overriding a keyframe's main curve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct FieldCurves([Option<Curve>; CURVE_FIELDS.len()]);
+
+impl FieldCurves {
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(Option::is_none)
+    }
+
+    fn set(&mut self, field: &str, curve: Curve) -> Result<()> {
+        let Some(index) = CURVE_FIELDS.iter().position(|&f| f == field) else {
+            bail!("unknown curve field '{field}' (expected: {})", CURVE_FIELDS.join(", "));
+        };
+        self.0[index] = Some(curve);
+        Ok(())
+    }
+
+    /// Curve of the field at `index`, or `default` without an override.
+    #[inline]
+    fn get(&self, index: usize, default: Curve) -> Curve {
+        self.0[index].unwrap_or(default)
+    }
+
+    /// Overridden fields and their curves, in source order.
+    fn iter(&self) -> impl Iterator<Item = (&'static str, Curve)> + '_ {
+        CURVE_FIELDS
+            .iter()
+            .zip(self.0)
+            .filter_map(|(&field, curve)| Some((field, curve?)))
+    }
+}
+
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Params
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
 /// Parameters at a point in time.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Params {
     /// Entrainment frequency in Hz (pulse/beat rate).
     pub freq: f64,
@@ -83,10 +222,32 @@ pub struct Params {
     pub vol: f32,
     /// Duty cycle for isochronic tones [0.01, 0.99].
     pub duty: f32,
+    /// Pulse edge length in seconds (0 = 10% of the period).
+    pub smooth: f32,
+    /// Shape of each isochronic pulse.
+    pub env: EnvelopeShape,
+    /// Carrier vibrato depth in Hz (0 = off).
+    pub vibrato_depth: f32,
+    /// Carrier vibrato rate in Hz.
+    pub vibrato_rate: f32,
+    /// Stereo autopan rate in Hz.
+    pub autopan_rate: f32,
+    /// Stereo autopan depth [0, 1] (0 = off, 1 = hard left to hard right).
+    pub autopan_depth: f32,
+    /// Stereo position of isochronic pulses [-1, 1] (0 = center).
+    pub pan: f32,
+    /// Volume swell rate in Hz.
+    pub swell_rate: f32,
+    /// Volume swell depth [0, 1] (0 = off, 1 = fades fully out at each trough).
+    pub swell_depth: f32,
+    /// Pink noise level [0, 1] relative to the volume (0 = off).
+    pub noise: f32,
     /// Visual color when pulse is on.
     pub on: Color,
     /// Visual color when pulse is off.
     pub off: Color,
+    /// Color the on color passes through halfway into this keyframe.
+    pub via: Option<Color>,
 }
 
 impl Default for Params {
@@ -96,8 +257,19 @@ impl Default for Params {
             tone: 200.0,
             vol: 0.5,
             duty: 0.5,
+            smooth: 0.0,
+            env: EnvelopeShape::Trapezoid,
+            vibrato_depth: 0.0,
+            vibrato_rate: 0.0,
+            autopan_rate: 0.0,
+            autopan_depth: 0.0,
+            pan: 0.0,
+            swell_rate: 0.0,
+            swell_depth: 0.0,
+            noise: 0.0,
             on: Color::WHITE,
             off: Color::BLACK,
+            via: None,
         }
     }
 }
@@ -115,9 +287,117 @@ impl Params {
             tone: a.tone * inv32 + b.tone * t32,
             vol: a.vol * inv32 + b.vol * t32,
             duty: a.duty * inv32 + b.duty * t32,
-            on: Color::lerp(a.on, b.on, t32),
+            smooth: a.smooth * inv32 + b.smooth * t32,
+            // Can't be blended, so it switches once the transition completes
+            env: if t < 1.0 { a.env } else { b.env },
+            vibrato_depth: a.vibrato_depth * inv32 + b.vibrato_depth * t32,
+            vibrato_rate: a.vibrato_rate * inv32 + b.vibrato_rate * t32,
+            autopan_rate: a.autopan_rate * inv32 + b.autopan_rate * t32,
+            autopan_depth: a.autopan_depth * inv32 + b.autopan_depth * t32,
+            pan: a.pan * inv32 + b.pan * t32,
+            swell_rate: a.swell_rate * inv32 + b.swell_rate * t32,
+            swell_depth: a.swell_depth * inv32 + b.swell_depth * t32,
+            noise: a.noise * inv32 + b.noise * t32,
+            on: match b.via {
+                Some(via) if t32 < 0.5 => Color::lerp(a.on, via, t32 * 2.0),
+                Some(via) => Color::lerp(via, b.on, t32 * 2.0 - 1.0),
+                None => Color::lerp(a.on, b.on, t32),
+            },
             off: Color::lerp(a.off, b.off, t32),
+            via: b.via,
+        }
+    }
+
+    /// Interpolate `progress` through a transition shaped by `curve`, with
+    /// `fields` overriding the curve of individual parameter groups.
+    #[inline]
+    pub fn lerp_curves(a: &Self, b: &Self, progress: f64, curve: Curve, fields: &FieldCurves) -> Self {
+        let mut params = Self::lerp(a, b, curve.apply(progress));
+
+        for (index, field_curve) in fields.0.iter().enumerate() {
+            let Some(field_curve) = field_curve else {
+                continue;
+            };
+            let p = Self::lerp(a, b, field_curve.apply(progress));
+            match index {
+                0 => params.freq = p.freq,
+                1 => params.tone = p.tone,
+                2 => params.vol = p.vol,
+                3 => params.duty = p.duty,
+                4 => params.smooth = p.smooth,
+                5 => (params.vibrato_depth, params.vibrato_rate) = (p.vibrato_depth, p.vibrato_rate),
+                6 => (params.autopan_rate, params.autopan_depth) = (p.autopan_rate, p.autopan_depth),
+                7 => params.pan = p.pan,
+                8 => (params.swell_rate, params.swell_depth) = (p.swell_rate, p.swell_depth),
+                9 => params.noise = p.noise,
+                _ => (params.on, params.off) = (p.on, p.off),
+            }
+        }
+        params
+    }
+}
+
+/// A parameter that differs between two keyframes, with values in source notation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamChange {
+    pub key: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+impl Params {
+    /// Parameters that differ visibly from `prev`, in source order.
+    ///
+    /// A `via` color is always reported, since it only applies to its own keyframe.
+    pub fn changes_from(&self, prev: &Self) -> Vec<ParamChange> {
+        let (p, mut changes) = (self, Vec::new());
+        let mut push = |changed: bool, key, from: String, to: String| {
+            if changed {
+                changes.push(ParamChange { key, from, to });
+            }
+        };
+
+        push((p.freq - prev.freq).abs() > 0.001, "freq", format!("{:.2}", prev.freq), format!("{:.2}", p.freq));
+        push((p.tone - prev.tone).abs() > 0.1, "tone", format!("{:.0}", prev.tone), format!("{:.0}", p.tone));
+        push((p.vol - prev.vol).abs() > 0.001, "vol", format!("{:.2}", prev.vol), format!("{:.2}", p.vol));
+        push((p.duty - prev.duty).abs() > 0.001, "duty", format!("{:.2}", prev.duty), format!("{:.2}", p.duty));
+        push(
+            (p.smooth - prev.smooth).abs() > 0.0001,
+            "smooth",
+            format!("{:.3}", prev.smooth),
+            format!("{:.3}", p.smooth),
+        );
+        push(p.env != prev.env, "env", prev.env.to_str().into(), p.env.to_str().into());
+        push(
+            (p.vibrato_depth - prev.vibrato_depth).abs() > 0.001
+                || (p.vibrato_rate - prev.vibrato_rate).abs() > 0.001,
+            "vibrato",
+            format!("{:.2}:{:.2}", prev.vibrato_depth, prev.vibrato_rate),
+            format!("{:.2}:{:.2}", p.vibrato_depth, p.vibrato_rate),
+        );
+        push(
+            (p.autopan_rate - prev.autopan_rate).abs() > 0.001
+                || (p.autopan_depth - prev.autopan_depth).abs() > 0.001,
+            "autopan",
+            format!("{:.2}:{:.2}", prev.autopan_rate, prev.autopan_depth),
+            format!("{:.2}:{:.2}", p.autopan_rate, p.autopan_depth),
+        );
+        push((p.pan - prev.pan).abs() > 0.001, "pan", format!("{:.2}", prev.pan), format!("{:.2}", p.pan));
+        push(
+            (p.swell_rate - prev.swell_rate).abs() > 0.001
+                || (p.swell_depth - prev.swell_depth).abs() > 0.001,
+            "swell",
+            format!("{:.3}:{:.2}", prev.swell_rate, prev.swell_depth),
+            format!("{:.3}:{:.2}", p.swell_rate, p.swell_depth),
+        );
+        push((p.noise - prev.noise).abs() > 0.001, "noise", format!("{:.2}", prev.noise), format!("{:.2}", p.noise));
+        push(p.on != prev.on, "on", hex(prev.on), hex(p.on));
+        push(p.off != prev.off, "off", hex(prev.off), hex(p.off));
+        if let Some(via) = p.via {
+            push(true, "via", hex(prev.on), hex(via));
         }
+
+        changes
     }
 }
 
@@ -126,42 +406,128 @@ impl Params {
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
 /// Session-level settings (set only at program start).
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     /// Use binaural beats instead of isochronic tones.
     pub binaural: bool,
+    /// Use monaural beats (both tones mixed into every channel) instead of isochronic tones.
+    pub monaural: bool,
     /// Disable visual output (audio only).
     pub headless: bool,
+    /// Drive visual brightness with the audio envelope instead of a hard on/off.
+    pub linked: bool,
+    /// Keep the pulse on for `1 - duty` of each period instead of `duty`.
+    pub invert_duty: bool,
+    /// Preferred playback sample rate in Hz (default: the device's rate).
+    pub sample_rate: Option<u32>,
+    /// Curve used by keyframes that omit a `>curve` directive.
+    pub default_curve: Curve,
+    /// Starting on color of binaural programs whose first keyframe sets none.
+    pub binaural_on: Option<Color>,
+    /// Starting on color of isochronic programs whose first keyframe sets none.
+    pub isochronic_on: Option<Color>,
 }
 
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Program
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
+/// Silence between plays of a [`Program::repeated`] program, in seconds.
+pub const REPEAT_GAP_SECS: f64 = 2.0;
+
+/// Fade to silence at the start of each gap, in seconds.
+const REPEAT_FADE_SECS: f64 = 0.5;
+
+/// Highest flash frequency times on/off luminance change that
+/// [`Program::validate`] accepts without a warning, e.g. full black-to-white at 25 Hz.
+const FLASH_INTENSITY_LIMIT: f64 = 25.0;
+
+/// Highest freq that [`Program::validate`] treats as entrainment. Above it a
+/// pulse is shorter than an audio buffer and the display can't show each flash,
+/// so the result is amplitude modulation of the carrier rather than discrete pulses.
+const MAX_ENTRAINMENT_FREQ: f64 = 100.0;
+
+/// Flash rates most likely to trigger photosensitive seizures, in Hz.
+pub const FLICKER_DANGER_BAND: std::ops::RangeInclusive<f64> = 15.0..=25.0;
+
+/// Points checked along each transition by [`Program::safety_warnings`].
+const SAFETY_SAMPLES: u32 = 256;
+
+/// Relative luminance [0, 1] of a color (Rec. 709 weights on linear light).
+fn luminance(color: Color) -> f64 {
+    let [r, g, b] = color.to_linear();
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Progress through a keyframe transition, as reported by
+/// [`Program::params_at_detailed`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransitionInfo {
+    /// Curve of the transition.
+    pub curve: Curve,
+    /// Normalized progress through the transition [0, 1).
+    pub progress: f64,
+    /// Time of the keyframe the transition starts from.
+    pub from_time: f64,
+    /// Time of the keyframe the transition ends at.
+    pub to_time: f64,
+}
+
 /// A single keyframe in the program timeline.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Keyframe {
     time: f64,
     params: Params,
+    #[serde(default)]
     curve: Curve,
+    /// Curves of parameter groups that don't follow `curve`.
+    #[serde(default, skip_serializing_if = "FieldCurves::is_empty")]
+    curves: FieldCurves,
+    /// Author's note; documentation only, with no effect on playback.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    /// Section name for skipping parts of a program with [`Program::without_tags`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
 }
 
 /// An entrainment program with keyframes and settings.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Program {
     keyframes: Vec<Keyframe>,
+    #[serde(default)]
     pub settings: Settings,
+    #[serde(with = "endless_as_null")]
     pub duration: f64,
+    /// Problems found while parsing that don't prevent playback.
+    #[serde(skip)]
+    pub warnings: Vec<String>,
     /// Cache for accelerating `params_at` lookups.
+    #[serde(skip)]
     cached_index: AtomicUsize,
 }
 
+/// JSON has no infinity, so endless programs store a `null` duration.
+mod endless_as_null {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        duration.is_finite().then_some(*duration).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::INFINITY))
+    }
+}
+
 impl Clone for Program {
     fn clone(&self) -> Self {
         Self {
             keyframes: self.keyframes.clone(),
             settings: self.settings,
             duration: self.duration,
+            warnings: self.warnings.clone(),
             cached_index: AtomicUsize::new(0),
         }
     }
@@ -173,6 +539,11 @@ impl Program {
         let mut keyframes: Vec<Keyframe> = Vec::new();
         let mut settings = Settings::default();
         let mut current = Params::default();
+        let mut warnings = Vec::new();
+        let mut rates: Vec<RateRamp> = Vec::new();
+        let mut rate_line = 0;
+        // Repeat count, index of the first keyframe and line of the open loop
+        let mut open_loop: Option<(u32, usize, usize)> = None;
 
         for (line_idx, line) in source.lines().enumerate() {
             let line_num = line_idx + 1;
@@ -183,9 +554,62 @@ impl Program {
                 continue;
             }
 
-            let is_first = keyframes.is_empty();
-            let kf = parse_line(line, &mut current, &mut settings, is_first)
+            // Rate directives resolve against the next keyframe
+            if let Some(directive) = line.strip_prefix("@rate") {
+                if keyframes.is_empty() {
+                    bail!("line {line_num}: @rate must follow a keyframe");
+                }
+                rates.push(RateRamp::parse(directive).with_context(|| format!("line {line_num}"))?);
+                rate_line = line_num;
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix("loop ") {
+                let count = header
+                    .strip_suffix('{')
+                    .with_context(|| format!("line {line_num}: loop must be 'loop <count> {{'"))?
+                    .trim()
+                    .parse::<u32>()
+                    .with_context(|| format!("line {line_num}: invalid loop count"))?;
+                if count == 0 {
+                    bail!("line {line_num}: loop count must be positive");
+                }
+                if open_loop.is_some() {
+                    bail!("line {line_num}: loops can't be nested");
+                }
+                if keyframes.is_empty() {
+                    bail!("line {line_num}: loop must follow a keyframe to start from");
+                }
+                open_loop = Some((count, keyframes.len(), line_num));
+                continue;
+            }
+
+            if line == "}" {
+                let (count, start, _) =
+                    open_loop.take().with_context(|| format!("line {line_num}: '}}' without a loop"))?;
+                if !rates.is_empty() {
+                    bail!("line {rate_line}: @rate needs a following keyframe inside the loop");
+                }
+                if start == keyframes.len() {
+                    bail!("line {line_num}: loop contains no keyframes");
+                }
+                let block = keyframes[start..].to_vec();
+                let length = block[block.len() - 1].time - keyframes[start - 1].time;
+                for iteration in 1..count {
+                    let offset = f64::from(iteration) * length;
+                    keyframes.extend(block.iter().map(|kf| Keyframe { time: kf.time + offset, ..kf.clone() }));
+                }
+                continue;
+            }
+
+            let prev_time = keyframes.last().map(|kf| kf.time);
+            if prev_time.is_some() && rates.is_empty() && tokenize(line)?.len() == 1 {
+                bail!("line {line_num}: keyframe must set at least one parameter or use 'hold'");
+            }
+            let mut line_warnings = Vec::new();
+            let mut kf = parse_line(line, &mut current, &mut settings, prev_time, &mut line_warnings)
                 .with_context(|| format!("line {line_num}"))?;
+            warnings.extend(line_warnings.into_iter().map(|w| format!("line {line_num}: {w}")));
 
             // Validate timestamp ordering
             if let Some(last) = keyframes.last() {
@@ -196,9 +620,38 @@ impl Program {
                 bail!("line {line_num}: first keyframe must be at 00:00");
             }
 
+            if let Some(last) = keyframes.last() && !rates.is_empty() {
+                let elapsed = kf.time - last.time;
+                let tokens = tokenize(line)?;
+                let explicit = |param: &str| {
+                    tokens
+                        .iter()
+                        .skip(1)
+                        .any(|t| t.split_once('=').is_some_and(|(key, _)| key == param))
+                };
+                for rate in rates.drain(..) {
+                    if !explicit(&rate.param) {
+                        rate.apply(&mut kf.params, elapsed)
+                            .with_context(|| format!("line {line_num}"))?;
+                    }
+                }
+                if !tokens.iter().any(|t| t.starts_with('>')) {
+                    kf.curve = Curve::Linear;
+                }
+                current = kf.params;
+            }
+
             keyframes.push(kf);
         }
 
+        if !rates.is_empty() {
+            bail!("line {rate_line}: @rate needs a following keyframe");
+        }
+
+        if let Some((_, _, line_num)) = open_loop {
+            bail!("line {line_num}: loop is never closed with '}}'");
+        }
+
         if keyframes.is_empty() {
             bail!("program contains no keyframes");
         }
@@ -207,18 +660,279 @@ impl Program {
 
         let duration = if last_time > 0.0 { last_time } else { f64::INFINITY };
 
-        Ok(Self {
+        let mut program = Self {
             keyframes,
             settings,
             duration,
+            warnings,
+            cached_index: AtomicUsize::new(0),
+        };
+        let semantic = program.validate();
+        program.warnings.extend(semantic);
+        Ok(program)
+    }
+
+    /// Check for problems that don't prevent playback: parameters the program's
+    /// mode ignores, flashing that may be uncomfortable to watch, and
+    /// frequencies too high to entrain.
+    pub fn validate(&self) -> Vec<String> {
+        let mut warnings = self.ignored_param_warnings();
+        warnings.extend(self.flash_intensity_warnings());
+        warnings.extend(self.high_freq_warnings());
+        warnings
+    }
+
+    /// Binaural and monaural beats have no pulse envelope, so duty, smooth,
+    /// env, vibrato, autopan and pan do nothing to the audio there.
+    fn ignored_param_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let mode = match (self.settings.binaural, self.settings.monaural) {
+            (true, _) => "binaural",
+            (false, true) => "monaural",
+            (false, false) => return warnings,
+        };
+
+        let mut prev = Params {
+            smooth: 0.0,
+            env: EnvelopeShape::Trapezoid,
+            vibrato_depth: 0.0,
+            autopan_depth: 0.0,
+            pan: 0.0,
+            ..self.keyframes[0].params
+        };
+        for kf in &self.keyframes {
+            let p = &kf.params;
+            let ignored = [
+                ("duty", p.duty != prev.duty),
+                ("smooth", p.smooth != prev.smooth),
+                ("env", p.env != prev.env),
+                ("vibrato", p.vibrato_depth != prev.vibrato_depth || p.vibrato_rate != prev.vibrato_rate),
+                ("autopan", p.autopan_depth != prev.autopan_depth || p.autopan_rate != prev.autopan_rate),
+                ("pan", p.pan != prev.pan),
+            ];
+            for (name, _) in ignored.iter().filter(|(_, changed)| *changed) {
+                warnings.push(format!(
+                    "{}: '{name}' has no effect on {mode} audio",
+                    format_timestamp(kf.time)
+                ));
+            }
+            prev = *p;
+        }
+
+        warnings
+    }
+
+    /// Warn where the flash rate times the luminance swing between the on and
+    /// off colors exceeds [`FLASH_INTENSITY_LIMIT`], once per stretch above it.
+    fn flash_intensity_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.settings.headless {
+            return warnings;
+        }
+
+        let mut above = false;
+        for kf in &self.keyframes {
+            let p = &kf.params;
+            let contrast = (luminance(p.on) - luminance(p.off)).abs();
+            let intensity = contrast * p.freq;
+            if intensity > FLASH_INTENSITY_LIMIT && !above {
+                warnings.push(format!(
+                    "{}: flashing at {:.1} Hz with a luminance change of {contrast:.2} may be uncomfortable \
+                     (intensity {intensity:.1}, comfortable up to {FLASH_INTENSITY_LIMIT:.0})",
+                    format_timestamp(kf.time),
+                    p.freq
+                ));
+            }
+            above = intensity > FLASH_INTENSITY_LIMIT;
+        }
+
+        warnings
+    }
+
+    /// Warn where freq exceeds [`MAX_ENTRAINMENT_FREQ`], once per stretch above it.
+    fn high_freq_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let mut above = false;
+        for kf in &self.keyframes {
+            let freq = kf.params.freq;
+            if freq > MAX_ENTRAINMENT_FREQ && !above {
+                warnings.push(format!(
+                    "{}: freq {freq:.1} Hz is above {MAX_ENTRAINMENT_FREQ:.0} Hz, where pulses blur into \
+                     amplitude modulation (AM) of the tone and flashes can't be shown individually",
+                    format_timestamp(kf.time)
+                ));
+            }
+            above = freq > MAX_ENTRAINMENT_FREQ;
+        }
+
+        warnings
+    }
+
+    /// Warn where the flash rate enters [`FLICKER_DANGER_BAND`], once per
+    /// stretch inside it.
+    ///
+    /// Transitions are sampled along their curve, so a ramp that passes
+    /// through the band is caught even if neither keyframe is in it.
+    /// Headless programs and stretches with identical on and off colors
+    /// don't flash, so they are never flagged.
+    pub fn safety_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.settings.headless {
+            return warnings;
+        }
+
+        let mut inside = false;
+        let mut check = |time: f64, p: &Params| {
+            let flashing = p.on != p.off && FLICKER_DANGER_BAND.contains(&p.freq);
+            if flashing && !inside {
+                warnings.push(format!(
+                    "{}: flashing at {:.1} Hz is within the {:.0}-{:.0} Hz band most likely to trigger \
+                     photosensitive seizures",
+                    format_timestamp(time),
+                    p.freq,
+                    FLICKER_DANGER_BAND.start(),
+                    FLICKER_DANGER_BAND.end()
+                ));
+            }
+            inside = flashing;
+        };
+
+        check(0.0, &self.keyframes[0].params);
+        for pair in self.keyframes.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            for i in 1..=SAFETY_SAMPLES {
+                let progress = f64::from(i) / f64::from(SAFETY_SAMPLES);
+                let p = Params::lerp_curves(&from.params, &to.params, progress, to.curve, &to.curves);
+                check(from.time + (to.time - from.time) * progress, &p);
+            }
+        }
+
+        warnings
+    }
+
+    /// Chain `count` plays of a finite program, fading to silence between them.
+    ///
+    /// Each play after the first starts [`REPEAT_GAP_SECS`] after the previous
+    /// one ends, so the repeated program lasts `count` durations plus the gaps.
+    pub fn repeated(&self, count: u32) -> Result<Self> {
+        anyhow::ensure!(self.duration.is_finite(), "only programs with an end can be repeated");
+        anyhow::ensure!(count > 0, "repeat count must be positive");
+
+        let period = self.duration + REPEAT_GAP_SECS;
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        let mut keyframes = Vec::with_capacity(self.keyframes.len() * count as usize);
+
+        for iteration in 0..count {
+            let offset = f64::from(iteration) * period;
+            if iteration > 0 {
+                keyframes.push(Keyframe {
+                    time: offset - REPEAT_GAP_SECS + REPEAT_FADE_SECS,
+                    params: Params { vol: 0.0, via: None, ..last.params },
+                    curve: Curve::Linear,
+                    curves: FieldCurves::default(),
+                    note: None,
+                    tag: None,
+                });
+            }
+            keyframes.extend(self.keyframes.iter().enumerate().map(|(i, kf)| Keyframe {
+                time: kf.time + offset,
+                // Restart cleanly instead of ramping through the gap
+                curve: if i == 0 { Curve::Step } else { kf.curve },
+                ..kf.clone()
+            }));
+        }
+
+        Ok(Self {
+            keyframes,
+            settings: self.settings,
+            duration: f64::from(count) * period - REPEAT_GAP_SECS,
+            warnings: self.warnings.clone(),
+            cached_index: AtomicUsize::new(0),
+        })
+    }
+
+    /// Remove the keyframes tagged with any of `tags`, closing the gaps they leave.
+    ///
+    /// A tagged keyframe takes the transition leading into it along with it,
+    /// so later keyframes move earlier by that much. If the start is skipped,
+    /// playback begins from the state at the end of the skipped section.
+    pub fn without_tags(&self, tags: &[&str]) -> Result<Self> {
+        let skipped = |kf: &Keyframe| kf.tag.as_deref().is_some_and(|t| tags.contains(&t));
+
+        let mut keyframes: Vec<Keyframe> = Vec::with_capacity(self.keyframes.len());
+        let mut shift = 0.0;
+        let mut prev_time = 0.0;
+        let mut skipped_state = None;
+
+        for kf in &self.keyframes {
+            if skipped(kf) {
+                shift += kf.time - prev_time;
+                skipped_state = Some(kf.params);
+            } else {
+                if keyframes.is_empty() && kf.time - shift > 0.0 {
+                    // Keep the first keyframe at 00:00
+                    keyframes.push(Keyframe {
+                        time: 0.0,
+                        params: skipped_state.unwrap_or(self.keyframes[0].params),
+                        curve: Curve::Step,
+                        curves: FieldCurves::default(),
+                        note: None,
+                        tag: None,
+                    });
+                }
+                keyframes.push(Keyframe { time: kf.time - shift, ..kf.clone() });
+            }
+            prev_time = kf.time;
+        }
+
+        if keyframes.is_empty() {
+            bail!("skipping tags {tags:?} removes every keyframe");
+        }
+
+        let last_time = keyframes[keyframes.len() - 1].time;
+        Ok(Self {
+            keyframes,
+            settings: self.settings,
+            duration: if last_time > 0.0 { last_time } else { f64::INFINITY },
+            warnings: self.warnings.clone(),
             cached_index: AtomicUsize::new(0),
         })
     }
 
+    /// Serialize the keyframes, settings and duration to JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("programs always serialize")
+    }
+
+    /// Reconstruct a program from [`Program::to_json`] output.
+    ///
+    /// Parameters missing from a keyframe take their defaults, as in `Params::default`.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut program: Self = serde_json::from_str(json).context("invalid program JSON")?;
+
+        let Some(first) = program.keyframes.first() else {
+            bail!("program has no keyframes");
+        };
+        if first.time != 0.0 {
+            bail!("first keyframe must be at 00:00, not {:.1}s", first.time);
+        }
+        if let Some(pair) = program.keyframes.windows(2).find(|pair| pair[1].time < pair[0].time) {
+            bail!("keyframe at {:.1}s comes after one at {:.1}s", pair[1].time, pair[0].time);
+        }
+
+        program.warnings = program.validate();
+        Ok(program)
+    }
+
     /// Load a program from a file.
+    ///
+    /// Files with a `.json` extension are read with [`Program::from_json`].
     pub fn load(path: &Path) -> Result<Self> {
         let source = std::fs::read_to_string(path)
             .with_context(|| format!("reading '{}'", path.display()))?;
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            return Self::from_json(&source).with_context(|| format!("parsing '{}'", path.display()));
+        }
         Self::parse(&source).with_context(|| format!("parsing '{}'", path.display()))
     }
 
@@ -229,29 +943,155 @@ impl Program {
                 time: 0.0,
                 params,
                 curve: Curve::Step,
+                curves: FieldCurves::default(),
+                note: None,
+                tag: None,
             }],
             settings,
             duration: f64::INFINITY,
+            warnings: Vec::new(),
+            cached_index: AtomicUsize::new(0),
+        }
+    }
+
+    /// Create an infinite program that holds `from` until `at`, then ramps
+    /// linearly to `to` over `secs` and holds it from then on.
+    pub fn ramp(from: Params, to: Params, at: f64, secs: f64, settings: Settings) -> Self {
+        let mut keyframes = vec![Keyframe {
+            time: 0.0,
+            params: from,
+            curve: Curve::Step,
+            curves: FieldCurves::default(),
+            note: None,
+            tag: None,
+        }];
+        if at > 0.0 {
+            keyframes.push(Keyframe {
+                time: at,
+                params: from,
+                curve: Curve::Step,
+                curves: FieldCurves::default(),
+                note: None,
+                tag: None,
+            });
+        }
+        keyframes.push(Keyframe {
+            time: at.max(0.0) + secs.max(1e-6),
+            params: to,
+            curve: Curve::Linear,
+            curves: FieldCurves::default(),
+            note: None,
+            tag: None,
+        });
+
+        Self {
+            keyframes,
+            settings,
+            duration: f64::INFINITY,
+            warnings: Vec::new(),
             cached_index: AtomicUsize::new(0),
         }
     }
 
+    /// Switch from this program to `next` at playback time `at`, keeping the
+    /// current position.
+    ///
+    /// Holds what plays at `at`, ramps linearly to where `next` is `secs` later,
+    /// then follows `next` from there. Settings and duration come from `next`.
+    #[cfg_attr(not(feature = "watch"), allow(dead_code))]
+    pub fn crossfade_to(&self, next: &Self, at: f64, secs: f64) -> Self {
+        // params_at applies invert_duty; keyframes store the duty as written
+        let raw = |program: &Self, time: f64| {
+            let mut params = program.params_at_uncached(time);
+            if program.settings.invert_duty {
+                params.duty = 1.0 - params.duty;
+            }
+            Params { via: None, ..params }
+        };
+        let from = raw(self, at);
+        let end = at.max(0.0) + secs.max(1e-6);
+
+        let mut program = Self::ramp(from, raw(next, end), at, secs, next.settings);
+        program.keyframes.extend(next.keyframes.iter().filter(|kf| kf.time > end).cloned());
+        program.duration = next.duration;
+        program.warnings = next.warnings.clone();
+        program
+    }
+
     /// Get interpolated parameters at the given time.
     ///
     /// Uses a cache to accelerate sequential lookups (O(1) for forward playback).
+    /// The cache is shared, so it only helps a single reader walking forward:
+    /// the audio thread. Other threads should use [`Self::params_at_uncached`]
+    /// instead of evicting its segment on every call.
+    ///
+    /// With `invert_duty`, the returned duty is already inverted so audio and
+    /// visuals agree on when the pulse is on.
     #[inline]
     pub fn params_at(&self, time: f64) -> Params {
-        let n = self.keyframes.len();
+        self.params_in(time, self.locate(time))
+    }
 
-        // Fast paths for common cases
-        if n == 1 {
-            return self.keyframes[0].params;
-        }
-        if time <= 0.0 {
-            return self.keyframes[0].params;
+    /// Get interpolated parameters at the given time without touching the
+    /// lookup cache.
+    ///
+    /// Same result as [`Self::params_at`], with an O(log n) search on every
+    /// call. Safe to call from any number of threads alongside the audio thread.
+    pub fn params_at_uncached(&self, time: f64) -> Params {
+        self.params_in(time, self.locate_uncached(time))
+    }
+
+    /// Parameters at `time`, given the segment containing it.
+    #[inline]
+    fn params_in(&self, time: f64, segment: Option<(usize, f64)>) -> Params {
+        let mut params = match segment {
+            Some((idx, t)) => {
+                let from = &self.keyframes[idx - 1];
+                let to = &self.keyframes[idx];
+                Params::lerp_curves(&from.params, &to.params, t, to.curve, &to.curves)
+            }
+            None if time <= 0.0 => self.keyframes[0].params,
+            None => self.keyframes[self.keyframes.len() - 1].params,
+        };
+
+        if self.settings.invert_duty {
+            params.duty = 1.0 - params.duty;
         }
-        if time >= self.duration {
-            return self.keyframes[n - 1].params;
+        params
+    }
+
+    /// Get interpolated parameters plus details of the transition in progress.
+    ///
+    /// The transition is `None` while parameters are holding, i.e. outside any
+    /// segment or inside a segment that ends in a step change. Doesn't use the
+    /// lookup cache, like [`Self::params_at_uncached`].
+    pub fn params_at_detailed(&self, time: f64) -> (Params, Option<TransitionInfo>) {
+        let segment = self.locate_uncached(time);
+        let params = self.params_in(time, segment);
+
+        let transition = segment.and_then(|(idx, t)| {
+            let from = &self.keyframes[idx - 1];
+            let to = &self.keyframes[idx];
+            let animated = to.curve != Curve::Step || to.curves.iter().any(|(_, c)| c != Curve::Step);
+            animated.then_some(TransitionInfo {
+                curve: to.curve,
+                progress: t,
+                from_time: from.time,
+                to_time: to.time,
+            })
+        });
+
+        (params, transition)
+    }
+
+    /// Find the segment containing `time`.
+    ///
+    /// Returns the index of the segment's end keyframe and the normalized
+    /// position within the segment, or `None` before the start or after the end.
+    #[inline]
+    fn locate(&self, time: f64) -> Option<(usize, f64)> {
+        if !self.within(time) {
+            return None;
         }
 
         // Try cached segment first (hot path for sequential access)
@@ -259,7 +1099,7 @@ impl Program {
 
         // Validate cache: check if time is in [keyframes[idx-1].time, keyframes[idx].time)
         let cache_valid = idx > 0
-            && idx < n
+            && idx < self.keyframes.len()
             && self.keyframes[idx - 1].time <= time
             && time < self.keyframes[idx].time;
 
@@ -269,7 +1109,25 @@ impl Program {
             self.cached_index.store(idx, Ordering::Relaxed);
         }
 
-        // Interpolate between keyframes[idx-1] and keyframes[idx]
+        Some(self.position_in(idx, time))
+    }
+
+    /// [`Self::locate`] by binary search alone, leaving the cache alone.
+    fn locate_uncached(&self, time: f64) -> Option<(usize, f64)> {
+        self.within(time)
+            .then(|| self.position_in(self.keyframes.partition_point(|k| k.time <= time), time))
+    }
+
+    /// Whether `time` falls strictly between the first and last keyframes.
+    #[inline]
+    fn within(&self, time: f64) -> bool {
+        let n = self.keyframes.len();
+        n > 1 && time > 0.0 && time < self.keyframes[n - 1].time
+    }
+
+    /// Normalized position of `time` in the segment ending at keyframe `idx`.
+    #[inline]
+    fn position_in(&self, idx: usize, time: f64) -> (usize, f64) {
         let from = &self.keyframes[idx - 1];
         let to = &self.keyframes[idx];
 
@@ -280,61 +1138,249 @@ impl Program {
             1.0
         };
 
-        Params::lerp(&from.params, &to.params, to.curve.apply(t))
+        (idx, t)
     }
 
-    /// Export the program back to source format.
-    pub fn to_source(&self) -> String {
-        let mut out = String::with_capacity(256);
-        out.push_str("// Entrainment Program\n");
+    /// Number of pulse cycles elapsed between time 0 and `time`.
+    ///
+    /// Integrates the frequency through each transition, so an engine that
+    /// starts mid-program can pick up the pulse phase it would have had.
+    pub fn pulse_cycles_at(&self, time: f64) -> f64 {
+        self.integrate(time, FREQ_FIELD, |p| p.freq)
+    }
 
-        for (i, kf) in self.keyframes.iter().enumerate() {
-            out.push_str(&format_timestamp(kf.time));
+    /// Total seconds the pulse is on over the whole program.
+    ///
+    /// Each cycle is on for `duty` of its period, so this is the duty cycle
+    /// integrated over the timeline. Infinite for programs without an end.
+    pub fn total_on_time(&self) -> f64 {
+        let on = self.integrate(self.duration, DUTY_FIELD, |p| f64::from(p.duty));
+        if self.settings.invert_duty {
+            self.duration - on
+        } else {
+            on
+        }
+    }
 
-            let p = &kf.params;
+    /// Integral of a parameter from time 0 to `time`, following each transition curve.
+    ///
+    /// `value` must be linear in the parameters, like a single field.
+    fn integrate(&self, time: f64, field: usize, value: impl Fn(&Params) -> f64) -> f64 {
+        let mut total = 0.0;
+
+        for pair in self.keyframes.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            if time <= from.time {
+                return total;
+            }
+            let span = to.time - from.time;
+            let t = ((time - from.time) / span).min(1.0);
+            let delta = value(&to.params) - value(&from.params);
+            let curve = to.curves.get(field, to.curve);
+            total += span * (value(&from.params) * t + delta * curve.integral(t));
+        }
 
-            if i == 0 {
-                // First keyframe: write all parameters
-                write!(
-                    out,
-                    " freq={:.2} tone={:.0} vol={:.2} duty={:.2}",
-                    p.freq, p.tone, p.vol, p.duty
-                ).unwrap();
-                write!(out, " on=#{:02X}{:02X}{:02X}", p.on.r, p.on.g, p.on.b).unwrap();
-                write!(out, " off=#{:02X}{:02X}{:02X}", p.off.r, p.off.g, p.off.b).unwrap();
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        total + (time - last.time).max(0.0) * value(&last.params)
+    }
 
-                if self.settings.binaural {
-                    out.push_str(" binaural");
-                }
-                if self.settings.headless {
-                    out.push_str(" headless");
-                }
-            } else {
-                // Subsequent keyframes: only write changed parameters
-                let prev = &self.keyframes[i - 1].params;
+    /// Indices of the keyframes passed when playback moves from `from` to `to` seconds.
+    pub fn keyframes_crossed(&self, from: f64, to: f64) -> std::ops::Range<usize> {
+        let start = self.keyframes.partition_point(|k| k.time <= from);
+        let end = self.keyframes.partition_point(|k| k.time <= to);
+        start..end.max(start)
+    }
 
-                if (p.freq - prev.freq).abs() > 0.001 {
-                    write!(out, " freq={:.2}", p.freq).unwrap();
-                }
-                if (p.tone - prev.tone).abs() > 0.1 {
-                    write!(out, " tone={:.0}", p.tone).unwrap();
-                }
-                if (p.vol - prev.vol).abs() > 0.001 {
-                    write!(out, " vol={:.2}", p.vol).unwrap();
+    /// Time of keyframe `index` and the parameters it changes from the one before.
+    pub fn keyframe_changes(&self, index: usize) -> (f64, Vec<ParamChange>) {
+        let prev = index.checked_sub(1).map_or_else(Params::default, |i| self.keyframes[i].params);
+        let kf = &self.keyframes[index];
+        (kf.time, kf.params.changes_from(&prev))
+    }
+
+    /// Times of all keyframes in seconds, in order.
+    pub fn keyframe_times(&self) -> impl Iterator<Item = f64> + '_ {
+        self.keyframes.iter().map(|k| k.time)
+    }
+
+    /// Describe the program for reading: settings, then what each keyframe changes.
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+
+        let length = if self.duration.is_finite() {
+            format!("{} long", format_timestamp(self.duration))
+        } else {
+            "runs until stopped".into()
+        };
+        writeln!(out, "Program: {} keyframes, {length}", self.keyframes.len()).unwrap();
+
+        let s = &self.settings;
+        let mut settings: Vec<String> = [
+            (s.binaural, "binaural"),
+            (s.monaural, "monaural"),
+            (s.headless, "headless"),
+            (s.linked, "linked"),
+            (s.invert_duty, "invert_duty"),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| name.to_owned())
+        .collect();
+        if let Some(rate) = s.sample_rate {
+            settings.push(format!("samplerate={rate}"));
+        }
+        if s.default_curve != Curve::Step {
+            settings.push(format!("default_curve={}", s.default_curve.to_str()));
+        }
+        if let Some(color) = s.binaural_on {
+            settings.push(format!("binaural_on={}", hex(color)));
+        }
+        if let Some(color) = s.isochronic_on {
+            settings.push(format!("isochronic_on={}", hex(color)));
+        }
+        if settings.is_empty() {
+            settings.push("none".into());
+        }
+        writeln!(out, "Settings: {}", settings.join(", ")).unwrap();
+
+        for i in 0..self.keyframes.len() {
+            writeln!(out, "{}", format_timestamp(self.keyframes[i].time)).unwrap();
+            for line in self.describe_keyframe(i) {
+                writeln!(out, "  {line}").unwrap();
+            }
+        }
+
+        out
+    }
+
+    /// Lines describing how keyframe `index` is reached and what it changes.
+    fn describe_keyframe(&self, index: usize) -> Vec<String> {
+        let kf = &self.keyframes[index];
+        let mut lines = Vec::new();
+
+        if index == 0 {
+            lines.push("start".into());
+            let changes = kf.params.changes_from(&Params::default());
+            if changes.is_empty() {
+                lines.push("  all parameters at defaults".into());
+            }
+            lines.extend(changes.into_iter().map(|c| format!("  {} = {}", c.key, c.to)));
+        } else {
+            let span = kf.time - self.keyframes[index - 1].time;
+            let mut line = match kf.curve {
+                Curve::Step => format!("step after {span:.1}s"),
+                curve => format!("{} over {span:.1}s", curve.to_str()),
+            };
+            let overrides: Vec<String> = kf.curves.iter().map(|(f, c)| format!("{f} {}", c.to_str())).collect();
+            if !overrides.is_empty() {
+                write!(line, " ({})", overrides.join(", ")).unwrap();
+            }
+            lines.push(line);
+            let changes = kf.params.changes_from(&self.keyframes[index - 1].params);
+            if changes.is_empty() {
+                lines.push("  no changes".into());
+            }
+            lines.extend(changes.into_iter().map(|c| format!("  {} {} -> {}", c.key, c.from, c.to)));
+        }
+
+        if let Some(note) = &kf.note {
+            lines.push(format!("  note: {note}"));
+        }
+        lines
+    }
+
+    /// Export the program back to source format.
+    pub fn to_source(&self) -> String {
+        let mut out = String::with_capacity(256);
+        out.push_str("// Entrainment Program\n");
+
+        for (i, kf) in self.keyframes.iter().enumerate() {
+            out.push_str(&format_timestamp(kf.time));
+            let line_start = out.len();
+
+            let p = &kf.params;
+
+            if i == 0 {
+                // First keyframe: write all parameters
+                write!(
+                    out,
+                    " freq={:.2} tone={:.0} vol={:.2} duty={:.2}",
+                    p.freq, p.tone, p.vol, p.duty
+                ).unwrap();
+                if p.smooth > 0.0 {
+                    write!(out, " smooth={:.3}", p.smooth).unwrap();
+                }
+                if p.env != EnvelopeShape::Trapezoid {
+                    write!(out, " env={}", p.env.to_str()).unwrap();
                 }
-                if (p.duty - prev.duty).abs() > 0.001 {
-                    write!(out, " duty={:.2}", p.duty).unwrap();
+                if p.vibrato_depth > 0.0 {
+                    write!(out, " vibrato={:.2}:{:.2}", p.vibrato_depth, p.vibrato_rate).unwrap();
                 }
-                if p.on != prev.on {
-                    write!(out, " on=#{:02X}{:02X}{:02X}", p.on.r, p.on.g, p.on.b).unwrap();
+                if p.autopan_depth > 0.0 {
+                    write!(out, " autopan={:.2}:{:.2}", p.autopan_rate, p.autopan_depth).unwrap();
                 }
-                if p.off != prev.off {
-                    write!(out, " off=#{:02X}{:02X}{:02X}", p.off.r, p.off.g, p.off.b).unwrap();
+                if p.pan != 0.0 {
+                    write!(out, " pan={:.2}", p.pan).unwrap();
                 }
+                if p.swell_depth > 0.0 {
+                    write!(out, " swell={:.3}:{:.2}", p.swell_rate, p.swell_depth).unwrap();
+                }
+                if p.noise > 0.0 {
+                    write!(out, " noise={:.2}", p.noise).unwrap();
+                }
+                write!(out, " on=#{:02X}{:02X}{:02X}", p.on.r, p.on.g, p.on.b).unwrap();
+                write!(out, " off=#{:02X}{:02X}{:02X}", p.off.r, p.off.g, p.off.b).unwrap();
 
-                if let Some(curve_str) = kf.curve.to_str() {
-                    write!(out, " >{curve_str}").unwrap();
+                if self.settings.binaural {
+                    out.push_str(" binaural");
+                }
+                if self.settings.monaural {
+                    out.push_str(" monaural");
+                }
+                if self.settings.headless {
+                    out.push_str(" headless");
+                }
+                if self.settings.linked {
+                    out.push_str(" linked");
+                }
+                if self.settings.invert_duty {
+                    out.push_str(" invert_duty");
+                }
+                if let Some(rate) = self.settings.sample_rate {
+                    write!(out, " samplerate={rate}").unwrap();
+                }
+                if self.settings.default_curve != Curve::Step {
+                    write!(out, " default_curve={}", self.settings.default_curve.to_str()).unwrap();
+                }
+                if let Some(color) = self.settings.binaural_on {
+                    write!(out, " binaural_on={}", hex(color)).unwrap();
                 }
+                if let Some(color) = self.settings.isochronic_on {
+                    write!(out, " isochronic_on={}", hex(color)).unwrap();
+                }
+            } else {
+                // Subsequent keyframes: only write changed parameters
+                let prev = &self.keyframes[i - 1].params;
+                for change in p.changes_from(prev) {
+                    write!(out, " {}={}", change.key, change.to).unwrap();
+                }
+
+                if kf.curve != self.settings.default_curve {
+                    write!(out, " >{}", kf.curve.to_str()).unwrap();
+                }
+                for (field, curve) in kf.curves.iter() {
+                    write!(out, " {field}>{}", curve.to_str()).unwrap();
+                }
+                if out.len() == line_start {
+                    out.push_str(" hold");
+                }
+            }
+
+            if let Some(tag) = &kf.tag {
+                write!(out, " tag={tag}").unwrap();
+            }
+            if let Some(note) = &kf.note {
+                write!(out, " note=\"{note}\"").unwrap();
             }
 
             out.push('\n');
@@ -344,6 +1390,38 @@ impl Program {
     }
 }
 
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Live Program
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// A program that can be replaced while it is playing.
+///
+/// Readers (including the audio thread) load the current program without
+/// locking; writers swap in a new one atomically.
+#[derive(Debug)]
+pub struct LiveProgram {
+    current: ArcSwap<Program>,
+}
+
+impl LiveProgram {
+    pub fn new(program: Arc<Program>) -> Self {
+        Self {
+            current: ArcSwap::new(program),
+        }
+    }
+
+    /// Get the current program.
+    #[inline]
+    pub fn load(&self) -> arc_swap::Guard<Arc<Program>> {
+        self.current.load()
+    }
+
+    /// Replace the current program.
+    pub fn store(&self, program: Program) {
+        self.current.store(Arc::new(program));
+    }
+}
+
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Parsing Utilities
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
@@ -381,41 +1459,212 @@ fn format_timestamp(secs: f64) -> String {
     }
 }
 
+/// Format a color as a #RRGGBB string.
+fn hex(color: Color) -> String {
+    format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b)
+}
+
+/// A parameter ramp at a fixed rate, from an `@rate` directive.
+#[derive(Debug, Clone, PartialEq)]
+struct RateRamp {
+    param: String,
+    /// Change per second.
+    per_sec: f64,
+}
+
+impl RateRamp {
+    /// Parse the directive arguments, e.g. `vol +0.1/60s`.
+    fn parse(args: &str) -> Result<Self> {
+        let mut tokens = args.split_whitespace();
+        let param = tokens.next().context("@rate is missing a parameter")?;
+        let rate = tokens.next().context("@rate is missing a rate like +0.1/60s")?;
+        if tokens.next().is_some() {
+            bail!("unexpected tokens after @rate {param} {rate}");
+        }
+
+        if !matches!(param, "freq" | "tone" | "vol" | "duty" | "smooth") {
+            bail!("@rate does not support parameter '{param}'");
+        }
+
+        let (delta, per) = rate
+            .split_once('/')
+            .context("rate must be <delta>/<secs>s, e.g. +0.1/60s")?;
+        let delta: f64 = delta.parse().context("invalid rate delta")?;
+        let secs: f64 = per
+            .strip_suffix('s')
+            .context("rate period must end in 's'")?
+            .parse()
+            .context("invalid rate period")?;
+        if secs <= 0.0 {
+            bail!("rate period must be positive");
+        }
+
+        Ok(Self {
+            param: param.to_owned(),
+            per_sec: delta / secs,
+        })
+    }
+
+    /// Advance `params` by `elapsed` seconds of this ramp.
+    fn apply(&self, params: &mut Params, elapsed: f64) -> Result<()> {
+        let delta = self.per_sec * elapsed;
+        match self.param.as_str() {
+            "freq" => {
+                params.freq += delta;
+                if params.freq <= 0.0 {
+                    bail!("@rate freq ramp reaches {:.2} Hz", params.freq);
+                }
+            }
+            "tone" => {
+                params.tone += delta as f32;
+                if params.tone <= 0.0 {
+                    bail!("@rate tone ramp reaches {:.2} Hz", params.tone);
+                }
+            }
+            "vol" => params.vol = (params.vol + delta as f32).clamp(0.0, 1.0),
+            "duty" => params.duty = (params.duty + delta as f32).clamp(0.01, 0.99),
+            "smooth" => params.smooth = (params.smooth + delta as f32).clamp(0.0, 1.0),
+            _ => unreachable!("validated in RateRamp::parse"),
+        }
+        Ok(())
+    }
+}
+
+/// Split a line on whitespace, keeping double-quoted text together.
+///
+/// Quotes cannot be escaped, so quoted text cannot contain `"`.
+fn tokenize(line: &str) -> Result<Vec<&str>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c.is_whitespace() && !quoted {
+            if let Some(s) = start.take() {
+                tokens.push(&line[s..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+
+    if quoted {
+        bail!("unterminated quote");
+    }
+    if let Some(s) = start {
+        tokens.push(&line[s..]);
+    }
+    Ok(tokens)
+}
+
 /// Parse a single program line into a keyframe.
 fn parse_line(
     line: &str,
     current: &mut Params,
     settings: &mut Settings,
-    is_first: bool,
+    prev_time: Option<f64>,
+    warnings: &mut Vec<String>,
 ) -> Result<Keyframe> {
-    let mut tokens = line.split_whitespace();
+    let mut tokens = tokenize(line)?.into_iter();
+    let is_first = prev_time.is_none();
 
     let timestamp = tokens.next().context("missing timestamp")?;
-    let time = parse_timestamp(timestamp)?;
-    let mut curve = Curve::Step;
+    // +MM:SS is relative to the previous keyframe
+    let time = match timestamp.strip_prefix('+') {
+        Some(delta) => {
+            let prev = prev_time.context("the first keyframe can't use a relative timestamp")?;
+            prev + parse_timestamp(delta)?
+        }
+        None => parse_timestamp(timestamp)?,
+    };
+    let mut curve = settings.default_curve;
+    let mut curves = FieldCurves::default();
+    let mut note = None;
+    let mut tag = None;
+    // A midpoint only shapes the transition into its own keyframe
+    current.via = None;
+    let (mut left, mut right) = (None, None);
+    let (mut set_tone, mut set_freq, mut set_on) = (false, false, false);
 
     for token in tokens {
         // Curve directive: >curve
         if let Some(curve_name) = token.strip_prefix('>') {
-            curve = Curve::parse(curve_name)?;
+            let parsed = Curve::parse(curve_name)?;
+            if is_first {
+                // Nothing precedes the first keyframe, so there is no transition to shape
+                warnings.push(format!("curve '>{curve_name}' on the first keyframe has no effect"));
+            } else {
+                curve = parsed;
+            }
             continue;
         }
 
         // Key=value pairs
         if let Some((key, val)) = token.split_once('=') {
             match key {
+                "default_curve" => {
+                    if !is_first {
+                        bail!("setting '{key}' can only appear on the first line");
+                    }
+                    settings.default_curve = Curve::parse(val)?;
+                }
+                "samplerate" => {
+                    if !is_first {
+                        bail!("setting '{key}' can only appear on the first line");
+                    }
+                    let rate: u32 = val.parse().context("invalid samplerate value")?;
+                    if rate == 0 {
+                        bail!("samplerate must be positive");
+                    }
+                    settings.sample_rate = Some(rate);
+                }
+                "binaural_on" | "isochronic_on" => {
+                    if !is_first {
+                        bail!("setting '{key}' can only appear on the first line");
+                    }
+                    let color = val
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("{e}"))
+                        .with_context(|| format!("invalid '{key}' color"))?;
+                    if key == "binaural_on" {
+                        settings.binaural_on = Some(color);
+                    } else {
+                        settings.isochronic_on = Some(color);
+                    }
+                }
                 "freq" => {
+                    if set_freq {
+                        bail!("use either freq or period, not both");
+                    }
+                    set_freq = true;
                     current.freq = val.parse().context("invalid freq value")?;
                     if current.freq <= 0.0 {
                         bail!("freq must be positive");
                     }
                 }
                 "tone" => {
+                    set_tone = true;
                     current.tone = val.parse().context("invalid tone value")?;
                     if current.tone <= 0.0 {
                         bail!("tone must be positive");
                     }
                 }
+                "period" => {
+                    let period: f64 = val.parse().context("invalid period value")?;
+                    if period <= 0.0 {
+                        bail!("period must be positive");
+                    }
+                    if set_freq {
+                        bail!("use either freq or period, not both");
+                    }
+                    set_freq = true;
+                    current.freq = 1.0 / period;
+                }
+                "left" => left = Some(val.parse::<f32>().context("invalid left tone")?),
+                "right" => right = Some(val.parse::<f32>().context("invalid right tone")?),
                 "vol" => {
                     current.vol = val
                         .parse::<f32>()
@@ -428,7 +1677,73 @@ fn parse_line(
                         .context("invalid duty value")?
                         .clamp(0.01, 0.99);
                 }
+                "smooth" => {
+                    current.smooth = val
+                        .parse::<f32>()
+                        .context("invalid smooth value")?
+                        .clamp(0.0, 1.0);
+                }
+                "env" => current.env = EnvelopeShape::parse(val)?,
+                "vibrato" => {
+                    let (depth, rate) = val
+                        .split_once(':')
+                        .context("vibrato must be <depth_hz>:<rate_hz>")?;
+                    current.vibrato_depth = depth.parse().context("invalid vibrato depth")?;
+                    current.vibrato_rate = rate.parse().context("invalid vibrato rate")?;
+                    if current.vibrato_depth < 0.0 || current.vibrato_rate < 0.0 {
+                        bail!("vibrato depth and rate must not be negative");
+                    }
+                }
+                "autopan" => {
+                    let (rate, depth) = val
+                        .split_once(':')
+                        .context("autopan must be <rate_hz>:<depth>")?;
+                    current.autopan_rate = rate.parse().context("invalid autopan rate")?;
+                    if current.autopan_rate < 0.0 {
+                        bail!("autopan rate must not be negative");
+                    }
+                    current.autopan_depth = depth
+                        .parse::<f32>()
+                        .context("invalid autopan depth")?
+                        .clamp(0.0, 1.0);
+                }
+                "pan" => {
+                    current.pan = val
+                        .parse::<f32>()
+                        .context("invalid pan value")?
+                        .clamp(-1.0, 1.0);
+                }
+                "swell" => {
+                    let (rate, depth) = val
+                        .split_once(':')
+                        .context("swell must be <rate_hz>:<depth>")?;
+                    current.swell_rate = rate.parse().context("invalid swell rate")?;
+                    if current.swell_rate < 0.0 {
+                        bail!("swell rate must not be negative");
+                    }
+                    current.swell_depth = depth
+                        .parse::<f32>()
+                        .context("invalid swell depth")?
+                        .clamp(0.0, 1.0);
+                }
+                "noise" => {
+                    current.noise = val
+                        .parse::<f32>()
+                        .context("invalid noise value")?
+                        .clamp(0.0, 1.0);
+                }
+                "tag" => {
+                    if val.is_empty() {
+                        bail!("tag must not be empty");
+                    }
+                    tag = Some(val.to_owned());
+                }
+                "note" => {
+                    let text = val.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(val);
+                    note = Some(text.to_owned());
+                }
                 "on" => {
+                    set_on = true;
                     current.on = val
                         .parse()
                         .map_err(|e| anyhow::anyhow!("{e}"))
@@ -440,8 +1755,30 @@ fn parse_line(
                         .map_err(|e| anyhow::anyhow!("{e}"))
                         .context("invalid 'off' color")?;
                 }
+                "via" => {
+                    let via = val
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("{e}"))
+                        .context("invalid 'via' color")?;
+                    if is_first {
+                        warnings.push("'via' on the first keyframe has no effect".into());
+                    } else {
+                        current.via = Some(via);
+                    }
+                }
                 _ => bail!("unknown parameter '{key}'"),
             }
+        } else if token == "hold" {
+            // Explicitly keep the current values
+            continue;
+        } else if let Some((field, curve_name)) = token.split_once('>').or_else(|| token.split_once(':')) {
+            // Per-field curve: field>curve or field:curve
+            let parsed = Curve::parse(curve_name)?;
+            curves.set(field, parsed)?;
+            if is_first {
+                warnings.push(format!("curve '{token}' on the first keyframe has no effect"));
+                curves = FieldCurves::default();
+            }
         } else {
             // Flags (only allowed on first line)
             if !is_first {
@@ -449,16 +1786,56 @@ fn parse_line(
             }
             match token {
                 "binaural" => settings.binaural = true,
+                "monaural" => settings.monaural = true,
                 "headless" => settings.headless = true,
+                "linked" => settings.linked = true,
+                "invert_duty" => settings.invert_duty = true,
                 _ => bail!("unknown setting '{token}'"),
             }
         }
     }
 
+    if is_first && settings.binaural && settings.monaural {
+        bail!("binaural and monaural can't be combined");
+    }
+
+    // The mode's color applies once settings are known; later keyframes inherit it
+    if is_first && !set_on {
+        let mode_on = if settings.binaural { settings.binaural_on } else { settings.isochronic_on };
+        if let Some(color) = mode_on {
+            current.on = color;
+        }
+    }
+
+    // Binaural tones given per ear: the left one is the base, the gap is the beat
+    if left.is_some() || right.is_some() {
+        if (left.is_some() && set_tone) || (right.is_some() && set_freq) {
+            bail!("use either left/right or tone/freq, not both");
+        }
+        if let Some(left) = left {
+            if left <= 0.0 {
+                bail!("left tone must be positive");
+            }
+            current.tone = left;
+        }
+        if let Some(right) = right {
+            if right <= current.tone {
+                bail!("right tone must be above the left tone ({})", current.tone);
+            }
+            current.freq = f64::from(right - current.tone);
+        }
+        if !settings.binaural {
+            warnings.push("left/right tones only apply to binaural programs".into());
+        }
+    }
+
     Ok(Keyframe {
         time,
         params: *current,
         curve,
+        curves,
+        note,
+        tag,
     })
 }
 
@@ -489,15 +1866,15 @@ mod tests {
 
     #[test]
     fn smooth_interpolation() {
-        let program = Program::parse("00:00 freq=0\n00:10 freq=100 >smooth").unwrap();
+        let program = Program::parse("00:00 freq=1\n00:10 freq=101 >smooth").unwrap();
 
         // Smoothstep should be 0.5 at t=0.5
         let mid = program.params_at(5.0).freq;
-        assert!((mid - 50.0).abs() < 0.1);
+        assert!((mid - 51.0).abs() < 0.1);
 
         // Should be slower at edges
         let early = program.params_at(1.0).freq;
-        assert!(early < 10.0); // Less than linear would give
+        assert!(early < 11.0); // Less than linear would give
     }
 
     #[test]
@@ -531,6 +1908,379 @@ mod tests {
         assert!((Curve::Smooth.apply(0.0) - 0.0).abs() < 0.001);
         assert!((Curve::Smooth.apply(0.5) - 0.5).abs() < 0.001);
         assert!((Curve::Smooth.apply(1.0) - 1.0).abs() < 0.001);
+
+        // Exp curve (ease-in)
+        assert!((Curve::Exp.apply(0.0) - 0.0).abs() < 0.001);
+        assert!((Curve::Exp.apply(0.5) - 0.25).abs() < 0.001);
+        assert!((Curve::Exp.apply(1.0) - 1.0).abs() < 0.001);
+
+        // Log curve (ease-out)
+        assert!((Curve::Log.apply(0.0) - 0.0).abs() < 0.001);
+        assert!((Curve::Log.apply(0.5) - 0.75).abs() < 0.001);
+        assert!((Curve::Log.apply(1.0) - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn curve_integrals_match_apply() {
+        for curve in [Curve::Linear, Curve::Smooth, Curve::Exp, Curve::Log] {
+            let steps = 10_000;
+            let numeric: f64 = (0..steps).map(|i| curve.apply((f64::from(i) + 0.5) / f64::from(steps))).sum::<f64>()
+                / f64::from(steps);
+            assert!((curve.integral(1.0) - numeric).abs() < 1e-6, "{curve:?}");
+        }
+
+        let program = Program::parse("00:00 vol=1\n00:10 vol=0 >log\n00:20 vol=1 >exp").unwrap();
+        assert!(program.to_source().contains(">log") && program.to_source().contains(">exp"));
+        assert_eq!(Program::parse(&program.to_source()).unwrap().to_source(), program.to_source());
+    }
+
+    #[test]
+    fn first_keyframe_curve_warns() {
+        let program = Program::parse("00:00 vol=0 >smooth\n00:10 vol=1 >linear").unwrap();
+        assert_eq!(
+            program.warnings,
+            ["line 1: curve '>smooth' on the first keyframe has no effect"]
+        );
+        assert!((program.params_at(2.5).vol - 0.25).abs() < 0.001);
+
+        let clean = Program::parse("00:00 vol=0\n00:10 vol=1 >linear").unwrap();
+        assert!(clean.warnings.is_empty());
+    }
+
+    #[test]
+    fn samplerate_setting() {
+        let program = Program::parse("00:00 freq=10 tone=12000 samplerate=96000").unwrap();
+        assert_eq!(program.settings.sample_rate, Some(96000));
+        assert!(program.to_source().contains(" samplerate=96000"));
+
+        assert_eq!(Program::parse("00:00 freq=10").unwrap().settings.sample_rate, None);
+        assert!(Program::parse("00:00 freq=10\n00:10 samplerate=96000").is_err());
+    }
+
+    #[test]
+    fn rate_directive_resolves_to_keyframe() {
+        let program = Program::parse("00:00 vol=0.2\n@rate vol +0.1/60s\n02:00 freq=8").unwrap();
+        assert!((program.params_at(120.0).vol - 0.4).abs() < 1e-6);
+        assert!((program.params_at(60.0).vol - 0.3).abs() < 1e-6);
+        assert_eq!(program.params_at(120.0).freq, 8.0);
+
+        // Explicit values on the next keyframe win
+        let program = Program::parse("00:00 vol=0.2\n@rate vol +0.1/60s\n02:00 vol=1").unwrap();
+        assert_eq!(program.params_at(120.0).vol, 1.0);
+
+        assert!(Program::parse("00:00 vol=0.2\n@rate vol +0.1/60s").is_err());
+        assert!(Program::parse("00:00 vol=0.2\n@rate on +1/60s\n01:00").is_err());
+    }
+
+    #[test]
+    fn notes_survive_round_trip() {
+        let source = "00:00 vol=0 note=\"warm up\"\n00:10 vol=1 note=\"start of deep phase, watch volume\" >linear\n00:20 note=short";
+        let program = Program::parse(source).unwrap();
+        let notes = |p: &Program| p.keyframes.iter().map(|k| k.note.clone()).collect::<Vec<_>>();
+        let expected = [
+            Some("warm up".to_owned()),
+            Some("start of deep phase, watch volume".to_owned()),
+            Some("short".to_owned()),
+        ];
+        assert_eq!(notes(&program), expected);
+
+        let reparsed = Program::parse(&program.to_source()).unwrap();
+        assert_eq!(notes(&reparsed), expected);
+        assert!((reparsed.params_at(5.0).vol - 0.5).abs() < 0.001);
+
+        assert!(Program::parse("00:00 note=\"unterminated").is_err());
+    }
+
+    #[test]
+    fn via_color_is_transition_midpoint() {
+        let source = "00:00 on=#FF0000\n00:10 on=#0000FF via=#FFFFFF >linear\n00:20 on=#00FF00 >linear";
+        let program = Program::parse(source).unwrap();
+
+        assert_eq!(program.params_at(5.0).on, Color::WHITE);
+        assert_eq!(program.params_at(10.0).on.b, 255);
+
+        // The midpoint belongs to its keyframe only
+        let plain = Color::lerp("#0000FF".parse().unwrap(), "#00FF00".parse().unwrap(), 0.5);
+        assert_eq!(program.params_at(15.0).on, plain);
+
+        let reparsed = Program::parse(&program.to_source()).unwrap();
+        assert_eq!(reparsed.params_at(5.0).on, Color::WHITE);
+    }
+
+    #[test]
+    fn pulse_cycles_integrate_frequency() {
+        let program = Program::parse("00:00 freq=10\n00:10 freq=20 >linear\n00:20 freq=4 >smooth\n00:30 hold").unwrap();
+
+        assert_eq!(program.pulse_cycles_at(0.0), 0.0);
+        assert!((program.pulse_cycles_at(5.0) - 62.5).abs() < 1e-9);
+        assert!((program.pulse_cycles_at(10.0) - 150.0).abs() < 1e-9);
+        // Smoothstep averages to the midpoint frequency over a full transition
+        assert!((program.pulse_cycles_at(20.0) - 270.0).abs() < 1e-9);
+        assert!((program.pulse_cycles_at(25.0) - 270.0 - 5.0 * 4.0).abs() < 1e-9);
+
+        // Matches a numeric integral of params_at
+        let steps = 100_000;
+        let numeric: f64 = (0..steps)
+            .map(|i| program.params_at((i as f64 + 0.5) * 17.0 / steps as f64).freq * 17.0 / steps as f64)
+            .sum();
+        assert!((program.pulse_cycles_at(17.0) - numeric).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tabs_and_trailing_whitespace_separate_tokens() {
+        let spaced = Program::parse("00:00 freq=10 vol=0.5\n00:10 freq=6 vol=0.2 >linear").unwrap();
+        let tabbed = Program::parse("00:00\tfreq=10 \t vol=0.5\t\n00:10\t\tfreq=6\tvol=0.2  >linear \t").unwrap();
+
+        for time in [0.0, 5.0, 10.0] {
+            assert_eq!(tabbed.params_at(time), spaced.params_at(time));
+        }
+    }
+
+    #[test]
+    fn empty_keyframe_needs_hold() {
+        let err = Program::parse("00:00 freq=10\n00:10 \t ").unwrap_err();
+        assert!(format!("{err:#}").contains("at least one parameter or use 'hold'"), "{err:#}");
+
+        let program = Program::parse("00:00 freq=10\n00:10 hold\n00:20 freq=6 >linear").unwrap();
+        assert_eq!(program.keyframes.len(), 3);
+        assert_eq!(program.params_at(10.0).freq, 10.0);
+        assert_eq!(program.params_at(15.0).freq, 8.0);
+
+        let reparsed = Program::parse(&program.to_source()).unwrap();
+        assert_eq!(reparsed.keyframes.len(), 3);
+    }
+
+    #[test]
+    fn binaural_programs_flag_isochronic_params() {
+        let program = Program::parse("00:00 freq=10 duty=0.3 binaural\n00:10 duty=0.6 >linear\n00:20 freq=6").unwrap();
+        assert_eq!(program.warnings, ["00:10: 'duty' has no effect on binaural audio"]);
+
+        let program = Program::parse("00:00 binaural vibrato=2:0.5").unwrap();
+        assert_eq!(program.warnings, ["00:00: 'vibrato' has no effect on binaural audio"]);
+
+        // Isochronic programs use all of them
+        let program = Program::parse("00:00 freq=10\n00:10 duty=0.6 smooth=0.01").unwrap();
+        assert!(program.validate().is_empty());
+    }
+
+    #[test]
+    fn on_time_integrates_duty() {
+        let program = Program::parse("00:00 freq=10 duty=0.5\n00:10 hold").unwrap();
+        assert!((program.total_on_time() - 5.0).abs() < 1e-6);
+
+        // Ramping duty 0.2 -> 0.6 averages to 0.4
+        let program = Program::parse("00:00 duty=0.2\n00:10 duty=0.6 >smooth").unwrap();
+        assert!((program.total_on_time() - 4.0).abs() < 1e-6);
+
+        let program = Program::parse("00:00 duty=0.3 invert_duty\n00:10 hold").unwrap();
+        assert!((program.total_on_time() - 7.0).abs() < 1e-6);
+
+        assert_eq!(Program::parse("00:00 freq=10").unwrap().total_on_time(), f64::INFINITY);
+    }
+
+    #[test]
+    fn keyframe_descriptions_list_changes() {
+        let source = "00:00 freq=10 vol=0\n00:10 vol=0.8 >linear note=\"fade in\"\n01:00 freq=6 on=#F00 >smooth\n01:30 hold";
+        let program = Program::parse(source).unwrap();
+
+        assert_eq!(program.describe_keyframe(0), ["start", "  vol = 0.00"]);
+        assert_eq!(
+            program.describe_keyframe(1),
+            ["linear over 10.0s", "  vol 0.00 -> 0.80", "  note: fade in"]
+        );
+        assert_eq!(
+            program.describe_keyframe(2),
+            ["smooth over 50.0s", "  freq 10.00 -> 6.00", "  on #FFFFFF -> #FF0000"]
+        );
+        assert_eq!(program.describe_keyframe(3), ["step after 30.0s", "  no changes"]);
+
+        let text = program.explain();
+        assert!(text.starts_with("Program: 4 keyframes, 01:30 long\nSettings: none\n00:00\n  start\n"), "{text}");
+    }
+
+    #[test]
+    fn repeated_program_plays_count_times() {
+        let program = Program::parse("00:00 freq=10 vol=0\n00:05 vol=0.8 >linear\n00:10 freq=6").unwrap();
+        let repeated = program.repeated(3).unwrap();
+
+        assert_eq!(repeated.duration, 3.0 * 10.0 + 2.0 * REPEAT_GAP_SECS);
+        let period = 10.0 + REPEAT_GAP_SECS;
+        for iteration in 0..3 {
+            let offset = f64::from(iteration) * period;
+            for time in [0.0, 2.5, 5.0, 7.0, 10.0] {
+                assert_eq!(repeated.params_at(offset + time), program.params_at(time), "{offset} + {time}");
+            }
+        }
+
+        // Silent within the gap, then restarts at the first keyframe
+        let gap = repeated.params_at(10.0 + REPEAT_FADE_SECS + 0.1);
+        assert_eq!((gap.vol, gap.freq), (0.0, 6.0));
+        assert_eq!(repeated.params_at(period - 0.01).vol, 0.0);
+
+        assert!(Program::parse("00:00 freq=10").unwrap().repeated(2).is_err());
+        assert!(program.repeated(0).is_err());
+    }
+
+    #[test]
+    fn left_right_tones_set_base_and_beat() {
+        let program = Program::parse("00:00 binaural left=200 right=210\n00:10 right=206 >linear").unwrap();
+        let start = program.params_at(0.0);
+        assert_eq!((start.tone, start.freq), (200.0, 10.0));
+        assert_eq!(program.params_at(10.0).freq, 6.0);
+        assert!(program.warnings.is_empty());
+
+        assert!(Program::parse("00:00 binaural left=210 right=200").is_err());
+        assert!(Program::parse("00:00 binaural left=200 tone=180").is_err());
+
+        let isochronic = Program::parse("00:00 left=200 right=210").unwrap();
+        assert_eq!(isochronic.warnings, ["line 1: left/right tones only apply to binaural programs"]);
+    }
+
+    #[test]
+    fn keyframe_crossings_between_samples() {
+        let program = Program::parse("00:00 freq=10\n00:10 vol=0.8\n00:20 freq=6").unwrap();
+
+        assert_eq!(program.keyframes_crossed(9.98, 10.01), 1..2);
+        assert!(program.keyframes_crossed(10.01, 10.05).is_empty());
+        // A long gap between samples reports every keyframe in it
+        assert_eq!(program.keyframes_crossed(5.0, 25.0), 1..3);
+        // Going backwards crosses nothing
+        assert!(program.keyframes_crossed(15.0, 5.0).is_empty());
+
+        let (time, changes) = program.keyframe_changes(1);
+        assert_eq!(time, 10.0);
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].key, changes[0].to.as_str()), ("vol", "0.80"));
+    }
+
+    #[test]
+    fn period_sets_frequency() {
+        let program = Program::parse("00:00 period=3\n00:10 period=0.5 >linear").unwrap();
+        assert!((program.params_at(0.0).freq - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(program.params_at(10.0).freq, 2.0);
+
+        assert!(Program::parse("00:00 period=0").is_err());
+        assert!(Program::parse("00:00 period=-2").is_err());
+        assert!(Program::parse("00:00 period=3 freq=2").is_err());
+    }
+
+    #[test]
+    fn fast_high_contrast_flash_warns() {
+        let harsh = Program::parse("00:00 freq=10 on=#FFFFFF off=#000000\n00:30 freq=30 >linear\n01:00 freq=40").unwrap();
+        let warnings = harsh.validate();
+        assert_eq!(warnings.len(), 1, "{warnings:?}"); // once for the whole stretch
+        assert!(warnings[0].starts_with("00:30: flashing at 30.0 Hz"), "{}", warnings[0]);
+
+        // Low contrast at the same rate is fine
+        let gentle = Program::parse("00:00 freq=40 on=#404040 off=#202020").unwrap();
+        assert!(gentle.validate().is_empty());
+
+        // So is full contrast at a slow rate, or no visuals at all
+        assert!(Program::parse("00:00 freq=10").unwrap().validate().is_empty());
+        assert!(Program::parse("00:00 freq=40 headless").unwrap().validate().is_empty());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let source = "00:00 binaural linked freq=10 tone=200 vol=0 on=#ff8800\n00:30 vol=0.8 >linear note=\"fade in\"\n01:00 freq=6 via=#00ff00 >smooth tag=deep\n02:00 vol=0 >linear";
+        let program = Program::parse(source).unwrap();
+        let json = program.to_json();
+        let restored = Program::from_json(&json).unwrap();
+
+        assert_eq!(restored.settings, program.settings);
+        assert_eq!(restored.duration, program.duration);
+        for time in [0.0, 15.0, 30.0, 45.0, 59.9, 75.0, 100.0, 120.0, 200.0] {
+            assert_eq!(restored.params_at(time), program.params_at(time), "at {time}s");
+        }
+        assert_eq!(restored.to_source(), program.to_source());
+
+        // Omitted fields take their defaults
+        let minimal = Program::from_json(r#"{"duration": null, "keyframes": [{"time": 0, "params": {"freq": 7}}]}"#).unwrap();
+        assert_eq!(minimal.params_at(0.0), Params { freq: 7.0, ..Params::default() });
+
+        assert!(Program::from_json(r#"{"duration": 0, "keyframes": []}"#).is_err());
+        assert!(Program::from_json(r#"{"duration": 1, "keyframes": [{"time": 1, "params": {}}]}"#).is_err());
+    }
+
+    #[test]
+    fn skipping_tags_removes_sections() {
+        let source = "00:00 vol=0 tag=intro\n00:30 vol=0.8 >linear tag=intro\n01:00 freq=6 >smooth\n01:30 freq=4 tag=deep\n02:00 vol=0 >linear";
+        let program = Program::parse(source).unwrap();
+
+        let main = program.without_tags(&["intro"]).unwrap();
+        assert_eq!(main.keyframe_times().collect::<Vec<_>>(), [0.0, 30.0, 60.0, 90.0]);
+        assert_eq!(main.duration, 90.0);
+        // Starts where the intro would have ended
+        assert_eq!(main.params_at(0.0).vol, 0.8);
+        assert_eq!(main.params_at(30.0), program.params_at(60.0));
+
+        // A section in the middle closes up; the end of the program is unchanged
+        let short = program.without_tags(&["deep"]).unwrap();
+        assert_eq!(short.keyframe_times().collect::<Vec<_>>(), [0.0, 30.0, 60.0, 90.0]);
+        assert_eq!(short.params_at(90.0), program.params_at(120.0));
+
+        assert_eq!(program.without_tags(&["other"]).unwrap().keyframe_times().count(), 5);
+        assert!(Program::parse("00:00 tag=a").unwrap().without_tags(&["a"]).is_err());
+
+        let reparsed = Program::parse(&program.to_source()).unwrap();
+        assert_eq!(reparsed.without_tags(&["intro"]).unwrap().duration, 90.0);
+    }
+
+    #[test]
+    fn missing_file_error_names_path() {
+        let path = std::env::temp_dir().join("isochronator-missing/session.ent");
+        let err = Program::load(&path).unwrap_err();
+
+        assert!(format!("{err:#}").contains(&path.display().to_string()), "{err:#}");
+        let io = err.root_cause().downcast_ref::<std::io::Error>().expect("io error in chain");
+        assert_eq!(io.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn default_curve_setting() {
+        let program = Program::parse("00:00 freq=10 vol=0 default_curve=linear\n00:10 vol=1").unwrap();
+        assert_eq!(program.settings.default_curve, Curve::Linear);
+        assert!((program.params_at(5.0).vol - 0.5).abs() < 0.001);
+
+        // Explicit directives still override the default
+        let program = Program::parse("00:00 vol=0 default_curve=linear\n00:10 vol=1 >step").unwrap();
+        assert!((program.params_at(5.0).vol - 0.0).abs() < 0.001);
+
+        // Only allowed on the first line
+        assert!(Program::parse("00:00 vol=0\n00:10 vol=1 default_curve=linear").is_err());
+
+        // Survives export, including explicit step overrides
+        let reparsed = Program::parse(&program.to_source()).unwrap();
+        assert_eq!(reparsed.settings.default_curve, Curve::Linear);
+        assert!((reparsed.params_at(5.0).vol - 0.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn detailed_params_report_transitions() {
+        let program = Program::parse("00:00 vol=0\n00:10 vol=1 >linear\n00:20 freq=5").unwrap();
+
+        let (params, transition) = program.params_at_detailed(4.2);
+        assert!((params.vol - 0.42).abs() < 0.001);
+        let transition = transition.unwrap();
+        assert_eq!(transition.curve, Curve::Linear);
+        assert!((transition.progress - 0.42).abs() < 1e-9);
+        assert_eq!((transition.from_time, transition.to_time), (0.0, 10.0));
+
+        // Holding before a step change and after the end
+        assert_eq!(program.params_at_detailed(15.0).1, None);
+        assert_eq!(program.params_at_detailed(25.0).1, None);
+    }
+
+    #[test]
+    fn ramp_program() {
+        let from = Params { vol: 0.0, ..Params::default() };
+        let to = Params { vol: 1.0, ..Params::default() };
+        let program = Program::ramp(from, to, 2.0, 1.0, Settings::default());
+
+        assert!(program.duration.is_infinite());
+        assert!((program.params_at(1.0).vol - 0.0).abs() < 0.001);
+        assert!((program.params_at(2.5).vol - 0.5).abs() < 0.001);
+        assert!((program.params_at(100.0).vol - 1.0).abs() < 0.001);
     }
 
     #[test]
@@ -538,7 +2288,11 @@ mod tests {
         assert_eq!("#FF0000".parse::<Color>().unwrap(), Color { r: 255, g: 0, b: 0, a: 255 });
         assert_eq!("00FF00".parse::<Color>().unwrap(), Color { r: 0, g: 255, b: 0, a: 255 });
         assert!("invalid".parse::<Color>().is_err());
-        assert!("#FFF".parse::<Color>().is_err());
+        assert_eq!("#FA0".parse::<Color>().unwrap(), Color { r: 255, g: 170, b: 0, a: 255 });
+        assert_eq!("fff".parse::<Color>().unwrap(), Color::WHITE);
+        assert!("#FFFF".parse::<Color>().is_err());
+        assert!("#FFFFF".parse::<Color>().is_err());
+        assert!("#FG0".parse::<Color>().is_err());
     }
 
     #[test]
@@ -556,4 +2310,222 @@ mod tests {
         assert!((p1.freq - p2.freq).abs() < 0.01);
         assert!((p1.vol - p2.vol).abs() < 0.01);
     }
+
+    #[test]
+    fn field_curve_overrides_line_curve() {
+        let program = Program::parse("00:00 freq=10 vol=0\n00:10 freq=6 vol=1 >linear freq>smooth").unwrap();
+        assert!((program.params_at(2.5).freq - (10.0 - 4.0 * Curve::Smooth.apply(0.25))).abs() < 1e-9);
+        assert!((program.params_at(2.5).vol - 0.25).abs() < 1e-6);
+
+        // Without overrides every field follows the line's curve, as before
+        let plain = Program::parse("00:00 freq=10 vol=0\n00:10 freq=6 vol=1 >smooth").unwrap();
+        assert!((plain.params_at(2.5).vol - Curve::Smooth.apply(0.25) as f32).abs() < 1e-6);
+
+        // Survives a round trip through the source format
+        let source = program.to_source();
+        assert!(source.contains(">linear freq>smooth"), "{source}");
+        assert_eq!(Program::parse(&source).unwrap().params_at(2.5), program.params_at(2.5));
+
+        assert!(Program::parse("00:00 freq=10\n00:10 freq=6 pitch>smooth").is_err());
+        assert!(Program::parse("00:00 vol=0\n00:10 vol=1 vol>bounce").is_err());
+        assert_eq!(Program::parse("00:00 vol=0 vol>linear").unwrap().warnings.len(), 1);
+    }
+
+    #[test]
+    fn loop_blocks_expand_at_parse_time() {
+        let source = "00:00 freq=10 vol=0\n00:10 vol=0.8 >linear\nloop 3 {\n02:00 freq=6 >linear\n05:10 freq=10 >smooth\n}\n20:00 vol=0 >linear";
+        let program = Program::parse(source).unwrap();
+
+        // Each play lasts 5:00, from 00:10 to 05:10, then 10:10 and 15:10
+        let times: Vec<f64> = program.keyframe_times().collect();
+        assert_eq!(times, [0.0, 10.0, 120.0, 310.0, 420.0, 610.0, 720.0, 910.0, 1200.0]);
+        assert_eq!(program.duration, 1200.0);
+        for offset in [0.0, 300.0, 600.0] {
+            assert_eq!(program.params_at(65.0 + offset), program.params_at(65.0));
+        }
+
+        for bad in [
+            "loop 2 {\n00:00 freq=10\n}",
+            "00:00 freq=10\nloop 2 {\nloop 2 {\n00:10 freq=6\n}\n}",
+            "00:00 freq=10\nloop 2 {\n00:10 freq=6",
+            "00:00 freq=10\n}",
+            "00:00 freq=10\nloop 0 {\n00:10 freq=6\n}",
+            "00:00 freq=10\nloop 2 {\n}",
+        ] {
+            assert!(Program::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn relative_timestamps_follow_previous_keyframe() {
+        let program = Program::parse("00:00 freq=10 vol=0\n+00:30 vol=0.8 >linear\n+01:30 freq=6\n05:00 hold\n+00:10 hold").unwrap();
+        let times: Vec<f64> = program.keyframe_times().collect();
+        assert_eq!(times, [0.0, 30.0, 120.0, 300.0, 310.0]);
+
+        // Exported source uses absolute times
+        let source = program.to_source();
+        assert!(!source.contains('+'), "{source}");
+        let reparsed = Program::parse(&source).unwrap();
+        assert_eq!(reparsed.keyframe_times().collect::<Vec<_>>(), times);
+
+        assert!(Program::parse("+00:00 freq=10").is_err());
+        assert!(Program::parse("00:00 freq=10\n+00:00 freq=6").is_err());
+    }
+
+    #[test]
+    fn pan_parses_clamps_and_round_trips() {
+        let program = Program::parse("00:00 freq=10 pan=-0.5\n00:10 pan=3 >linear\n00:20 pan>smooth hold").unwrap();
+        assert_eq!(program.params_at(0.0).pan, -0.5);
+        assert_eq!(program.params_at(10.0).pan, 1.0);
+        assert!((program.params_at(5.0).pan - 0.25).abs() < 1e-6);
+
+        let reparsed = Program::parse(&program.to_source()).unwrap();
+        for time in [0.0, 5.0, 10.0] {
+            assert_eq!(reparsed.params_at(time).pan, program.params_at(time).pan);
+        }
+
+        let binaural = Program::parse("00:00 freq=10 binaural\n00:10 pan=0.5").unwrap();
+        assert!(binaural.validate().iter().any(|w| w.contains("'pan'")));
+    }
+
+    #[test]
+    fn warns_above_entrainment_range() {
+        let program = Program::parse("00:00 freq=10 headless\n00:10 freq=2000 >linear\n00:20 freq=3000\n00:30 freq=10").unwrap();
+        let warnings = program.validate();
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(warnings[0].starts_with("00:10") && warnings[0].contains("amplitude modulation"), "{warnings:?}");
+
+        assert!(Program::parse("00:00 freq=100 headless").unwrap().validate().is_empty());
+    }
+
+    #[test]
+    fn mode_colors_tint_the_starting_on_color() {
+        let blue = Color { r: 0x40, g: 0x80, b: 0xFF, a: 255 };
+        let settings = "binaural_on=#4080FF isochronic_on=#FFFFFF";
+
+        let binaural = Program::parse(&format!("00:00 freq=6 {settings} binaural\n00:10 vol=1")).unwrap();
+        assert_eq!(binaural.params_at(0.0).on, blue);
+        assert_eq!(binaural.params_at(10.0).on, blue);
+
+        let isochronic = Program::parse(&format!("00:00 freq=6 {settings}")).unwrap();
+        assert_eq!(isochronic.params_at(0.0).on, Color::WHITE);
+
+        // An explicit color wins
+        let explicit = Program::parse(&format!("00:00 freq=6 on=#FF0000 {settings} binaural")).unwrap();
+        assert_eq!(explicit.params_at(0.0).on, Color { r: 255, g: 0, b: 0, a: 255 });
+
+        let reparsed = Program::parse(&binaural.to_source()).unwrap();
+        assert_eq!(reparsed.settings, binaural.settings);
+        assert!(Program::parse("00:00 freq=6\n00:10 binaural_on=#0000FF").is_err());
+    }
+
+    #[test]
+    fn monaural_setting() {
+        let program = Program::parse("00:00 freq=6 monaural\n00:10 duty=0.3").unwrap();
+        assert!(program.settings.monaural && !program.settings.binaural);
+        assert_eq!(program.warnings, ["00:10: 'duty' has no effect on monaural audio"]);
+        assert!(Program::parse(&program.to_source()).unwrap().settings.monaural);
+
+        assert!(Program::parse("00:00 freq=6 monaural binaural").is_err());
+        assert!(Program::parse("00:00 freq=6\n00:10 monaural").is_err());
+    }
+
+    #[test]
+    fn noise_parses_clamps_and_round_trips() {
+        let program = Program::parse("00:00 freq=6 noise=0.2\n00:10 noise=4 >linear").unwrap();
+        assert_eq!(program.params_at(0.0).noise, 0.2);
+        assert_eq!(program.params_at(10.0).noise, 1.0);
+        assert!((program.params_at(5.0).noise - 0.6).abs() < 1e-6);
+
+        let reparsed = Program::parse(&program.to_source()).unwrap();
+        assert_eq!(reparsed.params_at(5.0).noise, program.params_at(5.0).noise);
+    }
+
+    #[test]
+    fn field_curve_overrides_keyframe_curve() {
+        let program = Program::parse("00:00 vol=0 on=#000000\n00:10 vol=1 on=#FFFFFF >smooth color:step").unwrap();
+
+        let mid = program.params_at(5.0);
+        assert!((mid.vol - 0.5).abs() < 1e-6);
+        assert_eq!(mid.on, Color::BLACK); // color holds until the keyframe
+        let late = program.params_at(9.0);
+        assert!((late.vol - Curve::Smooth.apply(0.9) as f32).abs() < 1e-6);
+        assert_eq!(late.on, Color::BLACK);
+        assert_eq!(program.params_at(10.0).on, Color::WHITE);
+
+        // Same as the field>curve form, which is what the source format writes
+        let arrow = Program::parse("00:00 vol=0 on=#000000\n00:10 vol=1 on=#FFFFFF >smooth color>step").unwrap();
+        assert_eq!(arrow.params_at(9.0), late);
+        let source = program.to_source();
+        assert!(source.contains(">smooth color>step"), "{source}");
+
+        assert!(Program::parse("00:00 vol=0\n00:10 vol=1 shade:step").is_err());
+        assert!(Program::parse("00:00 vol=0\n00:10 vol=1 color:bounce").is_err());
+        assert_eq!(Program::parse("00:00 vol=0 vol:linear").unwrap().warnings.len(), 1);
+    }
+
+    #[test]
+    fn safety_warnings_sample_through_transitions() {
+        // Neither keyframe is in the band, but the ramp between them crosses it
+        let ramp = Program::parse("00:00 freq=10\n00:10 freq=40 >smooth\n00:20 freq=10 >linear").unwrap();
+        let warnings = ramp.safety_warnings();
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert!(warnings[0].starts_with("00:02") && warnings[1].starts_with("00:15"), "{warnings:?}");
+
+        // A step jumps over the band without flashing inside it
+        assert!(Program::parse("00:00 freq=10\n00:10 freq=40").unwrap().safety_warnings().is_empty());
+
+        let steady = Program::parse("00:00 freq=20").unwrap();
+        assert_eq!(steady.safety_warnings().len(), 1);
+        assert!(Program::parse("00:00 freq=20 headless").unwrap().safety_warnings().is_empty());
+        assert!(Program::parse("00:00 freq=20 on=#000000").unwrap().safety_warnings().is_empty());
+    }
+
+    #[test]
+    fn concurrent_lookups_agree_with_uncached() {
+        let source = (0..200).map(|i| format!("{:02}:{:02} freq={} >linear", i / 60, i % 60, 5 + i % 7));
+        let program = Arc::new(Program::parse(&source.collect::<Vec<_>>().join("\n")).unwrap());
+        let expected: Vec<Params> = (0..4000).map(|i| program.params_at_uncached(f64::from(i) * 0.05)).collect();
+
+        // Readers walking in different directions fight over the shared cache
+        let threads: Vec<_> = (0..4)
+            .map(|reader| {
+                let program = program.clone();
+                let expected = expected.clone();
+                std::thread::spawn(move || {
+                    for round in 0..5 {
+                        for step in 0..expected.len() {
+                            let i = if (reader + round) % 2 == 0 { step } else { expected.len() - 1 - step };
+                            let time = i as f64 * 0.05;
+                            assert_eq!(program.params_at(time), expected[i], "reader {reader} at {time}");
+                            assert_eq!(program.params_at_uncached(time), expected[i]);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn crossfade_reseeks_into_new_program() {
+        let old = Program::parse("00:00 freq=10 vol=0.5\n01:00 freq=10").unwrap();
+        let new = Program::parse("00:00 invert_duty freq=4 duty=0.2\n01:00 freq=8 duty=0.4 >linear").unwrap();
+        let reloaded = old.crossfade_to(&new, 30.0, 1.0);
+
+        // Unchanged until the reload, then halfway through the crossfade
+        assert!((reloaded.params_at(29.0).freq - 10.0).abs() < 1e-9);
+        let target = new.params_at(31.0).freq;
+        assert!((reloaded.params_at(30.5).freq - (10.0 + target) / 2.0).abs() < 1e-6);
+
+        // Follows the new timeline afterwards, including its settings
+        for time in [31.0, 45.0, 59.0, 90.0] {
+            let (a, b) = (reloaded.params_at(time), new.params_at(time));
+            assert!((a.freq - b.freq).abs() < 1e-6, "{time}");
+            assert!((a.duty - b.duty).abs() < 1e-6, "{time}");
+        }
+        assert_eq!(reloaded.duration, new.duration);
+    }
 }
\ No newline at end of file
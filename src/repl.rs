@@ -0,0 +1,172 @@
+//! Interactive live control of a constant program's audio from stdin.
+//!
+//! Each line is a command such as `freq 8` or `vol 0.7`. Changes ramp from the
+//! current sound to the new value over the time set with `smooth <secs>`.
+
+use crate::audio::{self, EngineOptions, SyncState};
+use crate::program::{LiveProgram, Params, Program, Settings};
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::io::BufRead;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Shortest ramp between values, so instant changes don't click.
+const MIN_RAMP_SECS: f64 = 0.03;
+
+/// Wait for the exit fade beyond its length before giving up.
+const EXIT_GRACE: Duration = Duration::from_millis(500);
+
+const HELP: &str = "\
+commands:
+  freq <hz>        pulse frequency
+  tone <hz>        carrier tone
+  vol <0-1>        volume
+  duty <0.01-0.99> duty cycle
+  smooth <secs>    ramp later changes over this long (0 = instant)
+  help             show this list
+  quit             fade out and exit";
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Commands
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// A single REPL command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Freq(f64),
+    Tone(f32),
+    Vol(f32),
+    Duty(f32),
+    Smooth(f64),
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// Parse a line of input; blank lines are `None`.
+    fn parse(line: &str) -> Result<Option<Self>> {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(None);
+        };
+        let arg = words.next();
+        if words.next().is_some() {
+            bail!("'{name}' takes at most one value");
+        }
+
+        let number = |min: f64, max: f64| -> Result<f64> {
+            let arg = arg.with_context(|| format!("'{name}' needs a value"))?;
+            let value: f64 = arg.parse().with_context(|| format!("invalid number '{arg}'"))?;
+            if !(min..=max).contains(&value) {
+                bail!("{name} must be in [{min}, {max}]");
+            }
+            Ok(value)
+        };
+
+        let command = match name {
+            "freq" => Self::Freq(number(f64::MIN_POSITIVE, f64::MAX)?),
+            "tone" => Self::Tone(number(f64::MIN_POSITIVE, f64::from(f32::MAX))? as f32),
+            "vol" => Self::Vol(number(0.0, 1.0)? as f32),
+            "duty" => Self::Duty(number(0.01, 0.99)? as f32),
+            "smooth" => Self::Smooth(number(0.0, f64::MAX)?),
+            "help" | "?" => Self::Help,
+            "quit" | "exit" => Self::Quit,
+            _ => bail!("unknown command '{name}' (try 'help')"),
+        };
+        Ok(Some(command))
+    }
+
+    /// Apply a parameter change; returns `false` for commands that aren't one.
+    fn apply(self, params: &mut Params) -> bool {
+        match self {
+            Self::Freq(freq) => params.freq = freq,
+            Self::Tone(tone) => params.tone = tone,
+            Self::Vol(vol) => params.vol = vol,
+            Self::Duty(duty) => params.duty = duty,
+            Self::Smooth(_) | Self::Help | Self::Quit => return false,
+        }
+        true
+    }
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Entry Point
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Play `program` and apply commands read from stdin until `quit` or end of input.
+pub fn run(program: Program, engine: EngineOptions, device: Option<&str>) -> Result<()> {
+    let settings = program.settings;
+    let mut target = program.params_at(0.0);
+    let live = Arc::new(LiveProgram::new(Arc::new(program)));
+    let sync = Arc::new(SyncState::new());
+    let _stream = audio::start(live.clone(), sync.clone(), engine, device)?;
+
+    info!("Live control ready, type 'help' for commands");
+    let mut ramp_secs = 0.0;
+
+    for line in std::io::stdin().lock().lines() {
+        let command = match Command::parse(&line.context("reading stdin")?) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("error: {e:#}");
+                continue;
+            }
+        };
+
+        match command {
+            Command::Quit => break,
+            Command::Help => println!("{HELP}"),
+            Command::Smooth(secs) => ramp_secs = secs,
+            command => {
+                command.apply(&mut target);
+                ramp_to(&live, &sync, target, ramp_secs, settings);
+            }
+        }
+    }
+
+    info!("Fading out");
+    sync.stopping.store(true, Ordering::Release);
+    let started = Instant::now();
+    let timeout = Duration::from_secs_f64(engine.fade_out_secs()) + EXIT_GRACE;
+    while !sync.stopped.load(Ordering::Acquire) && started.elapsed() < timeout {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+/// Ramp from whatever is playing now to `target`.
+fn ramp_to(live: &LiveProgram, sync: &SyncState, target: Params, secs: f64, settings: Settings) {
+    let at = sync.write_time();
+    let from = live.load().params_at_uncached(at);
+    live.store(Program::ramp(from, target, at, secs.max(MIN_RAMP_SECS), settings));
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Tests
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_map_to_changes() {
+        assert_eq!(Command::parse("freq 8").unwrap(), Some(Command::Freq(8.0)));
+        assert_eq!(Command::parse("  vol   0.7 ").unwrap(), Some(Command::Vol(0.7)));
+        assert_eq!(Command::parse("smooth 2").unwrap(), Some(Command::Smooth(2.0)));
+        assert_eq!(Command::parse("quit").unwrap(), Some(Command::Quit));
+        assert_eq!(Command::parse("").unwrap(), None);
+
+        for bad in ["freq", "freq fast", "freq -1", "vol 2", "duty 1", "freq 8 9", "louder"] {
+            assert!(Command::parse(bad).is_err(), "{bad}");
+        }
+
+        let mut params = Params::default();
+        assert!(Command::Duty(0.3).apply(&mut params));
+        assert_eq!(params.duty, 0.3);
+        assert!(!Command::Smooth(1.0).apply(&mut params));
+    }
+}
\ No newline at end of file
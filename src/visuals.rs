@@ -1,29 +1,96 @@
-use crate::audio::{self, SyncState};
-use crate::program::Program;
+use crate::audio::{self, EngineOptions, Envelope, SyncState};
+use crate::program::{LiveProgram, Params, Program, FLICKER_DANGER_BAND};
+use crate::Color;
 use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
 use log::{error, info, warn};
 use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use winit::application::ApplicationHandler;
 use winit::dpi::LogicalSize;
 use winit::event::{ElementState, KeyEvent, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
 use winit::keyboard::{Key, NamedKey};
-use winit::window::{Fullscreen, Window, WindowId};
+use winit::window::{Fullscreen, Window, WindowAttributes, WindowId, WindowLevel};
 
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // GPU State
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
+/// Draws solid rectangles given as instances of a unit quad.
+const RECT_SHADER: &str = r"
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) bounds: vec4<f32>,
+    @location(1) color: vec4<f32>,
+) -> VertexOut {
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    var out: VertexOut;
+    out.position = vec4<f32>(mix(bounds.xy, bounds.zw, corner), 0.0, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    return in.color;
+}
+";
+
+/// Per-instance data of the rect pipeline.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct RectInstance {
+    /// Bounds in normalized device coordinates (x0, y0, x1, y1).
+    bounds: [f32; 4],
+    /// Linear RGBA color.
+    color: [f32; 4],
+}
+
+/// An axis-aligned rectangle in window pixels, origin at the top left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+impl Rect {
+    /// Bounds in normalized device coordinates for a surface of the given size.
+    fn to_ndc(self, width: f32, height: f32) -> [f32; 4] {
+        [
+            self.x / width * 2.0 - 1.0,
+            1.0 - self.y / height * 2.0,
+            (self.x + self.w) / width * 2.0 - 1.0,
+            1.0 - (self.y + self.h) / height * 2.0,
+        ]
+    }
+}
+
 struct GpuState {
     surface: wgpu::Surface<'static>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
+    rect_pipeline: wgpu::RenderPipeline,
+    rect_buffer: wgpu::Buffer,
+    rect_capacity: usize,
 }
 
 impl GpuState {
-    async fn new(window: Arc<Window>) -> Result<Self> {
+    /// Set up rendering to `window`, with a surface that blends with the
+    /// desktop behind it if `transparent` and the compositor supports it.
+    async fn new(window: Arc<Window>, transparent: bool) -> Result<Self> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
@@ -58,27 +125,129 @@ impl GpuState {
             .copied()
             .unwrap_or(caps.formats[0]);
 
+        let mut alpha_mode = caps.alpha_modes[0];
+        if transparent {
+            let blended = [wgpu::CompositeAlphaMode::PreMultiplied, wgpu::CompositeAlphaMode::PostMultiplied];
+            match blended.into_iter().find(|mode| caps.alpha_modes.contains(mode)) {
+                Some(mode) => alpha_mode = mode,
+                None => warn!("The surface doesn't support transparency; the overlay will be opaque"),
+            }
+        }
+
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format,
             width: size.width.max(1),
             height: size.height.max(1),
             present_mode: wgpu::PresentMode::Fifo, // VSync for smooth visuals
-            alpha_mode: caps.alpha_modes[0],
+            alpha_mode,
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
 
         surface.configure(&device, &config);
 
+        let rect_pipeline = Self::create_rect_pipeline(&device, format);
+        let rect_capacity = 16;
+        let rect_buffer = Self::create_rect_buffer(&device, rect_capacity);
+
         Ok(Self {
             surface,
             device,
             queue,
             config,
+            rect_pipeline,
+            rect_buffer,
+            rect_capacity,
         })
     }
 
+    fn create_rect_pipeline(device: &wgpu::Device, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Rect Shader"),
+            source: wgpu::ShaderSource::Wgsl(RECT_SHADER.into()),
+        });
+
+        let attributes = wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4];
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Rect Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: size_of::<RectInstance>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &attributes,
+                }],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    // Rects are written like the clear color, alpha included, so an
+                    // overlay's translucent flash doesn't blend with what is under it
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    fn create_rect_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Rect Instances"),
+            size: (capacity * size_of::<RectInstance>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Upload rect instances, growing the buffer if needed.
+    fn upload_rects(&mut self, rects: &[(Rect, wgpu::Color)]) {
+        if rects.len() > self.rect_capacity {
+            self.rect_capacity = rects.len().next_power_of_two();
+            self.rect_buffer = Self::create_rect_buffer(&self.device, self.rect_capacity);
+        }
+
+        let (width, height) = (self.config.width as f32, self.config.height as f32);
+        let instances: Vec<RectInstance> = rects
+            .iter()
+            .map(|(rect, color)| RectInstance {
+                bounds: rect.to_ndc(width, height),
+                color: [color.r as f32, color.g as f32, color.b as f32, color.a as f32],
+            })
+            .collect();
+        self.queue
+            .write_buffer(&self.rect_buffer, 0, bytemuck::cast_slice(&instances));
+    }
+
+    /// `color` at `opacity`, in the form the surface's alpha mode expects.
+    fn with_opacity(&self, color: wgpu::Color, opacity: f64) -> wgpu::Color {
+        match self.config.alpha_mode {
+            wgpu::CompositeAlphaMode::PreMultiplied => wgpu::Color {
+                r: color.r * opacity,
+                g: color.g * opacity,
+                b: color.b * opacity,
+                a: opacity,
+            },
+            wgpu::CompositeAlphaMode::PostMultiplied => wgpu::Color { a: opacity, ..color },
+            _ => color,
+        }
+    }
+
     fn resize(&mut self, width: u32, height: u32) {
         if width == 0 || height == 0 {
             return;
@@ -88,7 +257,12 @@ impl GpuState {
         self.surface.configure(&self.device, &self.config);
     }
 
-    fn render(&self, color: wgpu::Color) -> Result<(), wgpu::SurfaceError> {
+    /// Clear to `color`, then draw `rects` on top.
+    fn render(&mut self, color: wgpu::Color, rects: &[(Rect, wgpu::Color)]) -> Result<(), wgpu::SurfaceError> {
+        if !rects.is_empty() {
+            self.upload_rects(rects);
+        }
+
         let output = self.surface.get_current_texture()?;
         let view = output.texture.create_view(&Default::default());
 
@@ -98,7 +272,7 @@ impl GpuState {
 
         // Clear to the specified color
         {
-            let _pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Clear Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &view,
@@ -113,6 +287,12 @@ impl GpuState {
                 timestamp_writes: None,
                 occlusion_query_set: None,
             });
+
+            if !rects.is_empty() {
+                pass.set_pipeline(&self.rect_pipeline);
+                pass.set_vertex_buffer(0, self.rect_buffer.slice(..));
+                pass.draw(0..4, 0..rects.len() as u32);
+            }
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -122,75 +302,699 @@ impl GpuState {
     }
 }
 
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Visual Color
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Visual brightness [0, 1] at the given pulse phase.
+///
+/// In linked mode this is the audio envelope itself, so a louder moment of the
+/// pulse is always a brighter one.
+fn brightness(params: &Params, phase: f64, linked: bool) -> f64 {
+    if linked {
+        Envelope::from_params(params).value(phase)
+    } else if phase < f64::from(params.duty) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Time since the audio engine last published its position, for extrapolating
+/// the pulse phase between audio buffers.
+#[derive(Debug, Clone, Copy)]
+struct AudioClock {
+    frames_written: u64,
+    updated: Instant,
+}
+
+impl AudioClock {
+    fn new(now: Instant) -> Self {
+        Self { frames_written: 0, updated: now }
+    }
+
+    /// Seconds since the last audio update, at most one buffer.
+    ///
+    /// Audio is published once per buffer, so this never runs further ahead
+    /// than the next update would move the position anyway.
+    fn elapsed(&mut self, sync: &SyncState, now: Instant) -> f64 {
+        let written = sync.frames_written.load(Ordering::Acquire);
+        if written != self.frames_written {
+            self.frames_written = written;
+            self.updated = now;
+        }
+
+        let rate = sync.sample_rate.load(Ordering::Acquire);
+        if rate == 0 || sync.paused.load(Ordering::Acquire) {
+            return 0.0;
+        }
+        let buffer_secs = f64::from(sync.buffer_frames.load(Ordering::Acquire)) / f64::from(rate);
+        now.duration_since(self.updated).as_secs_f64().min(buffer_secs)
+    }
+}
+
+/// Advance a pulse phase by `elapsed` seconds at `freq`.
+#[inline]
+fn extrapolate_phase(phase: f64, freq: f64, elapsed: f64) -> f64 {
+    (phase + freq * elapsed).rem_euclid(1.0)
+}
+
+/// Calculate the visual color based on current audio state, `elapsed`
+/// seconds after the audio engine last published it.
+fn visual_color(program: &Program, sync: &SyncState, elapsed: f64) -> wgpu::Color {
+    if program.settings.headless {
+        return wgpu::Color {
+            r: 0.1,
+            g: 0.1,
+            b: 0.1,
+            a: 1.0,
+        };
+    }
+
+    // Get current playback time from audio sync state
+    let time = sync.playback_time() + elapsed;
+    let params = program.params_at_uncached(time);
+
+    // Get phase synchronized with audio
+    let phase = extrapolate_phase(sync.visual_phase(params.freq), params.freq, elapsed);
+
+    let brightness = brightness(&params, phase, program.settings.linked) * sync.intensity();
+
+    // Interpolate between off and on colors in linear space
+    let on = params.on.to_linear();
+    let off = params.off.to_linear();
+
+    wgpu::Color {
+        r: off[0] + (on[0] - off[0]) * brightness,
+        g: off[1] + (on[1] - off[1]) * brightness,
+        b: off[2] + (on[2] - off[2]) * brightness,
+        a: 1.0,
+    }
+}
+
+/// Fraction of the brightness cap below which the soft knee leaves values unchanged.
+const KNEE_START: f64 = 0.8;
+
+/// Ceiling on the displayed brightness of each color channel, in linear light.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BrightnessCap {
+    pub max: f64,
+    /// Approach the ceiling smoothly instead of clipping at it.
+    pub knee: bool,
+}
+
+impl BrightnessCap {
+    /// Capped value of one linear channel.
+    fn level(self, value: f64) -> f64 {
+        let start = self.max * KNEE_START;
+        if !self.knee {
+            value.min(self.max)
+        } else if value <= start {
+            value
+        } else {
+            // Exponential shoulder: slope 1 at the knee, approaching the cap
+            let range = self.max - start;
+            start + range * (1.0 - (-(value - start) / range).exp())
+        }
+    }
+
+    fn apply(self, color: wgpu::Color) -> wgpu::Color {
+        wgpu::Color {
+            r: self.level(color.r),
+            g: self.level(color.g),
+            b: self.level(color.b),
+            a: color.a,
+        }
+    }
+}
+
+/// Aspect ratio of the flash area with `--lock-aspect`.
+const LOCKED_ASPECT: f32 = 16.0 / 9.0;
+
+/// The largest centered area of the given aspect ratio that fits the surface.
+fn flash_area(width: f32, height: f32, aspect: f32) -> Rect {
+    if width / height > aspect {
+        // Wider than the flash: pillarbox
+        let w = height * aspect;
+        Rect { x: (width - w) / 2.0, y: 0.0, w, h: height }
+    } else {
+        // Taller than the flash: letterbox
+        let h = width / aspect;
+        Rect { x: 0.0, y: (height - h) / 2.0, w: width, h }
+    }
+}
+
+/// Largest per-channel difference from the last rendered color that is still
+/// drawn as the same color.
+const COLOR_EPSILON: f64 = 1e-4;
+
+/// How long to wait before checking the color again after skipping a redraw.
+const IDLE_POLL: Duration = Duration::from_millis(1);
+
+/// Whether `next` differs enough from the last rendered color to be drawn.
+fn needs_render(last: Option<wgpu::Color>, next: wgpu::Color) -> bool {
+    let Some(last) = last else {
+        return true;
+    };
+    (last.r - next.r).abs() > COLOR_EPSILON
+        || (last.g - next.g).abs() > COLOR_EPSILON
+        || (last.b - next.b).abs() > COLOR_EPSILON
+}
+
+/// Minimum time between window title updates.
+const TITLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Window title with the live readout, e.g.
+/// "Isochronator - 8.3 Hz - 02:15 / 10:00 - holding".
+fn title_text(title: &str, freq: f64, time: f64, duration: f64, status: &str) -> String {
+    let position = if duration.is_finite() {
+        format!("{} / {}", clock(time), clock(duration))
+    } else {
+        clock(time)
+    };
+    format!("{title} - {freq:.1} Hz - {position} - {status}")
+}
+
+/// Duration of the fade to the idle color after pausing, in seconds.
+const IDLE_FADE_SECS: f64 = 0.3;
+
+/// Fade from the last displayed color: to the idle color while paused, or
+/// to the off color while the session exits.
+struct IdleFade {
+    from: wgpu::Color,
+    started: Instant,
+}
+
+impl IdleFade {
+    fn color(&self, idle: wgpu::Color, now: Instant) -> wgpu::Color {
+        self.color_over(idle, now, IDLE_FADE_SECS)
+    }
+
+    /// Color at `now` of a fade that reaches `to` after `secs`.
+    fn color_over(&self, to: wgpu::Color, now: Instant, secs: f64) -> wgpu::Color {
+        let t = if secs > 0.0 {
+            (now.duration_since(self.started).as_secs_f64() / secs).min(1.0)
+        } else {
+            1.0
+        };
+        wgpu::Color {
+            r: self.from.r + (to.r - self.from.r) * t,
+            g: self.from.g + (to.g - self.from.g) * t,
+            b: self.from.b + (to.b - self.from.b) * t,
+            a: 1.0,
+        }
+    }
+}
+
+/// Size of the bars drawn by the sync overlay, in pixels.
+const SYNC_BAR_WIDTH: f32 = 4.0;
+const SYNC_BAR_HEIGHT: f32 = 16.0;
+
+/// Sync overlay bar for the raw audio phase.
+const RAW_PHASE_COLOR: wgpu::Color = wgpu::Color { r: 1.0, g: 0.05, b: 0.05, a: 1.0 };
+
+/// Sync overlay bar for the latency-compensated phase the flash uses.
+const VISUAL_PHASE_COLOR: wgpu::Color = wgpu::Color { r: 0.05, g: 1.0, b: 0.05, a: 1.0 };
+
+/// Bar at `phase` [0, 1) across the surface, in `row` counted up from the bottom edge.
+fn phase_bar(phase: f64, row: u32, width: f32, height: f32) -> Rect {
+    Rect {
+        x: phase as f32 * (width - SYNC_BAR_WIDTH),
+        y: height - (row + 1) as f32 * SYNC_BAR_HEIGHT,
+        w: SYNC_BAR_WIDTH,
+        h: SYNC_BAR_HEIGHT,
+    }
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Text HUD
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Size of one font pixel of the HUD text, in window pixels.
+const HUD_PIXEL: f32 = 4.0;
+
+/// Distance of the HUD from the top left corner, in window pixels.
+const HUD_MARGIN: f32 = 16.0;
+
+/// Dim text on a black backdrop, so the HUD stays readable without adding
+/// a bright patch to the stimulus.
+const HUD_TEXT_COLOR: wgpu::Color = wgpu::Color { r: 0.35, g: 0.35, b: 0.35, a: 1.0 };
+const HUD_BACKDROP_COLOR: wgpu::Color = wgpu::Color::BLACK;
+
+/// Rows of a 3x5 bitmap glyph, top first, with the left column in bit 2.
+///
+/// Covers only the characters the HUD prints; anything else is blank.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'z' => [0b000, 0b111, 0b011, 0b110, 0b111],
+        'v' => [0b000, 0b101, 0b101, 0b101, 0b010],
+        'o' => [0b000, 0b111, 0b101, 0b101, 0b111],
+        'l' => [0b110, 0b010, 0b010, 0b010, 0b111],
+        _ => [0; 5],
+    }
+}
+
+/// Status line shown by the HUD, e.g. "01:23  10.0 Hz  vol 50%".
+fn hud_text(program: &Program, time: f64) -> String {
+    let params = program.params_at_uncached(time);
+    format!("{}  {:.1} Hz  vol {:.0}%", clock(time), params.freq, params.vol * 100.0)
+}
+
+/// Playback time as minutes and seconds, e.g. "02:15".
+fn clock(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+/// Rectangles that draw `text` with its top left corner at (`x`, `y`).
+///
+/// Lit pixels in a glyph row are merged into one rectangle per run.
+fn text_rects(text: &str, x: f32, y: f32) -> Vec<Rect> {
+    let mut rects = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as f32 * 4.0 * HUD_PIXEL;
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            let mut col = 0;
+            while col < 3 {
+                let lit = |col: u32| bits & (0b100 >> col) != 0;
+                if !lit(col) {
+                    col += 1;
+                    continue;
+                }
+                let start = col;
+                while col < 3 && lit(col) {
+                    col += 1;
+                }
+                rects.push(Rect {
+                    x: left + start as f32 * HUD_PIXEL,
+                    y: y + row as f32 * HUD_PIXEL,
+                    w: (col - start) as f32 * HUD_PIXEL,
+                    h: HUD_PIXEL,
+                });
+            }
+        }
+    }
+    rects
+}
+
+/// Backdrop and text of the HUD for the program state at `time`.
+fn hud_rects(program: &Program, time: f64) -> Vec<(Rect, wgpu::Color)> {
+    let text = hud_text(program, time);
+    let backdrop = Rect {
+        x: HUD_MARGIN - HUD_PIXEL,
+        y: HUD_MARGIN - HUD_PIXEL,
+        w: (text.chars().count() * 4 + 1) as f32 * HUD_PIXEL,
+        h: 7.0 * HUD_PIXEL,
+    };
+
+    let mut rects = vec![(backdrop, HUD_BACKDROP_COLOR)];
+    rects.extend(text_rects(&text, HUD_MARGIN, HUD_MARGIN).into_iter().map(|r| (r, HUD_TEXT_COLOR)));
+    rects
+}
+
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Session Application
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
+/// Runtime options for a session that are not part of the program.
+///
+/// Live edits nudge the pulse frequency with Left and Right and the volume
+/// with `+` and `-`; Up and Down adjust [`Self::intensity`].
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    /// Send MIDI notes in sync with the pulses.
+    pub midi_out: bool,
+    /// Options passed to the audio engine.
+    pub engine: EngineOptions,
+    /// Color shown while paused (default: the current off color).
+    pub idle_color: Option<Color>,
+    /// Hold the final frame when the program ends until a key is pressed.
+    pub freeze_on_exit: bool,
+    /// Keep the flash area at 16:9, filling the rest with the letterbox color.
+    pub lock_aspect: bool,
+    /// Color of the letterbox bars (default: black).
+    pub letterbox_color: Option<Color>,
+    /// Master intensity [0, 1] scaling audio and flash, adjustable with Up and Down
+    /// (default: full).
+    pub intensity: Option<f64>,
+    /// Initial audio-visual offset in milliseconds, adjustable with `[` and `]`.
+    pub av_offset_ms: i32,
+    /// Draw the raw and compensated pulse phase as moving bars (toggle with F3).
+    pub visualize_sync: bool,
+    /// Show elapsed time, frequency and volume in the corner (toggle with F1).
+    pub hud: bool,
+    /// Iterations of a program built with [`Program::repeated`], for logging.
+    pub repeat: Option<Repeat>,
+    /// Log each keyframe as playback passes it.
+    pub log_keyframes: bool,
+    /// Limit on how bright the flash may get.
+    pub brightness_cap: Option<BrightnessCap>,
+    /// Keep the display from sleeping for the whole session.
+    pub prevent_sleep: bool,
+    /// End the session after this many seconds of playback, even if the
+    /// program continues (default: when the program ends).
+    pub duration: Option<f64>,
+    /// Extrapolate the pulse phase between audio buffers for smoother flashes.
+    pub smooth_phase: bool,
+    /// Show the flash as a borderless, always-on-top, click-through window
+    /// at this opacity over the desktop.
+    pub overlay: Option<f64>,
+    /// Name of the audio output device (default: the system default).
+    pub device: Option<String>,
+    /// Play programs, reloads and live edits that flash within
+    /// [`FLICKER_DANGER_BAND`] instead of refusing them.
+    pub allow_unsafe_flicker: bool,
+    /// Program file to reload into the session whenever it changes.
+    pub watch: Option<PathBuf>,
+}
+
+/// Layout of a program repeated with `--repeat-count`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Repeat {
+    pub count: u32,
+    /// Time from the start of one play to the start of the next, in seconds.
+    pub period: f64,
+}
+
+impl Repeat {
+    /// One-based iteration playing at `time`.
+    fn iteration_at(self, time: f64) -> u32 {
+        ((time / self.period).floor() as u32 + 1).min(self.count)
+    }
+
+    /// Log the iteration once playback reaches a new one.
+    fn log_progress(self, time: f64, last: &mut u32) {
+        let iteration = self.iteration_at(time);
+        if iteration != *last {
+            *last = iteration;
+            info!("Iteration {iteration} of {}", self.count);
+        }
+    }
+}
+
+/// Logs playback milestones as they are reached.
+#[derive(Debug, Default)]
+struct ProgressLog {
+    repeat: Option<Repeat>,
+    iteration: u32,
+    keyframes: bool,
+    last_time: f64,
+}
+
+impl ProgressLog {
+    fn new(options: &SessionOptions) -> Self {
+        Self {
+            repeat: options.repeat,
+            keyframes: options.log_keyframes,
+            ..Self::default()
+        }
+    }
+
+    fn update(&mut self, program: &Program, time: f64) {
+        if let Some(repeat) = self.repeat {
+            repeat.log_progress(time, &mut self.iteration);
+        }
+
+        if self.keyframes {
+            for index in program.keyframes_crossed(self.last_time, time) {
+                let (at, changes) = program.keyframe_changes(index);
+                let changes: Vec<String> = changes.iter().map(|c| format!("{}={}", c.key, c.to)).collect();
+                let changes = if changes.is_empty() { "no changes".into() } else { changes.join(" ") };
+                info!("Keyframe at {at:.2}s (playback {time:.2}s): {changes}");
+            }
+        }
+
+        self.last_time = time;
+    }
+}
+
+/// Largest audio-visual offset in either direction, in milliseconds.
+pub const AV_OFFSET_LIMIT_MS: i32 = 500;
+
+/// Change in intensity per Up or Down key press.
+const INTENSITY_STEP: f64 = 0.1;
+
+/// Change in pulse frequency per Left or Right key press, in Hz.
+const LIVE_FREQ_STEP: f64 = 0.5;
+
+/// Change in volume per `+` or `-` key press.
+const LIVE_VOL_STEP: f32 = 0.05;
+
+/// Ramp time from the current sound to a live edit, in seconds.
+const LIVE_EDIT_RAMP_SECS: f64 = 0.05;
+
+/// Lowest pulse frequency reachable with live edits, in Hz.
+const LIVE_MIN_FREQ: f64 = 0.5;
+
+/// Adjust frequency and volume by the given steps, staying within their valid ranges.
+fn nudge_params(params: Params, freq_step: f64, vol_step: f32) -> Params {
+    Params {
+        freq: (params.freq + freq_step).max(LIVE_MIN_FREQ),
+        vol: (params.vol + vol_step).clamp(0.0, 1.0),
+        via: None,
+        ..params
+    }
+}
+
+/// Replace `live` with a constant program that ramps from what plays at `at`
+/// to the result of `edit`.
+///
+/// Keeps the current program if the edit would flash within
+/// [`FLICKER_DANGER_BAND`], unless `allow_unsafe_flicker` is set.
+fn apply_live_edit(
+    live: &LiveProgram,
+    at: f64,
+    allow_unsafe_flicker: bool,
+    edit: impl FnOnce(Params) -> Params,
+) -> Result<Params> {
+    let program = live.load();
+    let settings = program.settings;
+    let mut from = program.params_at_uncached(at);
+    if settings.invert_duty {
+        // params_at_uncached reports the inverted duty; the new program inverts it again
+        from.duty = 1.0 - from.duty;
+    }
+    let to = edit(from);
+    let edited = Program::ramp(from, to, at, LIVE_EDIT_RAMP_SECS, settings);
+    ensure_flicker_safe(&edited, allow_unsafe_flicker)?;
+    live.store(edited);
+    Ok(to)
+}
+
+/// Apply a live offset adjustment, keeping it within the limit.
+fn nudge_av_offset(current: i32, step: i32) -> i32 {
+    (current + step).clamp(-AV_OFFSET_LIMIT_MS, AV_OFFSET_LIMIT_MS)
+}
+
+/// Attributes of the session window; an overlay is borderless, transparent,
+/// always on top and covers the screen.
+fn window_attributes(title: &str, size: LogicalSize<f64>, overlay: bool) -> WindowAttributes {
+    let attrs = Window::default_attributes()
+        .with_title(title)
+        .with_inner_size(size);
+    if !overlay {
+        return attrs;
+    }
+    attrs
+        .with_decorations(false)
+        .with_transparent(true)
+        .with_window_level(WindowLevel::AlwaysOnTop)
+        .with_maximized(true)
+}
+
+/// Wait for the audio to finish fading out beyond the fade's length before
+/// closing anyway, e.g. when the audio device has gone away.
+const EXIT_FADE_GRACE_SECS: f64 = 0.5;
+
+/// Lifecycle of a session window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SessionState {
+    Running,
+    /// The program has ended and the final frame is held until a key press.
+    Frozen,
+    /// The audio fades out while the flash fades from `from` to the off color.
+    FadingOut { from: wgpu::Color, started: Instant },
+    Finished,
+}
+
 struct SessionApp {
     window: Option<Arc<Window>>,
     gpu: Option<GpuState>,
-    program: Arc<Program>,
+    program: Arc<LiveProgram>,
+    options: SessionOptions,
 
     // Audio state
     audio_stream: Option<cpal::Stream>,
     sync: Arc<SyncState>,
 
     // Session control
-    session_complete: bool,
+    state: SessionState,
+    last_color: wgpu::Color,
+    idle_fade: Option<IdleFade>,
+    audio_clock: Option<AudioClock>,
+
+    // Last color presented; `None` forces the next frame to render
+    rendered: Option<wgpu::Color>,
+    skipped_redraw: bool,
+    show_sync: bool,
+    show_hud: bool,
+    progress: ProgressLog,
+
+    // Window title, suffixed with the live readout
+    title: &'static str,
+    shown_title: String,
+    title_updated: Option<Instant>,
 }
 
 impl SessionApp {
-    fn new(program: Arc<Program>) -> Self {
+    fn new(program: Arc<Program>, options: SessionOptions) -> Self {
+        let sync = Arc::new(SyncState::new());
+        sync.av_offset_ms
+            .store(nudge_av_offset(options.av_offset_ms, 0), Ordering::Relaxed);
+        sync.set_intensity(options.intensity.unwrap_or(1.0));
+
         Self {
             window: None,
             gpu: None,
-            program,
+            program: Arc::new(LiveProgram::new(program)),
+            show_sync: options.visualize_sync,
+            show_hud: options.hud,
+            progress: ProgressLog::new(&options),
+            audio_clock: options.smooth_phase.then(|| AudioClock::new(Instant::now())),
+            options,
             audio_stream: None,
-            sync: Arc::new(SyncState::new()),
-            session_complete: false,
+            sync,
+            state: SessionState::Running,
+            last_color: wgpu::Color::BLACK,
+            idle_fade: None,
+            rendered: None,
+            skipped_redraw: false,
+            title: "Isochronator",
+            shown_title: String::new(),
+            title_updated: None,
+        }
+    }
+
+    /// Pause or resume playback.
+    fn set_paused(&self, paused: bool) {
+        if self.sync.paused.swap(paused, Ordering::AcqRel) != paused {
+            info!("{}", if paused { "Paused" } else { "Resumed" });
         }
     }
 
-    /// Calculate the visual color based on current audio state.
-    fn compute_visual_color(&self) -> wgpu::Color {
-        if self.program.settings.headless {
-            return wgpu::Color {
-                r: 0.1,
-                g: 0.1,
-                b: 0.1,
-                a: 1.0,
+    /// Pause when the window loses focus. An overlay passes clicks through to
+    /// the desktop, so it loses focus without the user leaving the session and
+    /// could not get it back to resume; it keeps playing instead.
+    fn pause_on_focus_loss(&self) {
+        if self.options.overlay.is_none() {
+            self.set_paused(true);
+        }
+    }
+
+    /// Color to display this frame, fading to the idle color while paused.
+    fn frame_color(&mut self, now: Instant) -> wgpu::Color {
+        if self.state == SessionState::Frozen {
+            return self.last_color;
+        }
+
+        let program = self.program.load();
+
+        if let SessionState::FadingOut { from, started } = self.state {
+            let [r, g, b] = program.params_at_uncached(self.sync.playback_time()).off.to_linear();
+            let fade = IdleFade { from, started };
+            return fade.color_over(wgpu::Color { r, g, b, a: 1.0 }, now, self.options.engine.fade_out_secs());
+        }
+
+        if !self.sync.paused.load(Ordering::Acquire) {
+            self.idle_fade = None;
+            let elapsed = match &mut self.audio_clock {
+                Some(clock) => clock.elapsed(&self.sync, now),
+                None => 0.0,
             };
+            self.last_color = visual_color(&program, &self.sync, elapsed);
+            if let Some(cap) = self.options.brightness_cap {
+                self.last_color = cap.apply(self.last_color);
+            }
+            return self.last_color;
         }
 
-        // Get current playback time from audio sync state
-        let time = self.sync.playback_time();
-        let params = self.program.params_at(time);
+        let idle = match self.options.idle_color {
+            Some(color) => color,
+            None => program.params_at_uncached(self.sync.playback_time()).off,
+        }
+        .to_linear();
+        let idle = wgpu::Color { r: idle[0], g: idle[1], b: idle[2], a: 1.0 };
+
+        let from = self.last_color;
+        self.idle_fade
+            .get_or_insert(IdleFade { from, started: now })
+            .color(idle, now)
+    }
 
-        // Get phase synchronized with audio
-        let phase = self.sync.visual_phase(params.freq);
+    /// Change the playing parameters without restarting the session.
+    ///
+    /// The rest of the program's timeline is dropped in favor of holding the
+    /// edited values.
+    fn live_edit(&mut self, edit: impl FnOnce(Params) -> Params) {
+        if self.program.load().duration.is_finite() {
+            info!("Live edit: holding the edited values instead of continuing the program");
+        }
+        let allow_unsafe = self.options.allow_unsafe_flicker;
+        match apply_live_edit(&self.program, self.sync.write_time(), allow_unsafe, edit) {
+            Ok(params) => info!("Live edit: freq {:.2} Hz, vol {:.2}", params.freq, params.vol),
+            Err(e) => warn!("Keeping the current program: {e:#}"),
+        }
+        self.rendered = None;
+    }
 
-        // Determine if we're in the "on" portion of the duty cycle
-        let brightness = if phase < params.duty as f64 { 1.0 } else { 0.0 };
+    /// Show the current frequency, time and transition status in the window
+    /// title, at most every [`TITLE_INTERVAL`].
+    fn update_title(&mut self, now: Instant) {
+        if self.title_updated.is_some_and(|last| now.duration_since(last) < TITLE_INTERVAL) {
+            return;
+        }
+        self.title_updated = Some(now);
 
-        // Interpolate between off and on colors in linear space
-        let on = params.on.to_linear();
-        let off = params.off.to_linear();
+        let program = self.program.load();
+        let time = self.sync.playback_time();
+        let text = title_text(
+            self.title,
+            program.params_at_uncached(time).freq,
+            time,
+            program.duration,
+            &transition_status(&program, time),
+        );
+        if text == self.shown_title {
+            return;
+        }
 
-        wgpu::Color {
-            r: off[0] + (on[0] - off[0]) * brightness,
-            g: off[1] + (on[1] - off[1]) * brightness,
-            b: off[2] + (on[2] - off[2]) * brightness,
-            a: 1.0,
+        if let Some(window) = &self.window {
+            window.set_title(&text);
         }
+        self.shown_title = text;
     }
 
     /// Check if the session should end.
     fn check_session_complete(&mut self) {
-        if self.session_complete {
+        if self.state != SessionState::Running {
             return;
         }
 
-        let duration = self.program.duration;
+        let duration = session_end(self.program.load().duration, self.options.duration);
         if !duration.is_finite() {
             return; // Infinite program never ends
         }
@@ -198,7 +1002,38 @@ impl SessionApp {
         let time = self.sync.playback_time();
         if time >= duration {
             info!("Session complete at {time:.1}s");
-            self.session_complete = true;
+            if self.options.freeze_on_exit {
+                info!("Holding the final frame; press any key to exit");
+                self.sync.paused.store(true, Ordering::Release);
+                self.state = SessionState::Frozen;
+            } else {
+                self.begin_exit(Instant::now());
+            }
+        }
+    }
+
+    /// Fade the audio out and the flash to the off color before closing.
+    /// Asking again during the fade closes right away.
+    fn begin_exit(&mut self, now: Instant) {
+        if matches!(self.state, SessionState::FadingOut { .. }) {
+            self.state = SessionState::Finished;
+            return;
+        }
+        self.sync.stopping.store(true, Ordering::Release);
+        self.state = SessionState::FadingOut { from: self.last_color, started: now };
+        self.rendered = None;
+    }
+
+    /// Finish once both the flash and the audio have faded out.
+    fn check_exit_fade(&mut self, now: Instant) {
+        let SessionState::FadingOut { started, .. } = self.state else {
+            return;
+        };
+        let fade = self.options.engine.fade_out_secs();
+        let elapsed = now.duration_since(started).as_secs_f64();
+        let audio_done = self.sync.stopped.load(Ordering::Acquire) || elapsed >= fade + EXIT_FADE_GRACE_SECS;
+        if elapsed >= fade && audio_done {
+            self.state = SessionState::Finished;
         }
     }
 }
@@ -207,7 +1042,12 @@ impl ApplicationHandler for SessionApp {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         // Start audio if not already running
         if self.audio_stream.is_none() {
-            match audio::start(self.program.clone(), self.sync.clone()) {
+            match audio::start(
+                self.program.clone(),
+                self.sync.clone(),
+                self.options.engine,
+                self.options.device.as_deref(),
+            ) {
                 Ok(stream) => {
                     self.audio_stream = Some(stream);
                     info!("Audio started");
@@ -221,16 +1061,15 @@ impl ApplicationHandler for SessionApp {
         }
 
         // Create window
-        let headless = self.program.settings.headless;
+        let headless = self.program.load().settings.headless;
         let (title, size) = if headless {
             ("Isochronator (Audio Only)", LogicalSize::new(320.0, 120.0))
         } else {
             ("Isochronator", LogicalSize::new(854.0, 480.0))
         };
 
-        let attrs = Window::default_attributes()
-            .with_title(title)
-            .with_inner_size(size);
+        self.title = title;
+        let attrs = window_attributes(title, size, self.options.overlay.is_some());
 
         let window = match event_loop.create_window(attrs) {
             Ok(w) => Arc::new(w),
@@ -241,10 +1080,17 @@ impl ApplicationHandler for SessionApp {
             }
         };
 
+        // Let clicks pass through to the windows underneath
+        if self.options.overlay.is_some()
+            && let Err(e) = window.set_cursor_hittest(false)
+        {
+            warn!("Overlay window can't be made click-through: {e}");
+        }
+
         self.window = Some(window.clone());
 
         // Initialize GPU
-        match pollster::block_on(GpuState::new(window)) {
+        match pollster::block_on(GpuState::new(window, self.options.overlay.is_some())) {
             Ok(gpu) => {
                 self.gpu = Some(gpu);
                 info!("GPU initialized");
@@ -263,6 +1109,18 @@ impl ApplicationHandler for SessionApp {
                 event_loop.exit();
             }
 
+            WindowEvent::KeyboardInput {
+                event:
+                KeyEvent {
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } if self.state == SessionState::Frozen => {
+                info!("Key pressed, closing frozen session");
+                event_loop.exit();
+            }
+
             WindowEvent::KeyboardInput {
                 event:
                 KeyEvent {
@@ -272,7 +1130,10 @@ impl ApplicationHandler for SessionApp {
                 ..
             } => {
                 info!("Escape pressed");
-                event_loop.exit();
+                self.begin_exit(Instant::now());
+                if self.state == SessionState::Finished {
+                    event_loop.exit();
+                }
             }
 
             WindowEvent::KeyboardInput {
@@ -294,34 +1155,166 @@ impl ApplicationHandler for SessionApp {
                 }
             }
 
-            WindowEvent::Resized(size) => {
-                if let Some(gpu) = &mut self.gpu {
-                    gpu.resize(size.width, size.height);
-                }
+            WindowEvent::KeyboardInput {
+                event:
+                KeyEvent {
+                    logical_key: Key::Named(NamedKey::F3),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.show_sync = !self.show_sync;
+                self.rendered = None;
             }
 
-            WindowEvent::RedrawRequested => {
-                // Check session completion first to handle mutable self borrow
-                self.check_session_complete();
-                if self.session_complete {
-                    event_loop.exit();
-                    return;
-                }
-
-                // Compute color before borrowing window/gpu references
-                let color = self.compute_visual_color();
-
-                let (Some(gpu), Some(window)) = (&self.gpu, &self.window) else {
-                    return;
+            WindowEvent::KeyboardInput {
+                event:
+                KeyEvent {
+                    logical_key: Key::Named(NamedKey::F1),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.show_hud = !self.show_hud;
+                self.rendered = None;
+            }
+
+            WindowEvent::KeyboardInput {
+                event:
+                KeyEvent {
+                    logical_key: Key::Named(NamedKey::Space),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                self.set_paused(!self.sync.paused.load(Ordering::Acquire));
+            }
+
+            WindowEvent::KeyboardInput {
+                event:
+                KeyEvent {
+                    logical_key: Key::Character(ref c),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } if c == "[" || c == "]" => {
+                let step = if c == "]" { 1 } else { -1 };
+                let offset = nudge_av_offset(self.sync.av_offset_ms.load(Ordering::Relaxed), step);
+                self.sync.av_offset_ms.store(offset, Ordering::Relaxed);
+                info!("AV offset: {offset} ms");
+            }
+
+            WindowEvent::KeyboardInput {
+                event:
+                KeyEvent {
+                    logical_key: Key::Named(key @ (NamedKey::ArrowUp | NamedKey::ArrowDown)),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                let step = if key == NamedKey::ArrowUp { INTENSITY_STEP } else { -INTENSITY_STEP };
+                self.sync.set_intensity(self.sync.intensity() + step);
+                info!("Intensity: {:.0}%", self.sync.intensity() * 100.0);
+            }
+
+            WindowEvent::KeyboardInput {
+                event:
+                KeyEvent {
+                    logical_key: Key::Named(key @ (NamedKey::ArrowLeft | NamedKey::ArrowRight)),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => {
+                let step = if key == NamedKey::ArrowRight { LIVE_FREQ_STEP } else { -LIVE_FREQ_STEP };
+                self.live_edit(|p| nudge_params(p, step, 0.0));
+            }
+
+            WindowEvent::KeyboardInput {
+                event:
+                KeyEvent {
+                    logical_key: Key::Character(ref c),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } if c == "+" || c == "=" || c == "-" => {
+                let step = if c == "-" { -LIVE_VOL_STEP } else { LIVE_VOL_STEP };
+                self.live_edit(|p| nudge_params(p, 0.0, step));
+            }
+
+            WindowEvent::Focused(false) => self.pause_on_focus_loss(),
+
+            WindowEvent::Resized(size) => {
+                if let Some(gpu) = &mut self.gpu {
+                    gpu.resize(size.width, size.height);
+                }
+                self.rendered = None;
+            }
+
+            WindowEvent::RedrawRequested => {
+                // Check session completion first to handle mutable self borrow
+                self.check_session_complete();
+                self.check_exit_fade(Instant::now());
+                if self.state == SessionState::Finished {
+                    event_loop.exit();
+                    return;
+                }
+
+                self.update_title(Instant::now());
+                self.progress.update(&self.program.load(), self.sync.playback_time());
+
+                // Compute color before borrowing window/gpu references
+                let color = self.frame_color(Instant::now());
+
+                // The last frame is still on screen; skip identical redraws
+                // unless the sync overlay or HUD is moving
+                self.skipped_redraw = !self.show_sync && !self.show_hud && !needs_render(self.rendered, color);
+                if self.skipped_redraw {
+                    return;
+                }
+
+                let (Some(gpu), Some(window)) = (&mut self.gpu, &self.window) else {
+                    return;
+                };
+
+                let size = window.inner_size();
+                let (width, height) = (size.width as f32, size.height as f32);
+                let translucent = |color| match self.options.overlay {
+                    Some(opacity) => gpu.with_opacity(color, opacity),
+                    None => color,
+                };
+                let mut rects = Vec::new();
+                let background = if self.options.lock_aspect {
+                    rects.push((flash_area(width, height, LOCKED_ASPECT), translucent(color)));
+                    let [r, g, b] = self.options.letterbox_color.unwrap_or(Color::BLACK).to_linear();
+                    translucent(wgpu::Color { r, g, b, a: 1.0 })
+                } else {
+                    translucent(color)
                 };
 
-                match gpu.render(color) {
-                    Ok(()) => {}
+                if self.show_sync {
+                    let freq = self.program.load().params_at_uncached(self.sync.playback_time()).freq;
+                    rects.push((phase_bar(self.sync.raw_phase(), 1, width, height), RAW_PHASE_COLOR));
+                    rects.push((phase_bar(self.sync.visual_phase(freq), 0, width, height), VISUAL_PHASE_COLOR));
+                }
+                if self.show_hud {
+                    rects.extend(hud_rects(&self.program.load(), self.sync.playback_time()));
+                }
+
+                self.rendered = None;
+                match gpu.render(background, &rects) {
+                    Ok(()) => self.rendered = Some(color),
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                        let size = window.inner_size();
-                        if let Some(gpu) = &mut self.gpu {
-                            gpu.resize(size.width, size.height);
-                        }
+                        gpu.resize(size.width, size.height);
                     }
                     Err(wgpu::SurfaceError::OutOfMemory) => {
                         error!("GPU out of memory");
@@ -337,7 +1330,16 @@ impl ApplicationHandler for SessionApp {
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // Rendering blocks on vsync; while redraws are skipped, wait briefly
+        // instead of spinning
+        let control_flow = if self.skipped_redraw {
+            ControlFlow::WaitUntil(Instant::now() + IDLE_POLL)
+        } else {
+            ControlFlow::Poll
+        };
+        event_loop.set_control_flow(control_flow);
+
         // Request continuous redraws
         if let Some(window) = &self.window {
             window.request_redraw();
@@ -345,33 +1347,444 @@ impl ApplicationHandler for SessionApp {
     }
 }
 
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Sleep Inhibition
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Keeps the display awake while held, by running the platform's inhibitor tool.
+///
+/// The tool also watches this process, so the inhibition ends even if we are
+/// killed before the guard is dropped.
+struct SleepInhibitor {
+    child: std::process::Child,
+}
+
+impl SleepInhibitor {
+    fn acquire() -> Result<Self> {
+        let pid = std::process::id().to_string();
+
+        #[cfg(target_os = "linux")]
+        let command = {
+            let mut command = std::process::Command::new("systemd-inhibit");
+            command.args([
+                "--what=idle:sleep",
+                "--who=Isochronator",
+                "--why=Entrainment session",
+                "tail",
+                "--pid",
+                &pid,
+                "-f",
+                "/dev/null",
+            ]);
+            command
+        };
+        #[cfg(target_os = "macos")]
+        let command = {
+            let mut command = std::process::Command::new("caffeinate");
+            command.args(["-d", "-i", "-w", &pid]);
+            command
+        };
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        anyhow::bail!("--prevent-sleep is not supported on this platform (pid {pid})");
+
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        Self::spawn(command)
+    }
+
+    fn spawn(mut command: std::process::Command) -> Result<Self> {
+        let child = command
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .with_context(|| format!("starting {:?}", command.get_program()))?;
+        info!("Preventing display sleep");
+        Ok(Self { child })
+    }
+
+    /// Acquire the inhibitor if requested, warning instead of failing the session.
+    fn acquire_if(requested: bool) -> Option<Self> {
+        requested
+            .then(|| Self::acquire().inspect_err(|e| warn!("Failed to prevent display sleep: {e:#}")).ok())
+            .flatten()
+    }
+}
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        info!("Display sleep allowed again");
+    }
+}
+
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+// Session Summary
+// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+
+/// Interval at which the program is sampled for the session summary.
+const SUMMARY_STEP: f64 = 0.1;
+
+/// Minimum, maximum and mean of a sampled parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl Range {
+    fn of(values: impl Iterator<Item = f32>) -> Self {
+        let (mut min, mut max, mut sum, mut count) = (f32::MAX, f32::MIN, 0.0f64, 0u32);
+        for value in values {
+            min = min.min(value);
+            max = max.max(value);
+            sum += f64::from(value);
+            count += 1;
+        }
+        if count == 0 {
+            return Self { min: 0.0, max: 0.0, mean: 0.0 };
+        }
+        Self { min, max, mean: (sum / f64::from(count)) as f32 }
+    }
+}
+
+/// What a finished session played, for the log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    pub elapsed: f64,
+    pub freq: Range,
+    pub vol: Range,
+    pub binaural: bool,
+    pub warnings: usize,
+}
+
+impl SessionSummary {
+    /// Aggregate parameters sampled over the session.
+    pub fn from_samples(elapsed: f64, samples: &[Params], binaural: bool, warnings: usize) -> Self {
+        Self {
+            elapsed,
+            freq: Range::of(samples.iter().map(|p| p.freq as f32)),
+            vol: Range::of(samples.iter().map(|p| p.vol)),
+            binaural,
+            warnings,
+        }
+    }
+
+    /// Sample the program over the first `elapsed` seconds.
+    fn sample(program: &Program, elapsed: f64, binaural: bool) -> Self {
+        let steps = (elapsed / SUMMARY_STEP).floor() as u64;
+        let samples: Vec<Params> = (0..=steps)
+            .map(|k| program.params_at_uncached(k as f64 * SUMMARY_STEP))
+            .collect();
+        Self::from_samples(elapsed, &samples, binaural, program.warnings.len())
+    }
+
+    fn report(&self) -> String {
+        format!(
+            "Session summary: {:.1}s {}, freq {:.2}/{:.2}/{:.2} Hz, vol {:.2}/{:.2}/{:.2} (min/mean/max), {} warning(s)",
+            self.elapsed,
+            if self.binaural { "binaural" } else { "isochronic" },
+            self.freq.min,
+            self.freq.mean,
+            self.freq.max,
+            self.vol.min,
+            self.vol.mean,
+            self.vol.max,
+            self.warnings
+        )
+    }
+}
+
+/// Log a summary of what was played so far.
+fn log_summary(program: &LiveProgram, sync: &SyncState, engine: &EngineOptions) {
+    let program = program.load();
+    let binaural = engine.binaural.unwrap_or(program.settings.binaural);
+    info!("{}", SessionSummary::sample(&program, sync.playback_time(), binaural).report());
+}
+
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 // Entry Points
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
 /// Run a full entrainment session with audio and visuals.
-pub fn run_session(program: Arc<Program>) -> Result<()> {
+pub fn run_session(program: Arc<Program>, options: SessionOptions) -> Result<()> {
+    anyhow::ensure!(
+        !options.midi_out || cfg!(feature = "midi"),
+        "MIDI output requires building with the `midi` feature"
+    );
+    ensure_watch_supported(&options)?;
+    ensure_flicker_safe(&program, options.allow_unsafe_flicker)?;
+
     let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let mut app = SessionApp::new(program);
+    // Released on return or unwind
+    let _awake = SleepInhibitor::acquire_if(options.prevent_sleep);
+
+    let mut app = SessionApp::new(program, options);
+
+    // Kept alive for the whole session; stops sending notes on drop
+    #[cfg(feature = "midi")]
+    let _midi = if app.options.midi_out {
+        crate::midi::MidiPulseOut::start(app.program.clone(), app.sync.clone())
+            .inspect_err(|e| warn!("Failed to start MIDI output: {e}"))
+            .ok()
+    } else {
+        None
+    };
+
+    // Kept alive for the whole session; stops reloading on drop
+    #[cfg(feature = "watch")]
+    let _watch = start_watch(&app.options, &app.program, &app.sync);
+
     event_loop.run_app(&mut app)?;
+    log_summary(&app.program, &app.sync, &app.options.engine);
 
     Ok(())
 }
 
-/// Run a profiling workload for PGO optimization.
-pub fn run_profile(program: Arc<Program>) {
+/// Log the program's [`Program::safety_warnings`] and refuse it if there are
+/// any, unless `allow_unsafe_flicker` is set.
+pub fn ensure_flicker_safe(program: &Program, allow_unsafe_flicker: bool) -> Result<()> {
+    let safety = program.safety_warnings();
+    for warning in &safety {
+        warn!("{warning}");
+    }
+    anyhow::ensure!(
+        safety.is_empty() || allow_unsafe_flicker,
+        "the program flashes within {:.0}-{:.0} Hz; pass --allow-unsafe-flicker to play it anyway",
+        FLICKER_DANGER_BAND.start(),
+        FLICKER_DANGER_BAND.end()
+    );
+    Ok(())
+}
+
+/// Describe the program state at `time`, e.g. "transitioning (42%)" or "holding".
+pub fn transition_status(program: &Program, time: f64) -> String {
+    match program.params_at_detailed(time).1 {
+        Some(transition) => format!("transitioning ({:.0}%)", transition.progress * 100.0),
+        None => "holding".into(),
+    }
+}
+
+/// Run a session with audio only and no window.
+///
+/// Ctrl-C fades the audio out before exiting instead of cutting it mid-buffer.
+pub fn run_headless(program: Arc<Program>, options: SessionOptions) -> Result<()> {
+    anyhow::ensure!(!options.midi_out, "MIDI output needs a window session");
+    ensure_watch_supported(&options)?;
+
+    let program = Arc::new(LiveProgram::new(program));
     let sync = Arc::new(SyncState::new());
-    let mut engine = audio::AudioEngine::new(48000.0, program, sync);
+    sync.set_intensity(options.intensity.unwrap_or(1.0));
+    let _stream = audio::start(program.clone(), sync.clone(), options.engine, options.device.as_deref())?;
+    let _awake = SleepInhibitor::acquire_if(options.prevent_sleep);
+    #[cfg(feature = "watch")]
+    let _watch = start_watch(&options, &program, &sync);
 
-    let mut buffer = vec![0.0f32; 1024];
+    let main_thread = std::thread::current();
+    let handler_sync = sync.clone();
+    ctrlc::set_handler(move || {
+        info!("Interrupted, fading out");
+        request_shutdown(&handler_sync, &main_thread);
+    })
+    .context("installing Ctrl-C handler")?;
 
-    // Simulate 100 seconds of audio processing
-    for _ in 0..4800 {
-        engine.process(&mut buffer, 2);
-        black_box(&buffer);
+    wait_for_shutdown(&program, &sync, &mut ProgressLog::new(&options), options.duration);
+    info!("Session stopped");
+    log_summary(&program, &sync, &options.engine);
+    Ok(())
+}
+
+fn ensure_watch_supported(options: &SessionOptions) -> Result<()> {
+    anyhow::ensure!(
+        options.watch.is_none() || cfg!(feature = "watch"),
+        "--watch requires building with the `watch` feature"
+    );
+    Ok(())
+}
+
+/// Start reloading the program file if `--watch` was given.
+#[cfg(feature = "watch")]
+fn start_watch(
+    options: &SessionOptions,
+    program: &Arc<LiveProgram>,
+    sync: &Arc<SyncState>,
+) -> Option<crate::watch::ProgramWatcher> {
+    let path = options.watch.as_deref()?;
+    crate::watch::ProgramWatcher::start(path, program.clone(), sync.clone(), options.allow_unsafe_flicker)
+        .inspect_err(|e| warn!("Failed to watch {}: {e:#}", path.display()))
+        .ok()
+}
+
+/// Ask the engine to fade out and wake the thread waiting for it.
+fn request_shutdown(sync: &SyncState, waiter: &std::thread::Thread) {
+    sync.stopping.store(true, Ordering::Release);
+    waiter.unpark();
+}
+
+/// Playback time at which a session ends: the end of the program, or the
+/// `limit` set with `--duration` if that comes first.
+fn session_end(program_duration: f64, limit: Option<f64>) -> f64 {
+    limit.map_or(program_duration, |limit| limit.min(program_duration))
+}
+
+/// Park until the engine has faded out, starting the fade when the session ends.
+fn wait_for_shutdown(program: &LiveProgram, sync: &SyncState, progress: &mut ProgressLog, limit: Option<f64>) {
+    while !sync.stopped.load(Ordering::Acquire) {
+        progress.update(&program.load(), sync.playback_time());
+        let duration = session_end(program.load().duration, limit);
+        if !sync.stopping.load(Ordering::Acquire) && sync.playback_time() >= duration {
+            info!("Session complete at {duration:.1}s");
+            sync.stopping.store(true, Ordering::Release);
+        }
+        std::thread::park_timeout(Duration::from_millis(50));
+    }
+}
+
+/// Frame counts produced by a simulated session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SimulationStats {
+    pub audio_buffers: u64,
+    pub video_frames: u64,
+}
+
+/// Simulate a session on a virtual clock, without audio device or window.
+///
+/// The clock advances in fixed video frame steps. Before each frame, audio
+/// buffers are rendered until they cover the clock, so the visual path reads
+/// the same sync state it would during live playback.
+pub fn simulate(program: Arc<Program>, seconds: f64) -> SimulationStats {
+    simulate_frames(program, seconds, 60.0, |color| {
+        black_box(color);
+    })
+}
+
+/// Simulate a session, passing the color of each video frame to `on_frame`.
+fn simulate_frames(
+    program: Arc<Program>,
+    seconds: f64,
+    fps: f64,
+    mut on_frame: impl FnMut(wgpu::Color),
+) -> SimulationStats {
+    const SAMPLE_RATE: u32 = 48000;
+    const BUFFER_FRAMES: u64 = 1024;
+
+    let sync = Arc::new(SyncState::new());
+    sync.sample_rate.store(SAMPLE_RATE, Ordering::Release);
+    let live = Arc::new(LiveProgram::new(program.clone()));
+    let mut engine = audio::AudioEngine::new(f64::from(SAMPLE_RATE), live, sync.clone());
+
+    let mut buffer = vec![0.0f32; BUFFER_FRAMES as usize * 2];
+    let mut stats = SimulationStats::default();
+    let total_frames = (seconds * fps).round() as u64;
+
+    for frame in 1..=total_frames {
+        let clock = frame as f64 / fps;
+
+        while ((stats.audio_buffers * BUFFER_FRAMES) as f64 / f64::from(SAMPLE_RATE)) < clock {
+            engine.process(&mut buffer, 2);
+            black_box(&buffer);
+            stats.audio_buffers += 1;
+        }
+
+        on_frame(visual_color(&program, &sync, 0.0));
+        stats.video_frames += 1;
     }
+
+    stats
+}
+
+/// Size of frames written by [`render_frames`].
+const VIDEO_SIZE: (u32, u32) = (640, 360);
+
+/// Render the flash of a finite program as numbered PNG frames in `dir`.
+///
+/// Returns the number of frames written. Assemble them into a video with
+/// external tooling such as ffmpeg.
+pub fn render_frames(program: Arc<Program>, dir: &Path, fps: u32) -> Result<u64> {
+    anyhow::ensure!(
+        program.duration.is_finite(),
+        "cannot render a program without a finite duration"
+    );
+    anyhow::ensure!(fps > 0, "fps must be positive");
+    std::fs::create_dir_all(dir).with_context(|| format!("creating '{}'", dir.display()))?;
+
+    let (width, height) = VIDEO_SIZE;
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+    let mut written = 0u64;
+    let mut result = Ok(());
+    let duration = program.duration;
+
+    simulate_frames(program, duration, f64::from(fps), |color| {
+        if result.is_err() {
+            return;
+        }
+        let srgb = Color::from_linear([color.r, color.g, color.b]);
+        for pixel in pixels.chunks_exact_mut(3) {
+            pixel.copy_from_slice(&[srgb.r, srgb.g, srgb.b]);
+        }
+        let path = dir.join(format!("frame_{written:06}.png"));
+        result = write_png(&path, width, height, &pixels);
+        written += 1;
+    });
+    result?;
+
+    Ok(written)
+}
+
+/// Flash brightness [0, 1] sampled at `fps` over the first `seconds` of a program,
+/// as `(time, brightness)` pairs.
+///
+/// The pulse phase comes from integrating the frequency over the timeline, so
+/// no audio is rendered.
+fn brightness_timeline(program: &Program, seconds: f64, fps: u32) -> Vec<(f64, f64)> {
+    let frames = (seconds * f64::from(fps)).ceil() as u64;
+    (0..frames)
+        .map(|frame| {
+            let time = frame as f64 / f64::from(fps);
+            let phase = program.pulse_cycles_at(time).fract();
+            (time, brightness(&program.params_at(time), phase, program.settings.linked))
+        })
+        .collect()
+}
+
+/// Write the flash brightness of a finite program as `time,brightness` CSV rows.
+///
+/// Returns the number of rows written.
+pub fn export_brightness(program: &Program, path: &Path, fps: u32) -> Result<usize> {
+    anyhow::ensure!(
+        program.duration.is_finite(),
+        "cannot export a program without a finite duration"
+    );
+    anyhow::ensure!(fps > 0, "fps must be positive");
+
+    let rows = brightness_timeline(program, program.duration, fps);
+    let mut csv = String::with_capacity(rows.len() * 20 + 16);
+    csv.push_str("time,brightness\n");
+    for (time, brightness) in &rows {
+        csv.push_str(&format!("{time:.6},{brightness:.4}\n"));
+    }
+    std::fs::write(path, csv).with_context(|| format!("writing '{}'", path.display()))?;
+
+    Ok(rows.len())
+}
+
+fn write_png(path: &Path, width: u32, height: u32, rgb: &[u8]) -> Result<()> {
+    let file = std::fs::File::create(path).with_context(|| format!("creating '{}'", path.display()))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgb))
+        .with_context(|| format!("writing '{}'", path.display()))
+}
+
+/// Run a profiling workload for PGO optimization.
+pub fn run_profile(program: Arc<Program>) {
+    // Simulate 100 seconds of playback
+    black_box(simulate(program, 100.0));
 }
 
 // ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
@@ -381,8 +1794,8 @@ pub fn run_profile(program: Arc<Program>) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::program::{Params, Settings};
-    use crate::Color;
+    use crate::program::Settings;
+    use std::str::FromStr;
 
     #[test]
     fn color_to_linear_conversion() {
@@ -397,9 +1810,486 @@ mod tests {
         assert!(black[2] < 0.01);
     }
 
+    #[test]
+    fn status_reports_transition_progress() {
+        let program = Program::parse("00:00 freq=10\n00:10 freq=20 >linear").unwrap();
+        assert_eq!(transition_status(&program, 4.2), "transitioning (42%)");
+        assert_eq!(transition_status(&program, 12.0), "holding");
+    }
+
+    #[test]
+    fn overlay_keeps_playing_on_focus_loss() {
+        let program = Arc::new(Program::parse("00:00 freq=10").unwrap());
+
+        let overlay = SessionApp::new(program.clone(), SessionOptions { overlay: Some(0.5), ..Default::default() });
+        overlay.pause_on_focus_loss();
+        assert!(!overlay.sync.paused.load(Ordering::Acquire));
+
+        let window = SessionApp::new(program, SessionOptions::default());
+        window.pause_on_focus_loss();
+        assert!(window.sync.paused.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn pause_fades_to_idle_color() {
+        let program = Program::parse("00:00 on=#FFFFFF off=#000000").unwrap();
+        let grey = Color::from_str("#808080").unwrap();
+        let options = SessionOptions {
+            idle_color: Some(grey),
+            ..Default::default()
+        };
+        let mut app = SessionApp::new(Arc::new(program), options);
+        app.last_color = wgpu::Color::WHITE;
+        app.sync.paused.store(true, Ordering::Release);
+
+        let idle = grey.to_linear();
+        let start = Instant::now();
+        let at = |ms| start + std::time::Duration::from_millis(ms);
+
+        assert_eq!(app.frame_color(start).r, 1.0);
+        let mid = app.frame_color(at(150)).r;
+        assert!(mid < 1.0 && mid > idle[0]);
+        for ms in [300, 1000] {
+            let color = app.frame_color(at(ms));
+            assert!((color.r - idle[0]).abs() < 1e-9 && (color.b - idle[2]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn freeze_on_exit_holds_until_key() {
+        let program = Program::parse("00:00 freq=10\n00:01 freq=12").unwrap();
+        let options = SessionOptions {
+            freeze_on_exit: true,
+            ..Default::default()
+        };
+        let mut app = SessionApp::new(Arc::new(program), options);
+        app.sync.sample_rate.store(48000, Ordering::Release);
+
+        app.check_session_complete();
+        assert_eq!(app.state, SessionState::Running);
+
+        app.last_color = wgpu::Color::WHITE;
+        app.sync.frames_written.store(48000, Ordering::Release);
+        app.check_session_complete();
+        assert_eq!(app.state, SessionState::Frozen);
+        assert!(app.sync.paused.load(Ordering::Acquire));
+
+        // The final frame is held, without fading to the idle color
+        let later = Instant::now() + Duration::from_secs(1);
+        assert_eq!(app.frame_color(later), wgpu::Color::WHITE);
+
+        // Without the option the session fades out right away
+        let program = Program::parse("00:00 freq=10\n00:01 freq=12").unwrap();
+        let mut app = SessionApp::new(Arc::new(program), SessionOptions::default());
+        app.sync.sample_rate.store(48000, Ordering::Release);
+        app.sync.frames_written.store(48000, Ordering::Release);
+        app.check_session_complete();
+        assert!(matches!(app.state, SessionState::FadingOut { .. }));
+    }
+
+    #[test]
+    fn exit_fades_flash_to_off_color_before_finishing() {
+        let program = Program::parse("00:00 freq=10 off=#000080").unwrap();
+        let options = SessionOptions {
+            engine: EngineOptions { fade_out: Some(2.0), ..Default::default() },
+            ..Default::default()
+        };
+        let mut app = SessionApp::new(Arc::new(program), options);
+        app.last_color = wgpu::Color::WHITE;
+
+        let start = Instant::now();
+        app.begin_exit(start);
+        assert!(app.sync.stopping.load(Ordering::Acquire));
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+
+        // Halfway to the off color, then there
+        let off = Color { r: 0, g: 0, b: 0x80, a: 255 }.to_linear();
+        let half = app.frame_color(at(1.0));
+        assert!((half.r - 0.5).abs() < 1e-9 && (half.b - (1.0 + off[2]) / 2.0).abs() < 1e-9);
+        let end = app.frame_color(at(2.5));
+        assert!(end.r.abs() < 1e-9 && (end.b - off[2]).abs() < 1e-9);
+
+        // Waits for the audio fade, but not forever
+        app.check_exit_fade(at(2.1));
+        assert!(matches!(app.state, SessionState::FadingOut { .. }));
+        app.sync.stopped.store(true, Ordering::Release);
+        app.check_exit_fade(at(1.9));
+        assert!(matches!(app.state, SessionState::FadingOut { .. }));
+        app.check_exit_fade(at(2.1));
+        assert_eq!(app.state, SessionState::Finished);
+
+        let mut stuck = SessionApp::new(Arc::new(Program::parse("00:00 freq=10").unwrap()), SessionOptions::default());
+        stuck.begin_exit(start);
+        stuck.check_exit_fade(at(audio::DEFAULT_EXIT_FADE_SECS + EXIT_FADE_GRACE_SECS));
+        assert_eq!(stuck.state, SessionState::Finished);
+
+        // Asking twice skips the fade
+        let mut impatient = SessionApp::new(Arc::new(Program::parse("00:00 freq=10").unwrap()), SessionOptions::default());
+        impatient.begin_exit(start);
+        impatient.begin_exit(start);
+        assert_eq!(impatient.state, SessionState::Finished);
+    }
+
+    #[test]
+    fn rect_shader_is_valid() {
+        use wgpu::naga::valid::{Capabilities, ValidationFlags, Validator};
+
+        let module = wgpu::naga::front::wgsl::parse_str(RECT_SHADER).unwrap();
+        Validator::new(ValidationFlags::all(), Capabilities::default())
+            .validate(&module)
+            .unwrap();
+    }
+
+    #[test]
+    fn letterbox_surrounds_flash_area() {
+        // 4:3 surface: bars above and below
+        let area = flash_area(1600.0, 1200.0, LOCKED_ASPECT);
+        assert_eq!(area, Rect { x: 0.0, y: 150.0, w: 1600.0, h: 900.0 });
+
+        // Ultrawide surface: bars left and right
+        let area = flash_area(2560.0, 1080.0, LOCKED_ASPECT);
+        assert_eq!(area, Rect { x: 320.0, y: 0.0, w: 1920.0, h: 1080.0 });
+
+        // Exact 16:9 fills the surface
+        let area = flash_area(1920.0, 1080.0, LOCKED_ASPECT);
+        assert_eq!(area, Rect { x: 0.0, y: 0.0, w: 1920.0, h: 1080.0 });
+        assert_eq!(area.to_ndc(1920.0, 1080.0), [-1.0, 1.0, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn av_offset_nudge_is_clamped() {
+        assert_eq!(nudge_av_offset(0, 1), 1);
+        assert_eq!(nudge_av_offset(-20, -1), -21);
+        assert_eq!(nudge_av_offset(500, 1), 500);
+        assert_eq!(nudge_av_offset(-500, -1), -500);
+        assert_eq!(nudge_av_offset(2000, 0), 500);
+
+        let options = SessionOptions {
+            av_offset_ms: -900,
+            ..Default::default()
+        };
+        let app = SessionApp::new(Arc::new(Program::parse("00:00").unwrap()), options);
+        assert_eq!(app.sync.av_offset_ms.load(Ordering::Relaxed), -500);
+    }
+
+    #[test]
+    fn shutdown_fades_out_then_stops() {
+        let program = Program::constant(Params { vol: 1.0, ..Params::default() }, Settings::default());
+        let live = Arc::new(LiveProgram::new(Arc::new(program)));
+        let sync = Arc::new(SyncState::new());
+        let options = audio::EngineOptions { fade_out: Some(0.3), ..Default::default() };
+        let mut engine = audio::AudioEngine::new(48000.0, live.clone(), sync.clone()).with_options(options);
+
+        let mut buffer = vec![0.0f32; 2048];
+        engine.process(&mut buffer, 2);
+
+        request_shutdown(&sync, &std::thread::current());
+
+        // 0.3 s at 48 kHz is just over 14 buffers of 1024 frames
+        let fade_frames = 0.3 * 48000.0;
+        let mut buffers = 0;
+        while !sync.stopped.load(Ordering::Acquire) {
+            engine.process(&mut buffer, 2);
+            let gain = (1.0 - f64::from(buffers * 1024) / fade_frames).max(0.0) as f32;
+            assert!(buffer.iter().all(|s| s.abs() <= gain + 1e-6));
+            buffers += 1;
+        }
+        assert_eq!(buffers, 15);
+        assert_eq!(buffer[buffer.len() - 2..], [0.0, 0.0]);
+
+        // The waiting thread returns once the engine has stopped
+        wait_for_shutdown(&live, &sync, &mut ProgressLog::default(), None);
+    }
+
+    #[test]
+    fn duration_limit_ends_session_early() {
+        assert_eq!(session_end(f64::INFINITY, None), f64::INFINITY);
+        assert_eq!(session_end(f64::INFINITY, Some(600.0)), 600.0);
+        assert_eq!(session_end(120.0, Some(600.0)), 120.0);
+        assert_eq!(session_end(120.0, Some(60.0)), 60.0);
+        assert_eq!(session_end(120.0, None), 120.0);
+    }
+
+    #[test]
+    fn repeat_iterations_follow_period() {
+        let repeat = Repeat { count: 3, period: 12.0 };
+        assert_eq!(repeat.iteration_at(0.0), 1);
+        assert_eq!(repeat.iteration_at(11.9), 1);
+        assert_eq!(repeat.iteration_at(12.0), 2);
+        assert_eq!(repeat.iteration_at(34.0), 3);
+        // The session ends in the last play; later times stay there
+        assert_eq!(repeat.iteration_at(99.0), 3);
+
+        let mut last = 0;
+        repeat.log_progress(13.0, &mut last);
+        assert_eq!(last, 2);
+    }
+
+    #[test]
+    fn render_only_on_color_change() {
+        let grey = wgpu::Color { r: 0.5, g: 0.5, b: 0.5, a: 1.0 };
+        let nudged = |d: f64| wgpu::Color { g: 0.5 + d, ..grey };
+
+        assert!(needs_render(None, grey));
+        assert!(!needs_render(Some(grey), grey));
+        assert!(!needs_render(Some(grey), nudged(COLOR_EPSILON / 2.0)));
+        assert!(needs_render(Some(grey), nudged(COLOR_EPSILON * 2.0)));
+        assert!(needs_render(Some(grey), wgpu::Color::WHITE));
+    }
+
+    #[test]
+    fn linked_brightness_matches_envelope() {
+        let params = Params { duty: 0.4, ..Params::default() };
+        let envelope = Envelope::from_params(&params);
+
+        for phase in [0.0, 0.01, 0.05, 0.2, 0.37, 0.399, 0.4, 0.7] {
+            assert_eq!(brightness(&params, phase, true), envelope.value(phase));
+        }
+
+        // Unlinked mode stays a hard on/off flash
+        assert_eq!(brightness(&params, 0.01, false), 1.0);
+        assert_eq!(brightness(&params, 0.5, false), 0.0);
+    }
+
+    #[test]
+    fn simulation_is_deterministic() {
+        let program = Arc::new(Program::constant(Params::default(), Settings::default()));
+
+        let stats = simulate(program.clone(), 1.0);
+        assert_eq!(stats.video_frames, 60);
+        assert_eq!(stats.audio_buffers, 47); // ceil(48000 / 1024)
+
+        assert_eq!(simulate(program, 1.0), stats);
+    }
+
+    #[test]
+    fn brightness_knee_rounds_off_the_cap() {
+        let hard = BrightnessCap { max: 0.5, knee: false };
+        let knee = BrightnessCap { max: 0.5, knee: true };
+
+        // Identical well below the cap
+        assert_eq!(hard.level(0.2), 0.2);
+        assert_eq!(knee.level(0.2), 0.2);
+
+        // Near the cap the knee compresses instead of clipping
+        assert_eq!(hard.level(0.45), 0.45);
+        assert!(knee.level(0.45) < 0.45);
+        assert_eq!(hard.level(1.0), 0.5);
+        assert!(knee.level(1.0) < 0.5 && knee.level(1.0) > 0.49);
+
+        // Still increasing above the cap, so bright flashes keep some contrast
+        assert!(knee.level(0.8) > knee.level(0.6));
+        assert_eq!(hard.level(0.8), hard.level(0.6));
+
+        // Continuous at the start of the knee
+        let start = 0.5 * KNEE_START;
+        assert!((knee.level(start + 1e-9) - start).abs() < 1e-8);
+    }
+
+    #[test]
+    fn phase_bars_span_the_surface() {
+        let start = phase_bar(0.0, 0, 800.0, 600.0);
+        assert_eq!(start, Rect { x: 0.0, y: 584.0, w: SYNC_BAR_WIDTH, h: SYNC_BAR_HEIGHT });
+
+        // Stays fully inside the surface at the end of the cycle
+        let end = phase_bar(1.0, 0, 800.0, 600.0);
+        assert_eq!(end.x + end.w, 800.0);
+
+        let raw = phase_bar(0.5, 1, 800.0, 600.0);
+        assert_eq!(raw.x, 398.0);
+        assert_eq!(raw.y + raw.h, start.y);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sleep_inhibitor_released_with_scope() {
+        let proc_entry = {
+            let mut command = std::process::Command::new("sleep");
+            command.arg("30");
+            let mut guard = SleepInhibitor::spawn(command).unwrap();
+            let entry = std::path::PathBuf::from(format!("/proc/{}", guard.child.id()));
+
+            // Held for the whole session scope
+            assert!(matches!(guard.child.try_wait(), Ok(None)));
+            assert!(entry.exists());
+            entry
+        };
+
+        assert!(!proc_entry.exists());
+    }
+
+    #[test]
+    fn summary_aggregates_samples() {
+        let samples: Vec<Params> = [(4.0, 0.2), (8.0, 0.6), (12.0, 1.0)]
+            .into_iter()
+            .map(|(freq, vol)| Params { freq, vol, ..Params::default() })
+            .collect();
+
+        let summary = SessionSummary::from_samples(30.0, &samples, false, 1);
+        assert_eq!(summary.freq, Range { min: 4.0, max: 12.0, mean: 8.0 });
+        assert_eq!(summary.vol.min, 0.2);
+        assert_eq!(summary.vol.max, 1.0);
+        assert!((summary.vol.mean - 0.6).abs() < 1e-6);
+
+        // Empty sessions don't report sentinel values
+        let empty = SessionSummary::from_samples(0.0, &[], false, 0);
+        assert_eq!(empty.freq, Range { min: 0.0, max: 0.0, mean: 0.0 });
+    }
+
+    #[test]
+    fn render_writes_one_png_per_frame() {
+        let program = Arc::new(Program::parse("00:00 freq=10\n00:01 freq=10").unwrap());
+        let dir = std::env::temp_dir().join(format!("isochronator-render-{}", std::process::id()));
+
+        let frames = render_frames(program, &dir, 30).unwrap();
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(frames, 30);
+        assert_eq!(files, 30);
+    }
+
     #[test]
     fn profile_completes() {
         let program = Arc::new(Program::constant(Params::default(), Settings::default()));
         run_profile(program);
     }
+
+    #[test]
+    fn phase_extrapolates_between_audio_updates() {
+        let sync = SyncState::new();
+        sync.sample_rate.store(48000, Ordering::Release);
+        sync.buffer_frames.store(4800, Ordering::Release); // 100 ms
+        sync.frames_written.store(48000, Ordering::Release);
+
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut clock = AudioClock::new(start);
+
+        assert_eq!(clock.elapsed(&sync, start), 0.0);
+        assert!((clock.elapsed(&sync, at(30)) - 0.03).abs() < 1e-9);
+        // Never further ahead than one buffer
+        assert!((clock.elapsed(&sync, at(250)) - 0.1).abs() < 1e-9);
+
+        // A new audio buffer restarts the extrapolation
+        sync.frames_written.store(52800, Ordering::Release);
+        assert_eq!(clock.elapsed(&sync, at(260)), 0.0);
+        assert!((clock.elapsed(&sync, at(270)) - 0.01).abs() < 1e-9);
+
+        assert!((extrapolate_phase(0.9, 10.0, 0.03) - 0.2).abs() < 1e-9);
+        assert_eq!(extrapolate_phase(0.4, 10.0, 0.0), 0.4);
+    }
+
+    #[test]
+    fn overlay_window_attributes() {
+        let size = LogicalSize::new(854.0, 480.0);
+
+        let normal = window_attributes("Isochronator", size, false);
+        assert!(normal.decorations && !normal.transparent);
+        assert_eq!(normal.window_level, WindowLevel::Normal);
+
+        let overlay = window_attributes("Isochronator", size, true);
+        assert!(!overlay.decorations && overlay.transparent && overlay.maximized);
+        assert_eq!(overlay.window_level, WindowLevel::AlwaysOnTop);
+    }
+
+    #[test]
+    fn brightness_timeline_follows_duty() {
+        let params = Params { freq: 1.0, duty: 0.5, ..Params::default() };
+        let program = Program::constant(params, Settings::default());
+
+        let rows = brightness_timeline(&program, 1.0, 10);
+        let times: Vec<f64> = rows.iter().map(|&(time, _)| time).collect();
+        let levels: Vec<f64> = rows.iter().map(|&(_, level)| level).collect();
+        assert_eq!(times.len(), 10);
+        assert!((times[3] - 0.3).abs() < 1e-12);
+        assert_eq!(levels, [1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn flash_follows_compensated_audio_phase() {
+        let params = Params { freq: 1.0, duty: 0.5, ..Params::default() };
+        let program = Program::constant(params, Settings::default());
+        let sync = SyncState::new();
+        sync.sample_rate.store(48000, Ordering::Release);
+        sync.buffer_frames.store(4800, Ordering::Release); // 100 ms of latency
+
+        // The engine has written 1.05 cycles; 0.95 have been heard
+        let publish = |frames: u64, phase: f64| {
+            sync.frames_written.store(frames, Ordering::Release);
+            sync.phase_bits.store(phase.to_bits(), Ordering::Release);
+        };
+        publish(50400, 0.05);
+        assert!((sync.visual_phase(1.0) - 0.95).abs() < 1e-9);
+        assert_eq!(visual_color(&program, &sync, 0.0).r, 0.0);
+
+        // Written past the end of the pulse, but the audible part is still on
+        publish(74400, 0.55);
+        assert!((sync.visual_phase(1.0) - 0.45).abs() < 1e-9);
+        assert_eq!(visual_color(&program, &sync, 0.0).r, 1.0);
+
+        publish(79200, 0.65);
+        assert_eq!(visual_color(&program, &sync, 0.0).r, 0.0);
+    }
+
+    #[test]
+    fn intensity_dims_flash_toward_off() {
+        let program = Program::constant(Params::default(), Settings::default());
+        let sync = SyncState::new();
+        sync.sample_rate.store(48000, Ordering::Release);
+        assert_eq!(visual_color(&program, &sync, 0.0).r, 1.0);
+
+        sync.set_intensity(0.5);
+        assert_eq!(visual_color(&program, &sync, 0.0).r, 0.5);
+    }
+
+    #[test]
+    fn live_edits_ramp_to_nudged_params() {
+        let program = Program::parse("00:00 freq=10 vol=0.5 duty=0.3 invert_duty\n01:00 freq=4 >linear").unwrap();
+        let live = LiveProgram::new(Arc::new(program));
+
+        let edited = apply_live_edit(&live, 30.0, false, |p| nudge_params(p, LIVE_FREQ_STEP, 0.0)).unwrap();
+        assert!((edited.freq - 7.5).abs() < 1e-9);
+
+        let program = live.load();
+        assert!(program.duration.is_infinite());
+        assert!((program.params_at(30.0).freq - 7.0).abs() < 1e-9);
+        let after = program.params_at(30.0 + LIVE_EDIT_RAMP_SECS);
+        assert!((after.freq - 7.5).abs() < 1e-9);
+        assert_eq!(after.duty, 0.7); // still inverted once
+
+        // Edits into the seizure risk band are refused unless allowed
+        let risky = |p| nudge_params(p, 10.0, 0.0);
+        assert!(apply_live_edit(&live, 40.0, false, risky).is_err());
+        assert!(Arc::ptr_eq(&live.load(), &program));
+        assert!((apply_live_edit(&live, 40.0, true, risky).unwrap().freq - 17.5).abs() < 1e-9);
+
+        assert_eq!(nudge_params(Params { vol: 0.98, ..Params::default() }, 0.0, LIVE_VOL_STEP).vol, 1.0);
+        assert_eq!(nudge_params(Params { freq: 0.6, ..Params::default() }, -LIVE_FREQ_STEP, 0.0).freq, LIVE_MIN_FREQ);
+    }
+
+    #[test]
+    fn hud_shows_time_and_params() {
+        let program = Program::parse("00:00 freq=10 vol=0.5").unwrap();
+        let text = hud_text(&program, 83.4);
+        assert_eq!(text, "01:23  10.0 Hz  vol 50%");
+        assert!(text.chars().all(|c| c == ' ' || glyph(c) != [0; 5]), "{text}");
+
+        // "1" is one run per row, stacked one font pixel apart
+        let rects = text_rects("1", 0.0, 0.0);
+        assert_eq!(rects.len(), 5);
+        assert_eq!(rects[4], Rect { x: 0.0, y: 4.0 * HUD_PIXEL, w: 3.0 * HUD_PIXEL, h: HUD_PIXEL });
+    }
+
+    #[test]
+    fn title_shows_live_readout() {
+        assert_eq!(
+            title_text("Isochronator", 8.34, 135.9, 600.0, "holding"),
+            "Isochronator - 8.3 Hz - 02:15 / 10:00 - holding"
+        );
+        assert_eq!(
+            title_text("Isochronator", 10.0, 61.0, f64::INFINITY, "transitioning (42%)"),
+            "Isochronator - 10.0 Hz - 01:01 - transitioning (42%)"
+        );
+    }
 }
\ No newline at end of file
@@ -0,0 +1,100 @@
+//! Hot reloading of the program file during a session.
+//!
+//! The file's directory is watched rather than the file itself, since many
+//! editors save by writing a new file and renaming it over the old one. Bursts
+//! of events from a single save are collapsed into one reload, which crossfades
+//! from what is playing to the new timeline at the current playback time.
+
+use crate::audio::SyncState;
+use crate::program::{LiveProgram, Program};
+use crate::visuals::ensure_flicker_safe;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Quiet time after the last change before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Crossfade from the old timeline to the reloaded one, in seconds.
+const RELOAD_FADE_SECS: f64 = 0.5;
+
+/// Watches a program file and swaps each saved version into the session.
+///
+/// Stops watching on drop.
+pub struct ProgramWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl ProgramWatcher {
+    /// Versions that flash within the seizure risk band are not swapped in
+    /// unless `allow_unsafe_flicker` is set.
+    pub fn start(
+        path: &Path,
+        program: Arc<LiveProgram>,
+        sync: Arc<SyncState>,
+        allow_unsafe_flicker: bool,
+    ) -> Result<Self> {
+        let path = path.canonicalize().with_context(|| format!("resolving {}", path.display()))?;
+        let dir = path.parent().context("program file has no parent directory")?.to_path_buf();
+
+        let (tx, rx) = mpsc::channel();
+        let target = path.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) if event.kind.is_modify() || event.kind.is_create() => {
+                if event.paths.contains(&target) {
+                    let _ = tx.send(());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("File watch error: {e}"),
+        })
+        .context("creating file watcher")?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("watching {}", dir.display()))?;
+
+        info!("Watching {} for changes", path.display());
+
+        // Exits once the watcher, and with it the sender, is dropped
+        std::thread::Builder::new()
+            .name("program-watch".into())
+            .spawn(move || reload_loop(&path, &rx, &program, &sync, allow_unsafe_flicker))
+            .context("spawning watch thread")?;
+
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+fn reload_loop(
+    path: &Path,
+    rx: &mpsc::Receiver<()>,
+    program: &LiveProgram,
+    sync: &SyncState,
+    allow_unsafe_flicker: bool,
+) {
+    while rx.recv().is_ok() {
+        // Wait for the save to settle
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+        match Program::load(path) {
+            Ok(next) => {
+                for warning in &next.warnings {
+                    warn!("{}: {warning}", path.display());
+                }
+                let at = sync.write_time();
+                let reloaded = program.load().crossfade_to(&next, at, RELOAD_FADE_SECS);
+                if let Err(e) = ensure_flicker_safe(&reloaded, allow_unsafe_flicker) {
+                    warn!("Keeping the current program: {e:#}");
+                    continue;
+                }
+                program.store(reloaded);
+                info!("Reloaded {} at {at:.1}s", path.display());
+            }
+            Err(e) => warn!("Keeping the current program: {e:#}"),
+        }
+    }
+}